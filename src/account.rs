@@ -1,6 +1,11 @@
 mod accounts_catalog;
+mod statement;
 
 pub use accounts_catalog::AccountsCatalog;
+pub use statement::{Direction, EntryKind, Statement, StatementEntry, Status};
+
+use rsa::sha2::{Digest as _, Sha256};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub enum AccountError {
@@ -32,7 +37,7 @@ const BUF_LEN: usize = 32;
     So, using a hash set practically provides little to no benefit over a circular buffer.
 */
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct NoncePool {
     iter: usize,
     buf_end: usize,
@@ -153,7 +158,7 @@ impl NoncePool {
 // An Account is a struct that represents a user account in the system.
 // It keeps track of the account's ID, nonce pool, held cents, and staked cents.
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Account {
     id: u32,
     nonce_pool: NoncePool,
@@ -211,6 +216,26 @@ impl Account {
     }
 }
 
+// deterministic commitment to the balances, stakes and nonces of the given accounts
+// the accounts are expected to be sorted by ID, so that the order is always the same
+pub fn calculate_state_root(accounts: &[Account]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    for acc in accounts {
+        hasher.update(acc.id.to_be_bytes());
+        hasher.update(acc.held_cents.to_be_bytes());
+        hasher.update(acc.staked_cents.to_be_bytes());
+
+        // the nonce pool is hashed as is, since two pools with
+        // the same fields always consider the same nonces used
+        hasher.update((acc.nonce_pool.iter as u64).to_be_bytes());
+        hasher.update((acc.nonce_pool.buf_end as u64).to_be_bytes());
+        hasher.update(acc.nonce_pool.buf.map(u8::from));
+    }
+
+    hasher.finalize().into()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,6 +6,7 @@ use crate::{
     },
     crypto::PublicKey,
     peer::PeersCatalog,
+    snapshot::{Snapshot, SnapshotError},
 };
 use std::ops::Deref;
 
@@ -18,6 +19,10 @@ use std::ops::Deref;
     The process_transaction() method is used to update the accounts of a catalog
     based on a transaction. Similarly, the process_block() method is used to update
     the accounts of a catalog based on the transactions a block.
+
+    The calculate_state_root() method produces a hash that commits to the balances,
+    stakes and nonces of every account. Every block carries the state root of the
+    accounts after it has been processed, so nodes that disagree on the state notice it.
 */
 
 #[derive(Debug)]
@@ -48,6 +53,33 @@ impl<'a> AccountsCatalog<'a> {
         Self { peers, accounts }
     }
 
+    // restore the accounts from a snapshot instead of replaying the blockchain
+    // fails if the snapshot cannot be verified or does not match the peers
+    pub fn from_snapshot(
+        peers: &'a PeersCatalog,
+        snapshot: &Snapshot,
+    ) -> Result<Self, SnapshotError> {
+        snapshot.verify()?;
+
+        let accounts = snapshot.accounts();
+        if accounts.len() != peers.len()
+            || accounts
+                .iter()
+                .zip(peers.iter().peers_by_id_asc())
+                .any(|(acc, peer)| acc.id() != peer.id())
+        {
+            return Err(SnapshotError::MismatchedAccounts {
+                expected: peers.len(),
+                actual: accounts.len(),
+            });
+        }
+
+        Ok(Self {
+            peers,
+            accounts: accounts.to_vec(),
+        })
+    }
+
     pub fn get_by_id(&self, id: u32) -> Option<&Account> {
         self.accounts.get(id as usize)
     }
@@ -87,10 +119,22 @@ impl<'a> AccountsCatalog<'a> {
             sndr.nonce_pool_mut().mark_used(tsx.nonce());
         }
 
-        // recipient is None in stake transactions
+        // recipient is None in stake transactions (except for genesis stakes) and multi-transfers
         if let Some(addr) = tsx.recp_addr() {
             let recp = self.get_by_publ_key_mut(addr).unwrap();
-            recp.add_held(tsx.total_cost() - tsx.fees());
+            if matches!(tsx.payload(), TransactionPayload::Stake(_)) {
+                recp.add_staked(tsx.total_cost() - tsx.fees());
+            } else {
+                recp.add_held(tsx.total_cost() - tsx.fees());
+            }
+        }
+
+        // the sender has paid for every output above, so the recipients cannot fail to be credited
+        if let TransactionPayload::MultiTransfer(outputs) = tsx.payload() {
+            for out in outputs {
+                let recp = self.get_by_publ_key_mut(out.recp_addr()).unwrap();
+                recp.add_held(out.amnt().get());
+            }
         }
 
         Ok(())
@@ -99,13 +143,24 @@ impl<'a> AccountsCatalog<'a> {
     // update the accounts of a catalog based on the transactions of a block
     // leaves the catalog unchanged if an error occurs
     pub fn process_block(&mut self, blk: &Block) -> Result<(), AccountsCatalogError> {
+        self.process_transactions(blk.tsxs(), blk.val())
+    }
+
+    // same as process_block() but for transactions that are not yet part of a block
+    // used to calculate the state root of a block before minting it
+    // leaves the catalog unchanged if an error occurs
+    pub fn process_transactions(
+        &mut self,
+        tsxs: &[Transaction],
+        val: Option<&PublicKey>,
+    ) -> Result<(), AccountsCatalogError> {
         let mut self_clone = self.clone();
 
-        for tsx in blk.tsxs() {
+        for tsx in tsxs {
             self_clone.process_transaction(tsx)?;
 
             // validator is None in genesis transactions
-            if let Some(v) = blk.val() {
+            if let Some(v) = val {
                 self_clone
                     .get_by_publ_key_mut(v)
                     .unwrap()
@@ -117,6 +172,10 @@ impl<'a> AccountsCatalog<'a> {
 
         Ok(())
     }
+
+    pub fn calculate_state_root(&self) -> [u8; 32] {
+        super::calculate_state_root(&self.accounts)
+    }
 }
 
 impl Deref for AccountsCatalog<'_> {
@@ -126,3 +185,94 @@ impl Deref for AccountsCatalog<'_> {
         &self.accounts
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::PrivateKey;
+    use rsa::RsaPrivateKey;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn test_state_root() {
+        let mut peers = PeersCatalog::new();
+        for port in 0..2 {
+            let priv_key =
+                PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+            peers
+                .insert((priv_key.to_publ_key(), ([127, 0, 0, 1], port).into()))
+                .unwrap();
+        }
+
+        let mut accounts = AccountsCatalog::new(&peers);
+        let empty_root = accounts.calculate_state_root();
+        assert_eq!(
+            empty_root,
+            AccountsCatalog::new(&peers).calculate_state_root()
+        );
+
+        let publ_key = peers.get_by_id(1).unwrap().publ_key().clone();
+        let tsx = Transaction::new_genesis(publ_key, NonZeroU32::new(100).unwrap());
+        accounts.process_transaction(&tsx).unwrap();
+        assert_ne!(empty_root, accounts.calculate_state_root());
+        assert_eq!(
+            accounts.calculate_state_root(),
+            accounts.clone().calculate_state_root()
+        );
+    }
+
+    #[test]
+    fn test_multi_transfer() {
+        use crate::blockchain::transaction::{TransactionValidator, TransferOutput};
+
+        let mut peers = PeersCatalog::new();
+        let mut priv_keys = vec![];
+        for port in 0..3 {
+            let priv_key =
+                PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+            peers
+                .insert((priv_key.to_publ_key(), ([127, 0, 0, 1], port).into()))
+                .unwrap();
+            priv_keys.push(priv_key);
+        }
+        let publ_key = |id| peers.get_by_id(id).unwrap().publ_key().clone();
+        let amnt = |n| NonZeroU32::new(n).unwrap();
+
+        let mut accounts = AccountsCatalog::new(&peers);
+        let tsx = Transaction::new_genesis(publ_key(0), amnt(10_000));
+        accounts.process_transaction(&tsx).unwrap();
+
+        let outputs = vec![
+            TransferOutput::new(publ_key(1), amnt(1_000)),
+            TransferOutput::new(publ_key(2), amnt(2_500)),
+        ];
+        let cost = Transaction::calculate_multi_transfer_total_cost(&outputs);
+        let tsx = Transaction::new_multi_transfer(publ_key(0), outputs, 0, &priv_keys[0]);
+        TransactionValidator::validate_structure(&tsx).unwrap();
+        accounts.process_transaction(&tsx).unwrap();
+
+        assert_eq!(accounts.get_by_id(0).unwrap().held_cents(), 10_000 - cost);
+        assert_eq!(accounts.get_by_id(1).unwrap().held_cents(), 1_000);
+        assert_eq!(accounts.get_by_id(2).unwrap().held_cents(), 2_500);
+        assert_eq!(tsx.fees() + 3_500, cost);
+
+        // the same recipient twice, or the sender itself, is rejected
+        for recps in [[1, 1], [1, 0]] {
+            let outputs = recps
+                .map(|id| TransferOutput::new(publ_key(id), amnt(100)))
+                .to_vec();
+            let tsx = Transaction::new_multi_transfer(publ_key(0), outputs, 1, &priv_keys[0]);
+            assert!(TransactionValidator::validate_structure(&tsx).is_err());
+        }
+
+        // nobody is credited if the sender cannot pay for every output
+        let before = accounts.calculate_state_root();
+        let outputs = vec![
+            TransferOutput::new(publ_key(0), amnt(1)),
+            TransferOutput::new(publ_key(2), amnt(2_000)),
+        ];
+        let tsx = Transaction::new_multi_transfer(publ_key(1), outputs, 0, &priv_keys[1]);
+        assert!(accounts.process_transaction(&tsx).is_err());
+        assert_eq!(accounts.calculate_state_root(), before);
+    }
+}
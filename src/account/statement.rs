@@ -0,0 +1,464 @@
+use crate::{
+    blockchain::{
+        block::Block,
+        transaction::{Transaction, TransactionPayload},
+        Blockchain,
+    },
+    crypto::PublicKey,
+    genesis::chain_params,
+    peer::PeersCatalog,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/*
+    A Statement lists every transaction involving an account, newest first,
+    so that its balance can be reconciled without reading the debug history.
+
+    It is built by walking the blocks of the blockchain that have not been pruned
+    and then the pending transactions. The fees a validator collects from a block
+    are listed as well, since they are also part of the balance of the validator.
+    The fee of an entry is the fee of its transaction, which is paid by the sender.
+*/
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+    // stakes move coins between the held and staked amounts of the same account
+    Own,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Transfer,
+    Message,
+    EncryptedMessage,
+    Stake,
+    MultiTransfer,
+    ValidatorFees,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pending,
+    Block(u32),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StatementEntry {
+    kind: EntryKind,
+    direction: Direction,
+    // None for genesis transactions, stakes, sent multi-transfers and validator fees
+    counterparty: Option<u32>,
+    amnt_cents: u32,
+    fee_cents: u32,
+    status: Status,
+}
+
+impl StatementEntry {
+    // None if the transaction does not involve the account
+    fn from_transaction(
+        tsx: &Transaction,
+        acc_addr: &PublicKey,
+        peers: &PeersCatalog,
+        status: Status,
+    ) -> Option<Self> {
+        let peer_id = |addr: Option<&PublicKey>| addr.and_then(|a| peers.get_by_publ_key(a));
+
+        // the output of a multi-transfer to the account
+        let output = match tsx.payload() {
+            TransactionPayload::MultiTransfer(outputs) => {
+                outputs.iter().find(|out| out.recp_addr() == acc_addr)
+            }
+            _ => None,
+        };
+
+        // what the recipient receives (or what is staked)
+        let mut amnt_cents = tsx.total_cost() - tsx.fees();
+
+        let (direction, counterparty) = if tsx.sndr_addr() == Some(acc_addr) {
+            match (tsx.recp_addr(), tsx.payload()) {
+                (Some(addr), _) => (Direction::Out, peer_id(Some(addr))),
+                (None, TransactionPayload::MultiTransfer(_)) => (Direction::Out, None),
+                (None, _) => (Direction::Own, None),
+            }
+        } else if tsx.recp_addr() == Some(acc_addr) {
+            (Direction::In, peer_id(tsx.sndr_addr()))
+        } else if let Some(out) = output {
+            // only its own part of the multi-transfer
+            amnt_cents = out.amnt().get();
+            (Direction::In, peer_id(tsx.sndr_addr()))
+        } else {
+            return None;
+        };
+
+        let kind = match tsx.payload() {
+            TransactionPayload::Transfer(_) => EntryKind::Transfer,
+            TransactionPayload::Message(_) => EntryKind::Message,
+            TransactionPayload::EncryptedMessage(_) => EntryKind::EncryptedMessage,
+            TransactionPayload::Stake(_) => EntryKind::Stake,
+            TransactionPayload::MultiTransfer(_) => EntryKind::MultiTransfer,
+        };
+
+        Some(Self {
+            kind,
+            direction,
+            counterparty: counterparty.map(|p| p.id()),
+            amnt_cents,
+            // nobody pays the fees of genesis transactions
+            fee_cents: if tsx.sndr_addr().is_some() {
+                tsx.fees()
+            } else {
+                0
+            },
+            status,
+        })
+    }
+
+    // None if the account is not the validator of the block (or there are no fees)
+    fn from_block(blk: &Block, acc_addr: &PublicKey) -> Option<Self> {
+        if blk.val() != Some(acc_addr) {
+            return None;
+        }
+
+        let fees: u32 = blk.tsxs().iter().map(|tsx| tsx.fees()).sum();
+        if fees == 0 {
+            return None;
+        }
+
+        Some(Self {
+            kind: EntryKind::ValidatorFees,
+            direction: Direction::In,
+            counterparty: None,
+            amnt_cents: fees,
+            fee_cents: 0,
+            status: Status::Block(blk.index()),
+        })
+    }
+
+    // getters
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn counterparty(&self) -> Option<u32> {
+        self.counterparty
+    }
+
+    pub fn amnt_cents(&self) -> u32 {
+        self.amnt_cents
+    }
+
+    pub fn fee_cents(&self) -> u32 {
+        self.fee_cents
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Statement {
+    entries: Vec<StatementEntry>,
+    // the transactions of blocks before this one have been pruned
+    first_block: u32,
+}
+
+impl Statement {
+    pub fn new(
+        acc_addr: &PublicKey,
+        blockchain: &Blockchain,
+        pending: &[Transaction],
+        peers: &PeersCatalog,
+    ) -> Self {
+        let first_block = blockchain.oldest_retained();
+        let mut entries = vec![];
+
+        // the block before the oldest retained one is never needed, so this cannot fail
+        let blocks = blockchain
+            .get_block(first_block)
+            .into_iter()
+            .chain(blockchain.blocks_after(first_block).unwrap());
+
+        for blk in blocks {
+            let status = Status::Block(blk.index());
+            entries.extend(
+                blk.tsxs().iter().filter_map(|tsx| {
+                    StatementEntry::from_transaction(tsx, acc_addr, peers, status)
+                }),
+            );
+            entries.extend(StatementEntry::from_block(blk, acc_addr));
+        }
+
+        entries.extend(pending.iter().filter_map(|tsx| {
+            StatementEntry::from_transaction(tsx, acc_addr, peers, Status::Pending)
+        }));
+
+        // newest first
+        entries.reverse();
+
+        Self {
+            entries,
+            first_block,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn first_block(&self) -> u32 {
+        self.first_block
+    }
+
+    // pages are numbered from 1
+    pub fn page(&self, page: usize, page_size: usize) -> &[StatementEntry] {
+        let start = (page - 1).saturating_mul(page_size).min(self.entries.len());
+        let end = start.saturating_add(page_size).min(self.entries.len());
+
+        &self.entries[start..end]
+    }
+
+    pub fn page_count(&self, page_size: usize) -> usize {
+        self.entries.len().div_ceil(page_size)
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::In => write!(f, "in"),
+            Self::Out => write!(f, "out"),
+            Self::Own => write!(f, "self"),
+        }
+    }
+}
+
+impl Display for EntryKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Transfer => write!(f, "transfer"),
+            Self::Message => write!(f, "message"),
+            Self::EncryptedMessage => write!(f, "encrypted message"),
+            Self::Stake => write!(f, "stake"),
+            Self::MultiTransfer => write!(f, "multi-transfer"),
+            Self::ValidatorFees => write!(f, "validator fees"),
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Block(index) => write!(f, "block {}", index),
+        }
+    }
+}
+
+impl Display for StatementEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let counterparty = match (self.direction, self.counterparty, self.kind) {
+            (Direction::In, Some(id), _) => format!("from {}", id),
+            (Direction::Out, Some(id), _) => format!("to {}", id),
+            (Direction::In, None, EntryKind::Transfer | EntryKind::Stake) => "genesis".to_string(),
+            (Direction::Out, None, EntryKind::MultiTransfer) => "several".to_string(),
+            _ => "-".to_string(),
+        };
+        let cents_per_coin = chain_params().cents_per_coin as f64;
+
+        write!(
+            f,
+            "{:<10} | {:<4} | {:<17} | {:<7} | {} BCC | fee {} BCC",
+            self.status.to_string(),
+            self.direction.to_string(),
+            self.kind.to_string(),
+            counterparty,
+            self.amnt_cents as f64 / cents_per_coin,
+            self.fee_cents as f64 / cents_per_coin,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blockchain::transaction::TransferOutput, crypto::PrivateKey};
+    use non_empty_string::NonEmptyString;
+    use rsa::RsaPrivateKey;
+    use std::num::NonZeroU32;
+
+    fn new_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap())
+    }
+
+    fn amt(cents: u32) -> NonZeroU32 {
+        NonZeroU32::new(cents).unwrap()
+    }
+
+    // what an entry shows, in a form that can be compared
+    fn summary(e: &StatementEntry) -> (EntryKind, Direction, Option<u32>, u32, u32, Status) {
+        (
+            e.kind(),
+            e.direction(),
+            e.counterparty(),
+            e.amnt_cents(),
+            e.fee_cents(),
+            e.status(),
+        )
+    }
+
+    // the local account and two other peers (ids 0, 1 and 2),
+    // and a chain of 3 blocks with transactions between them
+    fn chain() -> ([PrivateKey; 3], PeersCatalog, Blockchain) {
+        let keys = [new_priv_key(), new_priv_key(), new_priv_key()];
+        let mut peers = PeersCatalog::new();
+        for key in &keys {
+            peers
+                .insert((key.to_publ_key(), "127.0.0.1:0".parse().unwrap()))
+                .unwrap();
+        }
+        let [a, b, c] = &keys;
+        let (a_addr, b_addr, c_addr) = (a.to_publ_key(), b.to_publ_key(), c.to_publ_key());
+
+        let mut blockchain = Blockchain::new(Block::new_genesis(
+            vec![
+                Transaction::new_genesis(a_addr.clone(), amt(1000)),
+                Transaction::new_genesis_stake(a_addr.clone(), amt(10)),
+            ],
+            0,
+            [0; 32],
+        ));
+
+        // validated by another peer
+        blockchain.add_block(Block::new(
+            vec![
+                Transaction::new_transfer(a_addr.clone(), b_addr.clone(), amt(100), 1, a),
+                Transaction::new_transfer(b_addr.clone(), a_addr.clone(), amt(50), 1, b),
+                Transaction::new_stake(a_addr.clone(), amt(10), 2, a),
+                Transaction::new_multi_transfer(
+                    b_addr.clone(),
+                    vec![
+                        TransferOutput::new(a_addr.clone(), amt(20)),
+                        TransferOutput::new(c_addr.clone(), amt(30)),
+                    ],
+                    2,
+                    b,
+                ),
+            ],
+            c_addr.clone(),
+            [0; 32],
+            [0; 32],
+        ));
+
+        // validated by the local peer
+        let msg = NonEmptyString::new("hi".to_string()).unwrap();
+        blockchain.add_block(Block::new(
+            vec![Transaction::new_message(a_addr.clone(), c_addr, msg, 3, a)],
+            a_addr,
+            [0; 32],
+            [0; 32],
+        ));
+
+        (keys, peers, blockchain)
+    }
+
+    #[test]
+    fn test_entries() {
+        let ([a, _, c], peers, blockchain) = chain();
+        let pending = [Transaction::new_transfer(
+            c.to_publ_key(),
+            a.to_publ_key(),
+            amt(5),
+            1,
+            &c,
+        )];
+
+        let statement = Statement::new(&a.to_publ_key(), &blockchain, &pending, &peers);
+        let entries: Vec<_> = statement
+            .page(1, statement.len())
+            .iter()
+            .map(summary)
+            .collect();
+
+        use {Direction::*, EntryKind::*};
+        assert_eq!(
+            entries,
+            [
+                (Transfer, In, Some(2), 5, 1, Status::Pending),
+                // the fee of the message
+                (ValidatorFees, In, None, 200, 0, Status::Block(2)),
+                (Message, Out, Some(2), 2, 200, Status::Block(2)),
+                // only its own output, but the fee of the whole multi-transfer
+                (MultiTransfer, In, Some(1), 20, 2, Status::Block(1)),
+                (Stake, Own, None, 10, 0, Status::Block(1)),
+                (Transfer, In, Some(1), 50, 1, Status::Block(1)),
+                (Transfer, Out, Some(1), 100, 3, Status::Block(1)),
+                (Stake, In, None, 10, 0, Status::Block(0)),
+                (Transfer, In, None, 1000, 0, Status::Block(0)),
+            ]
+        );
+
+        // the other peers do not see what does not involve them
+        let statement = Statement::new(&c.to_publ_key(), &blockchain, &[], &peers);
+        let entries: Vec<_> = statement
+            .page(1, statement.len())
+            .iter()
+            .map(summary)
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (Message, In, Some(0), 2, 200, Status::Block(2)),
+                (ValidatorFees, In, None, 6, 0, Status::Block(1)),
+                (MultiTransfer, In, Some(1), 30, 2, Status::Block(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pruned_blocks() {
+        let ([a, ..], peers, mut blockchain) = chain();
+        blockchain.prune(2);
+
+        let statement = Statement::new(&a.to_publ_key(), &blockchain, &[], &peers);
+        assert_eq!(statement.first_block(), 2);
+        assert_eq!(statement.len(), 2);
+        assert!(statement
+            .page(1, 10)
+            .iter()
+            .all(|e| e.status() == Status::Block(2)));
+    }
+
+    #[test]
+    fn test_paging() {
+        let ([a, ..], peers, blockchain) = chain();
+        let statement = Statement::new(&a.to_publ_key(), &blockchain, &[], &peers);
+        assert_eq!(statement.len(), 8);
+
+        assert_eq!(statement.page_count(3), 3);
+        assert_eq!(statement.page_count(8), 1);
+        assert_eq!(statement.page(1, 3).len(), 3);
+        assert_eq!(statement.page(3, 3).len(), 2);
+        assert!(statement.page(4, 3).is_empty());
+        assert!(statement.page(usize::MAX, usize::MAX).is_empty());
+
+        // newest first
+        assert_eq!(statement.page(1, 3)[0].kind(), EntryKind::ValidatorFees);
+        assert_eq!(statement.page(3, 3)[1].amnt_cents(), 1000);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::crypto::PublicKey;
+use rsa::sha2::{Digest as _, Sha256};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/*
+    An Address identifies an account by its public key, unlike network IDs,
+    which depend on the order in which the peers joined the network.
+
+    It is the first 20 bytes of the fingerprint of the key (its SHA-256 hash),
+    written as `bc` followed by the Base58Check encoding of a version byte, the hash
+    and a checksum (the first 4 bytes of the double SHA-256 hash of the version and the hash).
+    The checksum catches mistyped addresses, and since the version is 0,
+    every address starts with `bc1`.
+*/
+
+pub const ADDRESS_PREFIX: &str = "bc";
+
+const VERSION: u8 = 0;
+const HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+
+// the Base58 alphabet of Bitcoin (without `0`, `O`, `I` and `l`, which are easily confused)
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("Addresses start with `{}`", ADDRESS_PREFIX)]
+    MissingPrefix,
+    #[error("Invalid character `{0}` in address")]
+    InvalidCharacter(char),
+    #[error("Invalid address length")]
+    InvalidLength,
+    #[error("Unsupported address version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid address checksum (the address may have been mistyped)")]
+    InvalidChecksum,
+}
+
+#[derive(Clone, Copy, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address([u8; HASH_LEN]);
+
+impl Address {
+    pub fn from_publ_key(publ_key: &PublicKey) -> Self {
+        let hash = publ_key.fingerprint_bytes();
+        Self(hash[..HASH_LEN].try_into().unwrap())
+    }
+
+    fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+        let hash = Sha256::digest(Sha256::digest(payload));
+        hash[..CHECKSUM_LEN].try_into().unwrap()
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut data = vec![VERSION];
+        data.extend_from_slice(&self.0);
+        data.extend_from_slice(&Self::checksum(&data));
+
+        write!(f, "{}{}", ADDRESS_PREFIX, base58_encode(&data))
+    }
+}
+
+impl Debug for Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .strip_prefix(ADDRESS_PREFIX)
+            .ok_or(AddressError::MissingPrefix)?;
+        let data = base58_decode(encoded)?;
+
+        if data.len() != 1 + HASH_LEN + CHECKSUM_LEN {
+            return Err(AddressError::InvalidLength);
+        }
+
+        let (payload, checksum) = data.split_at(1 + HASH_LEN);
+        if payload[0] != VERSION {
+            return Err(AddressError::UnsupportedVersion(payload[0]));
+        }
+        if checksum != Self::checksum(payload) {
+            return Err(AddressError::InvalidChecksum);
+        }
+
+        Ok(Self(payload[1..].try_into().unwrap()))
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = AddressError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Address> for String {
+    fn from(addr: Address) -> Self {
+        addr.to_string()
+    }
+}
+
+// the bytes as a big-endian number in base 58, with a `1` for each leading zero byte
+fn base58_encode(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+
+    // the digits in base 58, least significant first
+    let mut digits: Vec<u8> = vec![];
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let leading = std::iter::repeat_n('1', zeros);
+    let rest = digits.iter().rev().map(|&d| ALPHABET[d as usize] as char);
+    leading.chain(rest).collect()
+}
+
+fn base58_decode(s: &str) -> Result<Vec<u8>, AddressError> {
+    let zeros = s.chars().take_while(|&c| c == '1').count();
+
+    // the bytes, least significant first
+    let mut bytes: Vec<u8> = vec![];
+    for c in s.chars().skip(zeros) {
+        let mut carry = ALPHABET
+            .iter()
+            .position(|&a| a as char == c)
+            .ok_or(AddressError::InvalidCharacter(c))? as u32;
+
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base58() {
+        // test vectors of the Base58 encoding used by Bitcoin
+        assert_eq!(base58_encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+        assert_eq!(base58_encode(&[0, 0, 0x28, 0x7f, 0xb4, 0xcd]), "11233QC4");
+        assert_eq!(
+            base58_decode("11233QC4").unwrap(),
+            [0, 0, 0x28, 0x7f, 0xb4, 0xcd]
+        );
+        assert_eq!(base58_decode("2NEpo7TZRRrLZSi2U").unwrap(), b"Hello World!");
+        assert_eq!(base58_decode("0"), Err(AddressError::InvalidCharacter('0')));
+    }
+
+    #[test]
+    fn test_address() {
+        let addr = Address([7; HASH_LEN]);
+        let text = addr.to_string();
+
+        assert!(text.starts_with("bc1"));
+        assert_eq!(text.parse(), Ok(addr));
+
+        // changing any character breaks the checksum
+        let mut mistyped = text.into_bytes();
+        let last = mistyped.last_mut().unwrap();
+        *last = if *last == b'2' { b'3' } else { b'2' };
+        let mistyped = String::from_utf8(mistyped).unwrap();
+        assert_eq!(
+            mistyped.parse::<Address>(),
+            Err(AddressError::InvalidChecksum)
+        );
+    }
+}
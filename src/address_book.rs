@@ -0,0 +1,262 @@
+use crate::crypto::{self, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/*
+    The AddressBook maps names chosen by the local user to public keys,
+    so that peers can be referred to by name rather than by their network ID
+    (which depends on the order in which the peers joined the network).
+
+    Names are kept sorted. If a file is given, the address book is kept in it
+    (with the keys as PKCS#1 PEM), so it survives restarts.
+*/
+
+// names are at most this long
+pub const MAX_NAME_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum AddressBookError {
+    #[error("The address book could not be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("The address book could not be (de)serialized: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("The address book contains an invalid key for `{0}`")]
+    InvalidKey(String),
+    #[error(
+        "Invalid name `{0}` (names start with a letter, contain only letters, digits, `-` and `_`, and are not key fingerprints)"
+    )]
+    InvalidName(String),
+    #[error("The name `{0}` is already in the address book")]
+    DuplicateName(String),
+    #[error("The name `{0}` is not in the address book")]
+    NotFound(String),
+}
+
+// an entry as it is kept in the file
+#[derive(Deserialize, Serialize)]
+struct FileEntry {
+    name: String,
+    key: String, // PKCS#1 PEM
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: BTreeMap<String, PublicKey>,
+    file: Option<PathBuf>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // keep the address book in the given file (it is created once the first name is added)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AddressBookError> {
+        let path = path.as_ref();
+
+        let file_entries: Vec<FileEntry> = match fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+
+        let entries = file_entries
+            .into_iter()
+            .map(|e| match PublicKey::from_pem(&e.key) {
+                Some(key) => Ok((e.name, key)),
+                None => Err(AddressBookError::InvalidKey(e.name)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            entries,
+            file: Some(path.to_path_buf()),
+        })
+    }
+
+    fn save(&self) -> Result<(), AddressBookError> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let file_entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(name, key)| FileEntry {
+                name: name.clone(),
+                key: key.to_pem(),
+            })
+            .collect();
+
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &file_entries)?;
+
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, key: PublicKey) -> Result<(), AddressBookError> {
+        validate_name(name)?;
+
+        if self.entries.contains_key(name) {
+            return Err(AddressBookError::DuplicateName(name.to_string()));
+        }
+
+        // the address book is left as it was if it cannot be saved
+        self.entries.insert(name.to_string(), key);
+        self.save().inspect_err(|_| {
+            self.entries.remove(name);
+        })
+    }
+
+    // returns the key the name was given to
+    pub fn remove(&mut self, name: &str) -> Result<PublicKey, AddressBookError> {
+        let key = self
+            .entries
+            .remove(name)
+            .ok_or_else(|| AddressBookError::NotFound(name.to_string()))?;
+
+        if let Err(e) = self.save() {
+            self.entries.insert(name.to_string(), key);
+            return Err(e);
+        }
+
+        Ok(key)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PublicKey> {
+        self.entries.get(name)
+    }
+
+    // the entries sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PublicKey)> {
+        self.entries.iter().map(|(name, key)| (name.as_str(), key))
+    }
+}
+
+// a name starts with a letter and is not a key fingerprint (so it cannot be mistaken
+// for a network ID or a fingerprint), and contains only letters, digits, `-` and `_`
+pub fn validate_name(name: &str) -> Result<(), AddressBookError> {
+    let valid = name.len() <= MAX_NAME_LEN
+        && !crypto::is_fingerprint(name)
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if !valid {
+        return Err(AddressBookError::InvalidName(name.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::PrivateKey;
+    use rsa::RsaPrivateKey;
+    use std::env;
+
+    fn new_publ_key() -> PublicKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap()).to_publ_key()
+    }
+
+    fn names(book: &AddressBook) -> Vec<&str> {
+        book.iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["alice", "Bob_2", "c-3po", &"z".repeat(MAX_NAME_LEN)] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+
+        // "deadbeefdeadbeef" has the form of a fingerprint
+        for name in [
+            "",
+            "2pac",
+            "_alice",
+            "alice smith",
+            "alice!",
+            "élise",
+            "deadbeefdeadbeef",
+            &"z".repeat(MAX_NAME_LEN + 1),
+        ] {
+            assert!(
+                matches!(validate_name(name), Err(AddressBookError::InvalidName(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let path = env::temp_dir().join(format!(
+            "block_chat_address_book_{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        // the file is only created once the first name is added
+        let mut book = AddressBook::load(&path).unwrap();
+        assert!(names(&book).is_empty());
+        assert!(!path.exists());
+
+        let (alice, bob) = (new_publ_key(), new_publ_key());
+        book.add("bob", bob.clone()).unwrap();
+        book.add("alice", alice.clone()).unwrap();
+        assert!(matches!(
+            book.add("alice", bob.clone()),
+            Err(AddressBookError::DuplicateName(_))
+        ));
+
+        let book = AddressBook::load(&path).unwrap();
+        assert_eq!(names(&book), ["alice", "bob"]);
+        assert_eq!(book.get("alice"), Some(&alice));
+        assert_eq!(book.get("bob"), Some(&bob));
+
+        // a file with an invalid key is refused
+        fs::write(&path, r#"[{"name": "alice", "key": "not a key"}]"#).unwrap();
+        assert!(matches!(
+            AddressBook::load(&path),
+            Err(AddressBookError::InvalidKey(name)) if name == "alice"
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut book = AddressBook::new();
+        let alice = new_publ_key();
+        book.add("alice", alice.clone()).unwrap();
+
+        // the file cannot be created in a directory that does not exist
+        book.file = Some(
+            env::temp_dir()
+                .join(format!("block_chat_missing_{}", std::process::id()))
+                .join("address_book.json"),
+        );
+
+        assert!(matches!(
+            book.add("bob", new_publ_key()),
+            Err(AddressBookError::Io(_))
+        ));
+        assert!(book.get("bob").is_none());
+
+        assert!(matches!(book.remove("alice"), Err(AddressBookError::Io(_))));
+        assert_eq!(book.get("alice"), Some(&alice));
+
+        assert!(matches!(
+            book.remove("bob"),
+            Err(AddressBookError::NotFound(_))
+        ));
+    }
+}
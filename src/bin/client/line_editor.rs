@@ -0,0 +1,315 @@
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead as _, IsTerminal as _, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/*
+    A minimal line editor for the REPL, on top of the raw mode and the key events of crossterm
+    (the REPL needs very little of a full line editor). It supports:
+    - moving the cursor (arrows, Home/End, Ctrl-A/Ctrl-E) and deleting (Backspace, Delete, Ctrl-U)
+    - a history navigated with the up and down arrows, optionally kept in a file
+    - tab completion of the word before the cursor
+    - printing lines above the prompt while a line is being typed (see `Printer`)
+
+    Editing needs a terminal (in raw mode). If stdin is not a terminal,
+    lines are read as they are and no prompt is shown.
+*/
+
+// how many lines of the history file are loaded
+const MAX_HISTORY_LEN: usize = 1000;
+
+pub enum ReadLine {
+    Line(String),
+    // the line was discarded with Ctrl-C
+    Interrupted,
+    // Ctrl-D on an empty line, or the end of stdin
+    Eof,
+}
+
+// given the line up to the cursor, the candidates for the word before the cursor
+pub type Completer = Box<dyn Fn(&str) -> Vec<String>>;
+
+// the line being edited (if any), shared with the printers
+#[derive(Default)]
+struct EditState {
+    active: bool,
+    prompt: String,
+    buf: Vec<char>,
+    cursor: usize,
+}
+
+impl EditState {
+    // redraw the prompt and the line, and put the cursor back in place
+    fn redraw(&self, out: &mut impl Write) -> io::Result<()> {
+        let line: String = self.buf.iter().collect();
+        write!(out, "\r\x1b[2K{}{}", self.prompt, line)?;
+
+        let back = self.buf.len() - self.cursor;
+        if back > 0 {
+            write!(out, "\x1b[{}D", back)?;
+        }
+
+        out.flush()
+    }
+
+    fn insert(&mut self, s: &str) {
+        for c in s.chars() {
+            self.buf.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn set_line(&mut self, line: &str) {
+        self.buf = line.chars().collect();
+        self.cursor = self.buf.len();
+    }
+}
+
+// prints lines without mangling the line being edited
+#[derive(Clone)]
+pub struct Printer(Arc<Mutex<EditState>>);
+
+impl Printer {
+    pub fn println(&self, text: &str) {
+        let state = self.0.lock().unwrap();
+        let mut out = io::stdout().lock();
+
+        // output processing is turned off in raw mode, so `\n` does not return the cursor
+        let _ = if state.active {
+            write!(out, "\r\x1b[2K{}\r\n", text.replace('\n', "\r\n"))
+                .and_then(|_| state.redraw(&mut out))
+        } else {
+            writeln!(out, "{}", text)
+        };
+    }
+}
+
+pub struct LineEditor {
+    state: Arc<Mutex<EditState>>,
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+    completer: Completer,
+}
+
+impl LineEditor {
+    pub fn new(history_file: Option<PathBuf>, completer: Completer) -> Self {
+        // a missing history file is created once the first line is read
+        let history = history_file
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| {
+                let lines: Vec<_> = contents.lines().map(str::to_string).collect();
+                let skip = lines.len().saturating_sub(MAX_HISTORY_LEN);
+                lines.into_iter().skip(skip).collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            state: Default::default(),
+            history,
+            history_file,
+            completer,
+        }
+    }
+
+    pub fn printer(&self) -> Printer {
+        Printer(self.state.clone())
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine> {
+        let line = if io::stdin().is_terminal() {
+            let raw_mode = RawMode::enable()?;
+
+            {
+                let mut state = self.state.lock().unwrap();
+                state.active = true;
+                state.prompt = prompt.to_string();
+                state.set_line("");
+                state.redraw(&mut io::stdout())?;
+            }
+
+            let line = self.edit();
+            self.state.lock().unwrap().active = false;
+            drop(raw_mode);
+
+            line?
+        } else {
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line)? {
+                0 => ReadLine::Eof,
+                _ => ReadLine::Line(line.trim_end_matches(['\r', '\n']).to_string()),
+            }
+        };
+
+        if let ReadLine::Line(line) = &line {
+            self.add_history(line);
+        }
+
+        Ok(line)
+    }
+
+    fn edit(&self) -> io::Result<ReadLine> {
+        let mut out = io::stdout();
+
+        // the position in the history, and the line that was being typed before moving in it
+        let mut history_pos = self.history.len();
+        let mut draft = String::new();
+
+        loop {
+            let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            else {
+                continue;
+            };
+            if kind == KeyEventKind::Release {
+                continue;
+            }
+
+            let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+            let mut state = self.state.lock().unwrap();
+
+            match code {
+                KeyCode::Char('c') if ctrl => {
+                    write!(out, "^C\r\n")?;
+                    return Ok(ReadLine::Interrupted);
+                }
+                KeyCode::Char('d') if ctrl && state.buf.is_empty() => {
+                    write!(out, "\r\n")?;
+                    return Ok(ReadLine::Eof);
+                }
+                KeyCode::Char('d') if ctrl && state.cursor < state.buf.len() => {
+                    let cursor = state.cursor;
+                    state.buf.remove(cursor);
+                }
+                KeyCode::Delete if state.cursor < state.buf.len() => {
+                    let cursor = state.cursor;
+                    state.buf.remove(cursor);
+                }
+                KeyCode::Char('u') if ctrl => {
+                    let cursor = state.cursor;
+                    state.buf.drain(..cursor);
+                    state.cursor = 0;
+                }
+                KeyCode::Char('a') if ctrl => state.cursor = 0,
+                KeyCode::Char('e') if ctrl => state.cursor = state.buf.len(),
+                KeyCode::Char(c) if !ctrl => state.insert(c.encode_utf8(&mut [0; 4])),
+                KeyCode::Enter => {
+                    write!(out, "\r\n")?;
+                    return Ok(ReadLine::Line(state.buf.iter().collect()));
+                }
+                KeyCode::Backspace if state.cursor > 0 => {
+                    state.cursor -= 1;
+                    let cursor = state.cursor;
+                    state.buf.remove(cursor);
+                }
+                KeyCode::Left if state.cursor > 0 => state.cursor -= 1,
+                KeyCode::Right if state.cursor < state.buf.len() => state.cursor += 1,
+                KeyCode::Home => state.cursor = 0,
+                KeyCode::End => state.cursor = state.buf.len(),
+                KeyCode::Up if history_pos > 0 => {
+                    if history_pos == self.history.len() {
+                        draft = state.buf.iter().collect();
+                    }
+                    history_pos -= 1;
+                    state.set_line(&self.history[history_pos]);
+                }
+                KeyCode::Down if history_pos < self.history.len() => {
+                    history_pos += 1;
+                    match self.history.get(history_pos) {
+                        Some(line) => state.set_line(line),
+                        None => state.set_line(&draft),
+                    }
+                }
+                KeyCode::Tab => self.complete(&mut state, &mut out)?,
+                _ => {}
+            }
+
+            state.redraw(&mut out)?;
+        }
+    }
+
+    // complete the word before the cursor as far as the candidates agree,
+    // or list the candidates if they do not agree at all
+    fn complete(&self, state: &mut EditState, out: &mut impl Write) -> io::Result<()> {
+        let before: String = state.buf[..state.cursor].iter().collect();
+        let word = &before[before.rfind(' ').map_or(0, |i| i + 1)..];
+
+        let candidates: Vec<_> = (self.completer)(&before)
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .collect();
+
+        match candidates.as_slice() {
+            [] => {}
+            [candidate] => {
+                state.insert(&candidate[word.len()..]);
+                state.insert(" ");
+            }
+            [first, rest @ ..] => {
+                // the length (in bytes) of the prefix all candidates share
+                let common_len = rest.iter().fold(first.len(), |len, c| {
+                    first[..len]
+                        .chars()
+                        .zip(c.chars())
+                        .take_while(|(a, b)| a == b)
+                        .map(|(a, _)| a.len_utf8())
+                        .sum()
+                });
+
+                if common_len > word.len() {
+                    state.insert(&first[word.len()..common_len]);
+                } else {
+                    write!(out, "\r\x1b[2K{}\r\n", candidates.join("  "))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+
+        self.history.push(line.to_string());
+
+        if let Some(path) = &self.history_file {
+            let res = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line));
+
+            if let Err(e) = res {
+                log::warn!("Failed to save the history to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// puts the terminal in raw mode until dropped
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
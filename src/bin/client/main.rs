@@ -0,0 +1,79 @@
+mod line_editor;
+mod repl;
+
+use block_chat::{
+    cli::{self, Args, ClientCommand},
+    response::Response,
+};
+use clap::Parser as _;
+use env_logger::Env;
+use std::{
+    io::{self, BufReader},
+    net::TcpStream,
+    process::ExitCode,
+};
+
+// environment variable to set the logging level
+const LOGGIN_LEVEL_ENV: &str = "BLOCK_CHAT_CLIENT_LOGGING_LEVEL";
+const DEFAULT_LOGGING_LEVEL: &str = "warn";
+
+fn main() -> io::Result<ExitCode> {
+    // display message if arguments are incorrect (clap does this automatically)
+    let args = Args::parse();
+
+    // initialize logger and daemon address
+    init_logger();
+    let daemon_addr = cli::daemon_addr();
+
+    log::debug!("Daemon address: {}", daemon_addr);
+
+    let mut cmd = match args.cmd {
+        ClientCommand::Repl => {
+            repl::run(daemon_addr, args.json)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        ClientCommand::Daemon(cmd) => cmd,
+    };
+
+    if let Err(e) = cmd.load_batch() {
+        eprintln!("{}", e);
+        return Ok(ExitCode::FAILURE);
+    }
+
+    // send the command and print every response until the daemon closes the connection
+    // (a transaction submitted with `--wait` is followed by its confirmation,
+    // and `subscribe` is followed by events until the daemon exits)
+    let stream = cmd.send(daemon_addr)?;
+    let failed = print_responses(stream, args.json, |line| println!("{}", line))?;
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn init_logger() {
+    let env = Env::new().filter_or(LOGGIN_LEVEL_ENV, DEFAULT_LOGGING_LEVEL);
+    env_logger::init_from_env(env);
+}
+
+// print every response (as text or JSON) until the daemon closes the connection,
+// returns whether any of them is an error
+fn print_responses(stream: TcpStream, json: bool, mut print: impl FnMut(&str)) -> io::Result<bool> {
+    let responses = serde_json::Deserializer::from_reader(BufReader::new(stream));
+
+    let mut failed = false;
+    for response in responses.into_iter::<Response>() {
+        let response = response.map_err(io::Error::from)?;
+        failed |= response.is_error();
+
+        if json {
+            print(&serde_json::to_string(&response).expect("Failed to serialize response"));
+        } else {
+            print(&response.to_string());
+        }
+    }
+
+    Ok(failed)
+}
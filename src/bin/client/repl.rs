@@ -0,0 +1,244 @@
+use crate::{
+    line_editor::{LineEditor, Printer, ReadLine},
+    print_responses,
+};
+use block_chat::{
+    cli::{AddressBookCommand, Command},
+    events::{Event, EventType},
+    response::Response,
+};
+use clap::{CommandFactory as _, Parser};
+use std::{
+    env,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::PathBuf,
+    thread,
+};
+
+/*
+    The REPL (`client repl`, or `client shell`) keeps a session with the daemon:
+    commands are typed at a prompt (with a history and tab completion of commands
+    and peer IDs) and the messages sent to the local account are shown as soon as
+    they are included in a block, even while a command is being typed.
+
+    The daemon answers every command over its own connection, so each command is still
+    sent over a new connection, while the messages arrive over a subscription that
+    stays open for as long as the session lasts.
+*/
+
+// environment variable to set the file where the history of the REPL is kept
+// if it is not set, the history is lost when the REPL exits
+const HISTORY_FILE_ENV: &str = "BLOCK_CHAT_CLIENT_HISTORY_FILE";
+
+// the commands handled by the REPL itself
+const EXIT_COMMANDS: [&str; 2] = ["exit", "quit"];
+
+// a line typed at the prompt
+#[derive(Debug, Parser)]
+#[command(name = "", no_binary_name = true, disable_version_flag = true)]
+struct ReplLine {
+    /// Print the responses of the daemon as JSON, one object per line
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    cmd: Command,
+}
+
+pub fn run(daemon_addr: SocketAddr, json: bool) -> io::Result<()> {
+    // the session starts only if the daemon is up
+    let me = match request(Command::Whoami, daemon_addr)? {
+        Response::Whoami(me) => me,
+        res => return Err(io::Error::other(format!("Unexpected response: {}", res))),
+    };
+    let peer_ids = match request(Command::Peers, daemon_addr)? {
+        Response::Peers(peers) => peers.iter().map(|p| p.id.to_string()).collect(),
+        _ => vec![],
+    };
+    let names = match request(Command::AddressBook(AddressBookCommand::List), daemon_addr)? {
+        Response::AddressBook(contacts) => contacts.into_iter().map(|c| c.name).collect(),
+        _ => vec![],
+    };
+
+    let history_file = env::var(HISTORY_FILE_ENV).ok().map(PathBuf::from);
+    let mut editor = LineEditor::new(history_file, Box::new(completer(peer_ids, names)));
+    let printer = editor.printer();
+
+    spawn_message_thread(daemon_addr, printer.clone(), json)?;
+
+    println!(
+        "Connected to peer {} at {} (type `help` for the commands, `exit` to quit)",
+        me.id, daemon_addr
+    );
+    let prompt = format!("block_chat:{}> ", me.id);
+
+    loop {
+        let line = match editor.read_line(&prompt)? {
+            ReadLine::Line(line) => line,
+            ReadLine::Interrupted => continue,
+            ReadLine::Eof => break,
+        };
+
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+
+        match words.first() {
+            None => continue,
+            Some(word) if EXIT_COMMANDS.contains(&word.as_str()) => break,
+            Some(_) => {}
+        }
+
+        // clap prints the help and the usage errors
+        let mut line = match ReplLine::try_parse_from(&words) {
+            Ok(line) => line,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+
+        if let Command::Subscribe { .. } = line.cmd {
+            eprintln!(
+                "`subscribe` is not available in the REPL (messages are shown as they arrive)"
+            );
+            continue;
+        }
+
+        if let Err(e) = line.cmd.load_batch() {
+            eprintln!("{}", e);
+            continue;
+        }
+
+        let res = line
+            .cmd
+            .send(daemon_addr)
+            .and_then(|stream| print_responses(stream, json || line.json, |l| printer.println(l)));
+
+        if let Err(e) = res {
+            eprintln!("Failed to reach the daemon: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+// send a command and return its (only) response
+fn request(cmd: Command, addr: SocketAddr) -> io::Result<Response> {
+    let stream = cmd.send(addr)?;
+    let mut de = serde_json::Deserializer::from_reader(BufReader::new(stream));
+
+    Ok(serde::Deserialize::deserialize(&mut de)?)
+}
+
+// show the messages sent to the local account as they arrive
+fn spawn_message_thread(addr: SocketAddr, printer: Printer, json: bool) -> io::Result<()> {
+    let stream = Command::Subscribe {
+        types: vec![EventType::Message],
+    }
+    .send(addr)?;
+
+    thread::spawn(move || {
+        let responses = serde_json::Deserializer::from_reader(BufReader::new(stream));
+
+        for response in responses.into_iter::<Response>() {
+            let Ok(response) = response else {
+                break;
+            };
+
+            if json {
+                printer.println(&serde_json::to_string(&response).unwrap());
+                continue;
+            }
+
+            if let Response::Event(Event::Message {
+                sender,
+                block,
+                message,
+                encrypted,
+                ..
+            }) = response
+            {
+                let message = match (message, encrypted) {
+                    (Some(msg), false) => msg,
+                    (Some(msg), true) => format!("{} (encrypted)", msg),
+                    (None, _) => "<encrypted message that could not be decrypted>".to_string(),
+                };
+
+                printer.println(&format!(
+                    "New message from {} (block {}): {}",
+                    sender, block, message
+                ));
+            }
+        }
+
+        printer.println("The daemon closed the session, new messages will not be shown");
+    });
+
+    Ok(())
+}
+
+// complete command names, peer IDs where a peer is expected
+// and the names of the address book (as it was when the session started) where a recipient is
+fn completer(peer_ids: Vec<String>, names: Vec<String>) -> impl Fn(&str) -> Vec<String> {
+    let mut commands: Vec<_> = ReplLine::command()
+        .get_subcommands()
+        .filter(|cmd| !cmd.is_hide_set())
+        .map(|cmd| cmd.get_name().to_string())
+        .collect();
+    commands.extend(["help", "exit", "quit"].map(str::to_string));
+
+    move |before| {
+        let words: Vec<_> = before.split_whitespace().collect();
+
+        // the index of the word being completed
+        let index = if before.is_empty() || before.ends_with(' ') {
+            words.len()
+        } else {
+            words.len() - 1
+        };
+
+        let prev = index.checked_sub(1).map(|i| words[i]);
+        match (index, words.first().copied()) {
+            (0, _) => commands.clone(),
+            (1, Some("t" | "m" | "key")) => [peer_ids.as_slice(), names.as_slice()].concat(),
+            (1, Some("tx-history")) => peer_ids.clone(),
+            // every word of a multi-transfer starts with a recipient
+            (_, Some("mt" | "multi-transfer")) => [peer_ids.as_slice(), names.as_slice()].concat(),
+            (2, Some("address-book" | "ab")) if prev == Some("remove") => names.clone(),
+            _ if prev == Some("--from") => peer_ids.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+// split a line into words, keeping quoted text (in single or double quotes) together
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("Unterminated quote".to_string());
+    }
+    words.extend(word);
+
+    Ok(words)
+}
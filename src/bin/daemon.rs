@@ -1,20 +1,30 @@
 use block_chat::{
-    crypto::PrivateKey,
-    protocol::{Protocol, ProtocolConfig},
+    bootstrap::{JoinAuthorization, JoinTimeouts, StaticMembership},
+    crypto::{PrivateKey, PublicKey},
+    genesis::Genesis,
+    protocol::{Membership, Protocol, ProtocolConfig},
 };
 use env_logger::Env;
 use rsa::RsaPrivateKey;
 use std::{
-    env,
+    env, fs,
     net::{SocketAddr, ToSocketAddrs},
+    num::NonZeroU32,
+    path::PathBuf,
+    time::Duration,
 };
 
 // environment variable to set the logging level
 const LOGGIN_LEVEL_ENV: &str = "BLOCK_CHAT_DAEMON_LOGGING_LEVEL";
 const DEFAULT_LOGGING_LEVEL: &str = "warn";
 
+// environment variable to set the static membership file of the network
+// if it is set, no bootstrap round takes place and the bootstrap variables are ignored
+// the private key file must also be set, so that the node has the key it's listed with
+const MEMBERSHIP_FILE_ENV: &str = "BLOCK_CHAT_MEMBERSHIP_FILE";
+
 // environment variable to set the bootstrap peer address
-// if it is not set, the daemon will panic
+// if it is not set (and there is no static membership), the daemon will panic
 const BOOTSTRAP_PEER_SOCKET_ENV: &str = "BLOCK_CHAT_BOOTSTRAP_PEER_SOCKET";
 
 // environment variable to set the port for the bootstrapping process
@@ -30,32 +40,90 @@ const DEFAULT_NETWORK_PORT: u16 = 27737;
 const NETWORK_SIZE_ENV: &str = "BLOCK_CHAT_NETWORK_SIZE";
 const DEFAULT_NETWORK_SIZE: u16 = 5;
 
-// coins each peer will have when the network is initialized
-const INIT_COINS_PER_PEER: u32 = 1000;
+// environment variable to set how many seconds to wait for the network to form
+// if the deadline passes, the daemon exits with an error instead of waiting forever
+const JOIN_DEADLINE_ENV: &str = "BLOCK_CHAT_JOIN_DEADLINE";
+
+// environment variable to set how many times the bootstrap peer tries to send each join response
+const JOIN_RESPONSE_ATTEMPTS_ENV: &str = "BLOCK_CHAT_JOIN_RESPONSE_ATTEMPTS";
+
+// environment variable to set the file with the public keys allowed to join the network
+// (PKCS#1 PEM, one after the other), this only matters for the bootstrap peer (which is always allowed)
+// if it is not set, anyone who can reach the bootstrap peer can join
+const JOIN_ALLOWLIST_FILE_ENV: &str = "BLOCK_CHAT_JOIN_ALLOWLIST_FILE";
+
+// environment variable to set the file with the public key of the bootstrap peer (PKCS#1 PEM)
+// if it is set, join responses not signed with this key are rejected
+const BOOTSTRAP_PEER_KEY_FILE_ENV: &str = "BLOCK_CHAT_BOOTSTRAP_PEER_KEY_FILE";
+
+// environment variable to set how many blocks apart snapshots of the accounts are taken
+const SNAPSHOT_INTERVAL_ENV: &str = "BLOCK_CHAT_SNAPSHOT_INTERVAL";
+const DEFAULT_SNAPSHOT_INTERVAL: u32 = 100;
+
+// environment variable to set the directory where snapshots are saved
+// if it is not set, snapshots are only kept in memory
+const SNAPSHOT_DIR_ENV: &str = "BLOCK_CHAT_SNAPSHOT_DIR";
+
+// environment variable to set how many of the most recent blocks to keep in full
+// if it is not set, pruning is disabled and every block is kept
+const BLOCK_RETENTION_ENV: &str = "BLOCK_CHAT_BLOCK_RETENTION";
+
+// environment variable to set the file where the messages of the inbox and their read state are kept
+// if it is not set, the inbox starts out empty after a restart
+const INBOX_FILE_ENV: &str = "BLOCK_CHAT_INBOX_FILE";
+
+// environment variable to set the file where the address book is kept
+// if it is not set, the address book is only kept in memory
+const ADDRESS_BOOK_FILE_ENV: &str = "BLOCK_CHAT_ADDRESS_BOOK_FILE";
+
+// environment variable to set the address of the JSON-RPC API (e.g. `127.0.0.1:8545`)
+// if it is not set, the API is not served (see `rpc.rs` for the available methods)
+const RPC_SOCKET_ENV: &str = "BLOCK_CHAT_RPC_SOCKET";
+
+// environment variable to set the genesis file (chain spec) of the network
+// if it is not set, the default genesis is used
+// every peer in the network must use the same genesis
+const GENESIS_FILE_ENV: &str = "BLOCK_CHAT_GENESIS_FILE";
+
+// environment variable to set the file the private key is kept in (PKCS#1 PEM)
+// if the file does not exist, a new key is generated and saved in it
+// and its public key is saved next to it (with a `.pub` extension) to share with other peers
+// if it is not set, a new key is generated every time
+const PRIVATE_KEY_FILE_ENV: &str = "BLOCK_CHAT_PRIVATE_KEY_FILE";
 
 const RSA_BITS: usize = 2048;
 
 fn main() {
     init_logger();
-    let bootstrap_peer_addr = init_bootstrap_peer_addr();
-    let bootstrap_port = init_bootstrap_port();
-    let network_port = init_network_port();
-    let network_size = init_network_size();
+    let membership = init_membership();
+    let snapshot_interval = init_snapshot_interval();
+    let snapshot_dir = init_snapshot_dir();
+    let block_retention = init_block_retention();
+    let inbox_file = env::var(INBOX_FILE_ENV).ok().map(PathBuf::from);
+    let address_book_file = env::var(ADDRESS_BOOK_FILE_ENV).ok().map(PathBuf::from);
+    let rpc_addr = init_rpc_addr();
+    let genesis = init_genesis();
 
-    log::debug!("Bootstrap peer address: {}", bootstrap_peer_addr);
-    log::debug!("Bootstrap port: {}", bootstrap_port);
-    log::debug!("Network port: {}", network_port);
-    log::debug!("Network size: {}", network_size);
+    log::debug!("Snapshot interval: {}", snapshot_interval);
+    log::debug!("Snapshot directory: {:?}", snapshot_dir);
+    log::debug!("Block retention: {:?}", block_retention);
+    log::debug!("Inbox file: {:?}", inbox_file);
+    log::debug!("Address book file: {:?}", address_book_file);
+    log::debug!("RPC address: {:?}", rpc_addr);
 
-    // generate a new RSA private key
-    let priv_key = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), RSA_BITS).unwrap());
+    log::debug!("Genesis: {:#?}", genesis);
+
+    let priv_key = init_priv_key();
 
     let config = ProtocolConfig {
-        total_peers: network_size,
-        init_coins_per_peer: INIT_COINS_PER_PEER,
-        bootstrap_peer_addr,
-        bootstrap_port,
-        network_port,
+        membership,
+        genesis,
+        snapshot_interval,
+        snapshot_dir,
+        block_retention,
+        inbox_file,
+        address_book_file,
+        rpc_addr,
     };
 
     // create a new protocol instance and run it
@@ -68,6 +136,40 @@ fn init_logger() {
     env_logger::init_from_env(env);
 }
 
+fn init_membership() -> Membership<SocketAddr> {
+    if let Ok(path) = env::var(MEMBERSHIP_FILE_ENV) {
+        let membership = StaticMembership::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to load static membership from `{}`: {}", path, e));
+
+        log::debug!("Static membership: {:#?}", membership);
+
+        return Membership::Static(membership);
+    }
+
+    let bootstrap_peer_addr = init_bootstrap_peer_addr();
+    let bootstrap_port = init_bootstrap_port();
+    let network_port = init_network_port();
+    let network_size = init_network_size();
+    let auth = init_join_authorization();
+    let timeouts = init_join_timeouts();
+
+    log::debug!("Bootstrap peer address: {}", bootstrap_peer_addr);
+    log::debug!("Bootstrap port: {}", bootstrap_port);
+    log::debug!("Network port: {}", network_port);
+    log::debug!("Network size: {}", network_size);
+    log::debug!("Join authorization: {:?}", auth);
+    log::debug!("Join timeouts: {:?}", timeouts);
+
+    Membership::Dynamic {
+        total_peers: network_size,
+        bootstrap_peer_addr,
+        bootstrap_port,
+        network_port,
+        auth,
+        timeouts,
+    }
+}
+
 fn init_bootstrap_peer_addr() -> SocketAddr {
     env::var(BOOTSTRAP_PEER_SOCKET_ENV)
         .unwrap_or_else(|_| {
@@ -119,3 +221,148 @@ fn init_network_size() -> u16 {
         })
     })
 }
+
+fn init_join_timeouts() -> JoinTimeouts {
+    let mut timeouts = JoinTimeouts::default();
+
+    if let Ok(secs) = env::var(JOIN_DEADLINE_ENV) {
+        timeouts.deadline = Duration::from_secs(secs.parse().unwrap_or_else(|_| {
+            panic!(
+                "Environment variable `{}` could not be parsed as a valid number of seconds",
+                JOIN_DEADLINE_ENV
+            )
+        }));
+    }
+
+    if let Ok(attempts) = env::var(JOIN_RESPONSE_ATTEMPTS_ENV) {
+        timeouts.response_attempts = attempts.parse().unwrap_or_else(|_| {
+            panic!(
+                "Environment variable `{}` could not be parsed as a valid positive number",
+                JOIN_RESPONSE_ATTEMPTS_ENV
+            )
+        });
+    }
+
+    timeouts
+}
+
+fn init_join_authorization() -> JoinAuthorization {
+    let allowlist = env::var(JOIN_ALLOWLIST_FILE_ENV).ok().map(|path| {
+        let pem = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read the join allowlist `{}`: {}", path, e));
+
+        parse_publ_keys(&pem)
+            .unwrap_or_else(|| panic!("The join allowlist `{}` contains an invalid key", path))
+    });
+
+    let bootstrap_peer_key = env::var(BOOTSTRAP_PEER_KEY_FILE_ENV).ok().map(|path| {
+        let pem = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read the bootstrap peer key `{}`: {}", path, e));
+
+        PublicKey::from_pem(&pem)
+            .unwrap_or_else(|| panic!("The bootstrap peer key in `{}` is not valid", path))
+    });
+
+    JoinAuthorization {
+        allowlist,
+        bootstrap_peer_key,
+    }
+}
+
+// parses PEM encoded public keys, one after the other
+fn parse_publ_keys(pem: &str) -> Option<Vec<PublicKey>> {
+    const PEM_END: &str = "-----END RSA PUBLIC KEY-----";
+
+    pem.split_inclusive(PEM_END)
+        .filter(|block| block.contains(PEM_END))
+        .map(|block| PublicKey::from_pem(block.trim()))
+        .collect()
+}
+
+fn init_snapshot_interval() -> NonZeroU32 {
+    env::var(SNAPSHOT_INTERVAL_ENV).map_or(
+        NonZeroU32::new(DEFAULT_SNAPSHOT_INTERVAL).unwrap(),
+        |interval| {
+            interval.parse().unwrap_or_else(|_| {
+                panic!(
+                    "Environment variable `{}` could not be parsed as a valid positive number",
+                    SNAPSHOT_INTERVAL_ENV
+                )
+            })
+        },
+    )
+}
+
+fn init_snapshot_dir() -> Option<PathBuf> {
+    let dir = PathBuf::from(env::var(SNAPSHOT_DIR_ENV).ok()?);
+
+    fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        panic!(
+            "The snapshot directory `{}` could not be created: {}",
+            dir.display(),
+            e
+        )
+    });
+
+    Some(dir)
+}
+
+fn init_rpc_addr() -> Option<SocketAddr> {
+    let addr = env::var(RPC_SOCKET_ENV).ok()?;
+
+    Some(
+        addr.to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .unwrap_or_else(|| {
+                panic!(
+                    "Environment variable `{}` could not be parsed as a valid socket address",
+                    RPC_SOCKET_ENV
+                )
+            }),
+    )
+}
+
+fn init_block_retention() -> Option<NonZeroU32> {
+    let retention = env::var(BLOCK_RETENTION_ENV).ok()?;
+
+    Some(retention.parse().unwrap_or_else(|_| {
+        panic!(
+            "Environment variable `{}` could not be parsed as a valid positive number",
+            BLOCK_RETENTION_ENV
+        )
+    }))
+}
+
+fn init_genesis() -> Genesis {
+    let Ok(path) = env::var(GENESIS_FILE_ENV) else {
+        return Genesis::default();
+    };
+
+    Genesis::load(&path).unwrap_or_else(|e| panic!("Failed to load genesis from `{}`: {}", path, e))
+}
+
+fn init_priv_key() -> PrivateKey {
+    fn generate_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), RSA_BITS).unwrap())
+    }
+
+    let Ok(path) = env::var(PRIVATE_KEY_FILE_ENV) else {
+        return generate_priv_key();
+    };
+
+    if let Ok(pem) = fs::read_to_string(&path) {
+        return PrivateKey::from_pem(&pem)
+            .unwrap_or_else(|| panic!("The private key in `{}` is not valid", path));
+    }
+
+    let priv_key = generate_priv_key();
+    fs::write(&path, priv_key.to_pem())
+        .unwrap_or_else(|e| panic!("Failed to save the private key to `{}`: {}", path, e));
+
+    let publ_path = format!("{}.pub", path);
+    fs::write(&publ_path, priv_key.to_publ_key().to_pem())
+        .unwrap_or_else(|e| panic!("Failed to save the public key to `{}`: {}", publ_path, e));
+
+    priv_key
+}
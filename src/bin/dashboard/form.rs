@@ -0,0 +1,168 @@
+use block_chat::cli::{self, Command, WaitOptions};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::num::NonZeroU32;
+
+// the transactions that can be sent from the dashboard
+#[derive(Clone, Copy)]
+pub enum FormKind {
+    Transfer,
+    Message,
+    Stake,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Recipient, // a network ID, a name in the address book, an address or a key fingerprint
+    Amount,
+    Text,
+    Toggle, // switched with Space, `value` is either "yes" or "no"
+}
+
+pub struct Field {
+    pub label: &'static str,
+    pub value: String,
+    kind: FieldKind,
+}
+
+impl Field {
+    fn new(label: &'static str, kind: FieldKind) -> Self {
+        let value = match kind {
+            FieldKind::Toggle => "no".to_string(),
+            _ => String::new(),
+        };
+
+        Self { label, value, kind }
+    }
+}
+
+pub enum FormAction {
+    None,
+    Cancel,
+    Submit(Command),
+}
+
+pub struct Form {
+    kind: FormKind,
+    pub fields: Vec<Field>,
+    pub focus: usize,
+    pub error: Option<String>,
+}
+
+impl Form {
+    pub fn new(kind: FormKind) -> Self {
+        let fields = match kind {
+            FormKind::Transfer => vec![
+                Field::new("Recipient", FieldKind::Recipient),
+                Field::new("Amount (BCC)", FieldKind::Amount),
+            ],
+            FormKind::Message => vec![
+                Field::new("Recipient", FieldKind::Recipient),
+                Field::new("Message", FieldKind::Text),
+                Field::new("Encrypted", FieldKind::Toggle),
+            ],
+            FormKind::Stake => vec![Field::new("Amount (BCC)", FieldKind::Amount)],
+        };
+
+        Self {
+            kind,
+            fields,
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.kind {
+            FormKind::Transfer => "Send BCC",
+            FormKind::Message => "Send a message",
+            FormKind::Stake => "Stake BCC",
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> FormAction {
+        // the error is shown until the form is changed
+        if key.code != KeyCode::Enter {
+            self.error = None;
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let field = &mut self.fields[self.focus];
+
+        match key.code {
+            KeyCode::Esc => return FormAction::Cancel,
+            KeyCode::Char('c') if ctrl => return FormAction::Cancel,
+            KeyCode::Char('u') if ctrl && field.kind != FieldKind::Toggle => field.value.clear(),
+            KeyCode::Enter => match self.command() {
+                Ok(cmd) => return FormAction::Submit(cmd),
+                Err(e) => self.error = Some(e),
+            },
+            KeyCode::Tab | KeyCode::Down => self.focus = (self.focus + 1) % self.fields.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.focus = (self.focus + self.fields.len() - 1) % self.fields.len()
+            }
+            KeyCode::Char(' ') if field.kind == FieldKind::Toggle => {
+                field.value = if field.value == "yes" { "no" } else { "yes" }.to_string();
+            }
+            KeyCode::Char(c) if !ctrl && field.kind != FieldKind::Toggle => {
+                // numbers are typed as they are, anything else is rejected when submitting
+                field.value.push(c);
+            }
+            KeyCode::Backspace if field.kind != FieldKind::Toggle => {
+                field.value.pop();
+            }
+            _ => {}
+        }
+
+        FormAction::None
+    }
+
+    // the command for the transaction, if every field is valid
+    fn command(&self) -> Result<Command, String> {
+        let value = |label| {
+            self.fields
+                .iter()
+                .find(|f| f.label == label)
+                .map(|f| f.value.trim())
+                .unwrap_or_default()
+        };
+        let rcp = || {
+            cli::parse_recipient(value("Recipient")).map_err(|_| {
+                "The recipient must be a network ID, a name, an address or a key fingerprint"
+                    .to_string()
+            })
+        };
+        let amt = || {
+            value("Amount (BCC)")
+                .parse::<NonZeroU32>()
+                .map_err(|_| "The amount must be a positive whole number of BCC".to_string())
+        };
+
+        // the dashboard does not wait for the transaction to be included
+        // (the blocks and the mempool show when it is)
+        let wait = WaitOptions::default();
+
+        let cmd = match self.kind {
+            FormKind::Transfer => Command::T {
+                rcp: rcp()?,
+                amt: amt()?,
+                wait,
+            },
+            FormKind::Message => {
+                let msg = value("Message");
+                if msg.is_empty() {
+                    return Err("The message cannot be empty".to_string());
+                }
+
+                Command::M {
+                    rcp: rcp()?,
+                    msg: vec![msg.to_string()],
+                    encrypted: value("Encrypted") == "yes",
+                    wait,
+                }
+            }
+            FormKind::Stake => Command::S { amt: amt()?, wait },
+        };
+
+        Ok(cmd)
+    }
+}
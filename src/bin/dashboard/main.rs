@@ -0,0 +1,174 @@
+mod form;
+mod ui;
+mod view;
+
+use block_chat::{cli, response::Response};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use form::{Form, FormAction, FormKind};
+use ratatui::DefaultTerminal;
+use std::{
+    io::{self, IsTerminal as _},
+    net::SocketAddr,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+use view::NodeView;
+
+/*
+    A full-screen dashboard for node operators: it connects to a daemon (the same one the client
+    would, see `cli::daemon_addr`) and shows the chain, the validators, the accounts of the network
+    along with whether their peers are connected, the latest blocks and the mempool.
+    Transactions can be sent from a form without leaving the dashboard.
+
+    The dashboard only sends the commands of the client, so it can watch any running daemon.
+    It polls the daemon every `REFRESH_INTERVAL`, while another thread reads the keys.
+    The screen is drawn with ratatui (see `ui`).
+*/
+
+// how often the daemon is queried
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+// what the main loop reacts to
+enum Update {
+    Key(KeyEvent),
+    // the terminal was resized, so the screen is redrawn
+    Resize,
+    View(Result<Box<NodeView>, String>),
+    // the outcome of sending a transaction
+    Submitted(Result<String, String>),
+}
+
+pub struct Dashboard {
+    daemon_addr: SocketAddr,
+    view: Option<Box<NodeView>>,
+    // why the daemon could not be queried the last time
+    error: Option<String>,
+    form: Option<Form>,
+    status: Option<Result<String, String>>,
+}
+
+fn main() -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("The dashboard must be run in a terminal"));
+    }
+
+    let mut dash = Dashboard {
+        daemon_addr: cli::daemon_addr(),
+        view: None,
+        error: None,
+        form: None,
+        status: None,
+    };
+
+    // keys are read only once the terminal is in raw mode
+    let mut screen = Screen::enter()?;
+
+    let (tx, rx) = mpsc::channel();
+    spawn_key_thread(tx.clone());
+    spawn_poll_thread(dash.daemon_addr, tx.clone());
+
+    loop {
+        screen.0.draw(|frame| ui::render(frame, &dash))?;
+
+        // the screen is also redrawn periodically, for the ages of the blocks
+        let update = match rx.recv_timeout(REFRESH_INTERVAL) {
+            Ok(update) => update,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        match update {
+            Update::View(Ok(view)) => {
+                dash.view = Some(view);
+                dash.error = None;
+            }
+            Update::View(Err(e)) => dash.error = Some(e),
+            Update::Resize => {}
+            Update::Submitted(status) => dash.status = Some(status),
+            Update::Key(key) => {
+                if let Some(form) = &mut dash.form {
+                    match form.handle_key(key) {
+                        FormAction::None => {}
+                        FormAction::Cancel => dash.form = None,
+                        FormAction::Submit(cmd) => {
+                            dash.form = None;
+                            dash.status = Some(Ok("Sending the transaction...".to_string()));
+                            spawn_submit_thread(cmd, dash.daemon_addr, tx.clone());
+                        }
+                    }
+                    continue;
+                }
+
+                let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char('c' | 'd') if ctrl => break,
+                    KeyCode::Char('t') => dash.form = Some(Form::new(FormKind::Transfer)),
+                    KeyCode::Char('m') => dash.form = Some(Form::new(FormKind::Message)),
+                    KeyCode::Char('s') => dash.form = Some(Form::new(FormKind::Stake)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn spawn_key_thread(tx: Sender<Update>) {
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            let update = match event {
+                Event::Key(key) if key.kind != KeyEventKind::Release => Update::Key(key),
+                Event::Resize(..) => Update::Resize,
+                _ => continue,
+            };
+
+            if tx.send(update).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_poll_thread(daemon_addr: SocketAddr, tx: Sender<Update>) {
+    thread::spawn(move || loop {
+        let view = NodeView::fetch(daemon_addr)
+            .map(Box::new)
+            .map_err(|e| e.to_string());
+        if tx.send(Update::View(view)).is_err() {
+            break;
+        }
+
+        thread::sleep(REFRESH_INTERVAL);
+    });
+}
+
+fn spawn_submit_thread(cmd: cli::Command, daemon_addr: SocketAddr, tx: Sender<Update>) {
+    thread::spawn(move || {
+        let status = match view::request(cmd, daemon_addr) {
+            Ok(Response::Submitted { hash }) => Ok(format!("Transaction submitted: {}", hash)),
+            Ok(res) => Err(format!("Unexpected response: {}", res)),
+            Err(e) => Err(format!("Failed to send the transaction: {}", e)),
+        };
+
+        let _ = tx.send(Update::Submitted(status));
+    });
+}
+
+// the terminal in raw mode, showing the alternate screen until dropped
+struct Screen(DefaultTerminal);
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        // the terminal is also restored before a panic is reported
+        ratatui::try_init().map(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{form::Form, view::NodeView, Dashboard};
+use block_chat::response::Liveness;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize as _},
+    text::Line,
+    widgets::{Block, Cell, Paragraph, Row, Table},
+    Frame,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+    The whole screen is drawn from the last view of the node on every update (ratatui only
+    writes what changed since the previous frame). From top to bottom: the title, the chain,
+    the accounts, the latest blocks, the mempool and the footer, which is the form while
+    a transaction is being filled in.
+*/
+
+// how many characters of a hash are shown
+const HASH_LEN: usize = 16;
+
+// the width of the labels of the form fields
+const LABEL_WIDTH: u16 = 15;
+
+pub fn render(frame: &mut Frame, dash: &Dashboard) {
+    let footer_height = match &dash.form {
+        // the fields, the error and the keys, within the borders
+        Some(form) => form.fields.len() as u16 + 4,
+        None => 2,
+    };
+    let [title_area, body_area, footer_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(footer_height),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(title(dash), title_area);
+
+    match &dash.view {
+        Some(view) => body(frame, view, body_area),
+        None => frame.render_widget(
+            Paragraph::new(format!(
+                "Connecting to the daemon at {}...",
+                dash.daemon_addr
+            )),
+            body_area,
+        ),
+    }
+
+    match &dash.form {
+        Some(form) => form_footer(frame, form, footer_area),
+        None => frame.render_widget(footer(dash), footer_area),
+    }
+}
+
+fn body(frame: &mut Frame, view: &NodeView, area: Rect) {
+    // every table has a border and a header
+    let [chain_area, accounts_area, blocks_area, mempool_area] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Length(view.peers.len() as u16 + 3),
+        Constraint::Max(view.blocks.len() as u16 + 3),
+        Constraint::Min(3),
+    ])
+    .areas(area);
+
+    frame.render_widget(chain(view), chain_area);
+    frame.render_widget(accounts(view), accounts_area);
+    frame.render_widget(blocks(view), blocks_area);
+    frame.render_widget(mempool(view, mempool_area.height), mempool_area);
+}
+
+fn chain(view: &NodeView) -> Paragraph<'static> {
+    let chain = &view.chain;
+    let network = &view.network;
+
+    Paragraph::new(vec![
+        Line::from(format!(
+            "Chain      height {} | tip {} | oldest retained block {}",
+            chain.height,
+            short(&chain.tip),
+            chain.oldest_retained
+        )),
+        Line::from(format!(
+            "Validator  last block by {} | next block by {}",
+            view.current_validator()
+                .map_or("genesis".to_string(), |id| id.to_string()),
+            chain.next_validator
+        )),
+        Line::from(format!(
+            "Supply     {} BCC | {} BCC staked",
+            chain.total_supply, chain.total_staked
+        )),
+        Line::from(format!(
+            "Network    {} of {} peers connected | {} pending transactions",
+            network.connected_peers, network.other_peers, network.mempool
+        )),
+    ])
+    .block(Block::bordered().title("Node".bold()))
+}
+
+fn accounts(view: &NodeView) -> Table<'static> {
+    let rows = view.peers.iter().map(|peer| {
+        let (status, style) = match (peer.liveness, peer.since_secs) {
+            (Liveness::Local, _) => ("local".to_string(), Style::new().bold()),
+            (Liveness::Connected, secs) => (with_secs("connected", secs), Style::new().green()),
+            (Liveness::Disconnected, secs) => (with_secs("disconnected", secs), Style::new().red()),
+            (Liveness::Unknown, _) => ("never connected".to_string(), Style::new().dim()),
+        };
+
+        let mut roles = vec![];
+        if view.current_validator() == Some(peer.id) {
+            roles.push("last validator");
+        }
+        if view.chain.next_validator == peer.id {
+            roles.push("next validator");
+        }
+
+        Row::new([
+            Cell::from(peer.id.to_string()),
+            Cell::from(peer.address.to_string()),
+            Cell::from(Line::from(format!("{} BCC", peer.balance)).right_aligned()),
+            Cell::from(Line::from(format!("{} BCC", peer.stake)).right_aligned()),
+            Cell::from(status),
+            Cell::from(roles.join(", ")),
+        ])
+        .style(style)
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(4),
+            Constraint::Length(21),
+            Constraint::Length(14),
+            Constraint::Length(12),
+            Constraint::Length(26),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header([
+        "id", "address", "balance", "stake", "status", "role",
+    ]))
+    .block(Block::bordered().title("Accounts".bold()))
+}
+
+fn blocks(view: &NodeView) -> Table<'static> {
+    // the newest blocks first (the table shows as many as fit)
+    let rows = view.blocks.iter().rev().map(|blk| {
+        Row::new([
+            Cell::from(blk.index.to_string()),
+            Cell::from(short(&blk.hash).to_string()),
+            Cell::from(
+                blk.validator
+                    .map_or("genesis".to_string(), |id| id.to_string()),
+            ),
+            Cell::from(Line::from(blk.transactions.len().to_string()).right_aligned()),
+            Cell::from(age(blk.timestamp)),
+        ])
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Length(HASH_LEN as u16),
+            Constraint::Length(10),
+            Constraint::Length(5),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header(["block", "hash", "validator", "txs", "age"]))
+    .block(Block::bordered().title("Recent blocks".bold()))
+}
+
+fn mempool(view: &NodeView, height: u16) -> Paragraph<'static> {
+    let title = format!("Mempool ({} pending)", view.mempool.len());
+    let room = height.saturating_sub(2) as usize;
+
+    let mut lines = vec![];
+    if view.mempool.is_empty() {
+        lines.push(Line::from("No pending transactions").dim());
+    }
+
+    for (i, tsx) in view.mempool.iter().enumerate() {
+        // keep the last row for the count of the transactions that do not fit
+        if i + 1 == room && view.mempool.len() > room {
+            lines.push(Line::from(format!("... and {} more", view.mempool.len() - i)).dim());
+            break;
+        }
+
+        lines.push(Line::from(tsx.to_string()));
+    }
+
+    Paragraph::new(lines).block(Block::bordered().title(title.bold()))
+}
+
+fn header<const N: usize>(labels: [&'static str; N]) -> Row<'static> {
+    Row::new(labels).style(Style::new().dim())
+}
+
+fn title(dash: &Dashboard) -> Line<'static> {
+    let title = match &dash.view {
+        Some(view) => format!(
+            " BlockChat dashboard | peer {} at {} | account {}",
+            view.me.id, view.me.address, view.me.account
+        ),
+        None => format!(" BlockChat dashboard | {}", dash.daemon_addr),
+    };
+
+    Line::from(title).reversed()
+}
+
+fn footer(dash: &Dashboard) -> Paragraph<'static> {
+    // the outcome of the last submission, or why the daemon cannot be reached
+    let status = match (&dash.error, &dash.status) {
+        (Some(e), _) => Line::from(format!("Daemon unreachable: {}", e)).red(),
+        (None, Some(Ok(status))) => Line::from(status.clone()).green(),
+        (None, Some(Err(e))) => Line::from(e.clone()).red(),
+        (None, None) => Line::default(),
+    };
+
+    Paragraph::new(vec![
+        status,
+        Line::from(" t transfer | m message | s stake | q quit").reversed(),
+    ])
+}
+
+fn form_footer(frame: &mut Frame, form: &Form, area: Rect) {
+    let mut lines: Vec<_> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let line = Line::from(format!(
+                "{:<width$}{}",
+                field.label,
+                field.value,
+                width = LABEL_WIDTH as usize
+            ));
+
+            if i == form.focus {
+                line.bold()
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    lines.push(match &form.error {
+        Some(e) => Line::from(e.clone()).red(),
+        None => Line::default(),
+    });
+    lines.push(Line::from(" Enter send | Tab next field | Space toggle | Esc cancel").reversed());
+
+    let block = Block::bordered().title(form.title().bold());
+    let inner = block.inner(area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+
+    // the cursor is left at the end of the focused field
+    let value = &form.fields[form.focus].value;
+    frame.set_cursor_position((
+        inner.x + LABEL_WIDTH + value.chars().count() as u16,
+        inner.y + form.focus as u16,
+    ));
+}
+
+fn short(hash: &str) -> &str {
+    &hash[..HASH_LEN.min(hash.len())]
+}
+
+fn with_secs(status: &str, secs: Option<u64>) -> String {
+    match secs {
+        Some(secs) => format!("{} for {}s", status, secs),
+        None => status.to_string(),
+    }
+}
+
+// how long ago a block was created, given its timestamp in ms since the UNIX epoch
+fn age(timestamp: u128) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let secs = now.saturating_sub(timestamp) / 1000;
+
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
@@ -0,0 +1,85 @@
+use block_chat::{
+    cli::{BlockRange, Command},
+    response::{ChainInfo, NetworkInfo, PeerIdentity, PeerSummary, Response},
+    rpc::{BlockInfo, TransactionSummary},
+};
+use serde::Deserialize as _;
+use std::{
+    io::{self, BufReader},
+    net::SocketAddr,
+};
+
+// how many of the latest blocks are fetched
+pub const RECENT_BLOCKS: u32 = 10;
+
+// everything the dashboard shows about the node
+pub struct NodeView {
+    pub me: PeerIdentity,
+    pub chain: ChainInfo,
+    pub network: NetworkInfo,
+    pub peers: Vec<PeerSummary>,
+    pub blocks: Vec<BlockInfo>, // oldest first
+    pub mempool: Vec<TransactionSummary>,
+}
+
+impl NodeView {
+    // query the daemon with the same commands the client sends
+    pub fn fetch(addr: SocketAddr) -> io::Result<Self> {
+        let Response::Whoami(me) = request(Command::Whoami, addr)? else {
+            return Err(unexpected());
+        };
+        let Response::ChainInfo(chain) = request(Command::ChainInfo, addr)? else {
+            return Err(unexpected());
+        };
+        let Response::Network(network) = request(Command::Network, addr)? else {
+            return Err(unexpected());
+        };
+        let Response::Peers(peers) = request(Command::Peers, addr)? else {
+            return Err(unexpected());
+        };
+        let Response::Mempool(mempool) = request(Command::Mempool, addr)? else {
+            return Err(unexpected());
+        };
+
+        // older blocks may have been pruned
+        let range = BlockRange {
+            from: chain
+                .height
+                .saturating_sub(RECENT_BLOCKS - 1)
+                .max(chain.oldest_retained),
+            to: None,
+        };
+        let Response::Blocks(blocks) = request(Command::Blocks { range }, addr)? else {
+            return Err(unexpected());
+        };
+
+        Ok(Self {
+            me,
+            chain,
+            network,
+            peers,
+            blocks,
+            mempool,
+        })
+    }
+
+    // the validator of the last block
+    pub fn current_validator(&self) -> Option<u32> {
+        self.blocks.last().and_then(|blk| blk.validator)
+    }
+}
+
+// send a command and return its (only) response, with errors of the daemon as `io::Error`s
+pub fn request(cmd: Command, addr: SocketAddr) -> io::Result<Response> {
+    let stream = cmd.send(addr)?;
+    let mut de = serde_json::Deserializer::from_reader(BufReader::new(stream));
+
+    match Response::deserialize(&mut de)? {
+        Response::Error { message, .. } => Err(io::Error::other(message)),
+        response => Ok(response),
+    }
+}
+
+fn unexpected() -> io::Error {
+    io::Error::other("Unexpected response from the daemon")
+}
@@ -1,3 +1,4 @@
+use block_chat::response::Response;
 use std::{
     env,
     error::Error,
@@ -34,9 +35,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         env::var(INPUT_FOLDER_ENV).unwrap_or_else(|_| panic!("{} not set", INPUT_FOLDER_ENV));
 
     let id = loop {
-        let id_cmd = block_chat::cli::Command::Id;
+        let id_cmd = block_chat::cli::Command::Whoami;
         match send_cmd(id_cmd, daemon_addr) {
-            Ok(res) => break String::from_utf8(res)?,
+            Ok(res) => match serde_json::from_slice(&res)? {
+                Response::Whoami(me) => break me.id,
+                res => return Err(format!("Unexpected response to `whoami`: {}", res).into()),
+            },
             Err(_) => thread::sleep(Duration::from_secs(1)),
         }
     };
@@ -44,7 +48,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     let filename = format!("{}/trans{}.txt", input_folder, id);
     let file = fs::File::open(&filename)?;
 
-    let stake_cmd = block_chat::cli::Command::S { amt: fixed_staking };
+    let stake_cmd = block_chat::cli::Command::S {
+        amt: fixed_staking,
+        wait: Default::default(),
+    };
     send_cmd(stake_cmd, daemon_addr)?;
 
     println!("Helper starting; reading from {}", filename);
@@ -60,7 +67,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             .map(|s| s.to_string())
             .collect::<Vec<_>>();
 
-        let cmd = block_chat::cli::Command::M { rcp_id, msg };
+        let cmd = block_chat::cli::Command::M {
+            rcp: block_chat::cli::Recipient::Id(rcp_id),
+            msg,
+            encrypted: false,
+            wait: Default::default(),
+        };
         send_cmd(cmd, daemon_addr)?;
 
         count += 1;
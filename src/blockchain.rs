@@ -1,34 +1,157 @@
 pub mod block;
 pub mod transaction;
 
-use self::block::Block;
+use self::block::{Block, BlockHeader};
+use crate::snapshot::Snapshot;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/*
+    The Blockchain struct keeps the blocks of the chain in order.
+
+    Long-running nodes can prune old blocks: the body (transactions) of a pruned block
+    is dropped and only its header is kept. The blocks that have not been pruned
+    are the retention window of the blockchain, and only those can be served to peers.
+*/
+
+#[derive(Error, Debug)]
+pub enum BlockchainError {
+    #[error("Block {index} has been pruned (the oldest retained block is {oldest_retained})")]
+    Pruned { index: u32, oldest_retained: u32 },
+    #[error("Block {index} does not exist yet (the last block is {last})")]
+    NotFound { index: u32, last: u32 },
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Blockchain {
-    blocks: Vec<Block>,
+    // the headers of the blocks whose bodies have been pruned
+    pruned: Vec<BlockHeader>,
+    blocks: VecDeque<Block>,
 }
 
 impl Blockchain {
     pub fn new(gen_blk: Block) -> Self {
         Self {
-            blocks: vec![gen_blk],
+            pruned: vec![],
+            blocks: VecDeque::from([gen_blk]),
         }
     }
 
+    // start the blockchain from the block of a (verified) snapshot
+    // instead of the genesis block
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        Self::new(snapshot.block().clone())
+    }
+
+    // the number of blocks, including the pruned ones
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
-        self.blocks.len()
+        self.pruned.len() + self.blocks.len()
     }
 
     pub fn add_block(&mut self, mut blk: Block) {
-        blk.index = self.blocks.len() as u32;
-        self.blocks.push(blk);
+        // the first block is not necessarily the genesis block
+        blk.index = self.last_block().index() + 1;
+        self.blocks.push_back(blk);
+    }
+
+    // drop the bodies of all blocks before the given index
+    // the last block is never pruned
+    pub fn prune(&mut self, index: u32) {
+        while self.blocks.len() > 1 && self.oldest_retained() < index {
+            let blk = self.blocks.pop_front().unwrap();
+            self.pruned.push(blk.header());
+        }
+    }
+
+    // the index of the oldest block whose body has not been pruned
+    pub fn oldest_retained(&self) -> u32 {
+        self.blocks.front().unwrap().index()
+    }
+
+    pub fn get_block(&self, index: u32) -> Result<&Block, BlockchainError> {
+        let oldest_retained = self.oldest_retained();
+
+        if index < oldest_retained {
+            return Err(BlockchainError::Pruned {
+                index,
+                oldest_retained,
+            });
+        }
+
+        self.blocks
+            .get((index - oldest_retained) as usize)
+            .ok_or(BlockchainError::NotFound {
+                index,
+                last: self.last_block().index(),
+            })
+    }
+
+    // headers are available for pruned blocks as well
+    pub fn get_header(&self, index: u32) -> Option<BlockHeader> {
+        match self.get_block(index) {
+            Ok(blk) => Some(blk.header()),
+            Err(BlockchainError::Pruned { .. }) => {
+                let first = self.pruned.first()?.index;
+                self.pruned.get(index.checked_sub(first)? as usize).cloned()
+            }
+            Err(BlockchainError::NotFound { .. }) => None,
+        }
+    }
+
+    // the index of the block with the given hash, even if it has been pruned
+    pub fn find_index(&self, hash: &[u8; 32]) -> Option<u32> {
+        self.blocks
+            .iter()
+            .map(|blk| (blk.hash(), blk.index()))
+            .chain(self.pruned.iter().map(|hdr| (&hdr.hash, hdr.index)))
+            .find(|(h, _)| *h == hash)
+            .map(|(_, index)| index)
+    }
+
+    // the blocks that come after the block with the given index
+    // fails if any of them has been pruned
+    pub fn blocks_after(
+        &self,
+        index: u32,
+    ) -> Result<impl Iterator<Item = &Block>, BlockchainError> {
+        let oldest_retained = self.oldest_retained();
+
+        // the index comes from peers, and no block can come after the last possible one
+        let Some(next) = index.checked_add(1) else {
+            return Ok(self.blocks.iter().skip(self.blocks.len()));
+        };
+
+        if next < oldest_retained {
+            return Err(BlockchainError::Pruned {
+                index: next,
+                oldest_retained,
+            });
+        }
+
+        let skip = (next - oldest_retained) as usize;
+        Ok(self.blocks.iter().skip(skip))
     }
 
     // the blockchain will always have at least one block
     // so this helps avoid unwrapping every time we need the last block
     pub fn last_block(&self) -> &Block {
-        self.blocks.last().unwrap()
+        self.blocks.back().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_after() {
+        let mut blockchain = Blockchain::new(Block::new_genesis(vec![], 0, [0; 32]));
+        blockchain.add_block(Block::new_genesis(vec![], 1, [0; 32]));
+
+        assert_eq!(blockchain.blocks_after(0).unwrap().count(), 1);
+        assert_eq!(blockchain.blocks_after(1).unwrap().count(), 0);
+        assert_eq!(blockchain.blocks_after(u32::MAX).unwrap().count(), 0);
     }
 }
@@ -12,8 +12,6 @@ use std::{
     time::SystemTime,
 };
 
-pub const BLOCK_CAPACITY: usize = 5;
-
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Block {
     pub(super) index: u32,
@@ -24,20 +22,42 @@ pub struct Block {
     val: Option<PublicKey>,
     #[serde(rename = "previous_hash")]
     prev_hash: [u8; 32],
+    // the state root of the accounts after processing the block
+    state_root: [u8; 32],
     hash: [u8; 32],
 }
 
+// everything in a block except its transactions
+// kept around after the body of a block has been pruned
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockHeader {
+    pub index: u32,
+    pub timestamp: u128,
+    #[serde(rename = "validator")]
+    pub val: Option<PublicKey>,
+    #[serde(rename = "previous_hash")]
+    pub prev_hash: [u8; 32],
+    pub state_root: [u8; 32],
+    pub hash: [u8; 32],
+}
+
 impl Block {
-    pub fn new(tsxs: [Transaction; BLOCK_CAPACITY], val: PublicKey, prev_hash: [u8; 32]) -> Self {
+    pub fn new(
+        tsxs: Vec<Transaction>,
+        val: PublicKey,
+        prev_hash: [u8; 32],
+        state_root: [u8; 32],
+    ) -> Self {
         let mut blk = Self {
             index: 0,
             timestamp: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .expect("Time went backwards")
                 .as_millis(),
-            tsxs: tsxs.to_vec(),
+            tsxs,
             val: Some(val),
             prev_hash,
+            state_root,
             hash: [0; 32],
         };
 
@@ -46,16 +66,15 @@ impl Block {
         blk
     }
 
-    pub fn new_genesis(gen_tsxs: Vec<Transaction>) -> Self {
+    // the timestamp is given explicitly so that the genesis block can be deterministic
+    pub fn new_genesis(gen_tsxs: Vec<Transaction>, timestamp: u128, state_root: [u8; 32]) -> Self {
         let mut blk = Self {
             index: 0,
-            timestamp: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_millis(),
+            timestamp,
             tsxs: gen_tsxs,
             val: None,
             prev_hash: [0; 32],
+            state_root,
             hash: [0; 32],
         };
 
@@ -78,10 +97,22 @@ impl Block {
         }
 
         hasher.update(self.prev_hash());
+        hasher.update(self.state_root());
 
         hasher.finalize().into()
     }
 
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            val: self.val.clone(),
+            prev_hash: self.prev_hash,
+            state_root: self.state_root,
+            hash: self.hash,
+        }
+    }
+
     // getters
 
     pub fn index(&self) -> u32 {
@@ -104,6 +135,10 @@ impl Block {
         &self.prev_hash
     }
 
+    pub fn state_root(&self) -> &[u8; 32] {
+        &self.state_root
+    }
+
     pub fn hash(&self) -> &[u8; 32] {
         &self.hash
     }
@@ -120,6 +155,10 @@ impl Debug for Block {
                 "prev_hash",
                 &format_args!("{}", &self.prev_hash.encode_hex::<String>()),
             )
+            .field(
+                "state_root",
+                &format_args!("{}", &self.state_root.encode_hex::<String>()),
+            )
             .field(
                 "hash",
                 &format_args!("{}", &self.hash.encode_hex::<String>()),
@@ -1,26 +1,21 @@
-use super::{Block, BLOCK_CAPACITY};
+use super::Block;
 use crate::{
     account::AccountsCatalog,
     blockchain::{
         transaction::{self, TransactionValidator},
         Blockchain,
     },
+    genesis::chain_params,
 };
 use std::cmp::Ordering;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ValidateStructureError {
-    #[error(
-        "The block has {0} transactions less than the required {}",
-        BLOCK_CAPACITY
-    )]
-    PartiallyFilledBlock(usize),
-    #[error(
-        "The block has {0} transactions more than the required {}",
-        BLOCK_CAPACITY
-    )]
-    OverfilledBlock(usize),
+    #[error("The block has {0} transactions less than the required {1}")]
+    PartiallyFilledBlock(usize, usize),
+    #[error("The block has {0} transactions more than the required {1}")]
+    OverfilledBlock(usize, usize),
     #[error("The validator should be `Some` but is `None`")]
     MissingValidator,
     #[error("The timestamp contains a future date")]
@@ -49,6 +44,10 @@ pub enum ValidateSemanticsError {
     },
     #[error("The previous hash does not match the blockchain's last block's hash")]
     InvalidPreviousHash,
+    #[error("The transactions cannot be applied to the accounts catalog (account {account_id})")]
+    UnprocessableTransactions { account_id: u32 },
+    #[error("The state root does not match the locally computed one")]
+    InvalidStateRoot,
 }
 
 pub struct BlockValidator;
@@ -58,10 +57,11 @@ impl BlockValidator {
     pub fn validate_structure(blk: &Block) -> Result<(), ValidateStructureError> {
         use ValidateStructureError::*;
 
-        let diff = blk.tsxs().len().abs_diff(BLOCK_CAPACITY);
-        match blk.tsxs().len().cmp(&BLOCK_CAPACITY) {
-            Ordering::Less => return Err(PartiallyFilledBlock(diff)),
-            Ordering::Greater => return Err(OverfilledBlock(diff)),
+        let capacity = chain_params().block_capacity;
+        let diff = blk.tsxs().len().abs_diff(capacity);
+        match blk.tsxs().len().cmp(&capacity) {
+            Ordering::Less => return Err(PartiallyFilledBlock(diff, capacity)),
+            Ordering::Greater => return Err(OverfilledBlock(diff, capacity)),
             Ordering::Equal => (),
         }
 
@@ -112,6 +112,18 @@ impl BlockValidator {
             return Err(InvalidPreviousHash);
         }
 
+        // execute the block locally and compare the resulting state root
+        let mut post_accounts = ctx.0.clone();
+        if let Err(e) = post_accounts.process_block(blk) {
+            return Err(UnprocessableTransactions {
+                account_id: e.account_id,
+            });
+        }
+
+        if post_accounts.calculate_state_root() != *blk.state_root() {
+            return Err(InvalidStateRoot);
+        }
+
         Ok(())
     }
 }
@@ -1,14 +1,13 @@
+mod encrypted_message;
 mod transaction_validator;
 
+pub use encrypted_message::EncryptedMessage;
 pub use transaction_validator::{
     TransactionValidator, ValidateSemanticsError, ValidateStructureError,
 };
 
 use crate::crypto::{PrivateKey, PublicKey};
-use crate::protocol::{
-    CENTS_PER_COIN, MESSAGE_FEE_PER_CHARACTER_CENTS, MINIMUM_TRANSFER_FEE_CENTS,
-    TRANSFER_FEE_PERCENTAGE,
-};
+use crate::genesis::chain_params;
 use hex::ToHex;
 use non_empty_string::NonEmptyString;
 use rsa::sha2::{Digest as _, Sha256};
@@ -18,11 +17,40 @@ use std::{
     num::NonZeroU32,
 };
 
+// how many recipients a multi-transfer has at most
+pub const MAX_TRANSFER_OUTPUTS: usize = 32;
+
 #[derive(Clone, Deserialize, Serialize)]
 pub enum TransactionPayload {
     Transfer(NonZeroU32),
     Message(NonEmptyString),
     Stake(NonZeroU32),
+    // a message only the recipient can read
+    EncryptedMessage(EncryptedMessage),
+    // a transfer to several recipients at once (the transaction itself has no recipient)
+    MultiTransfer(Vec<TransferOutput>),
+}
+
+// a recipient of a multi-transfer, along with what it receives
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TransferOutput {
+    #[serde(rename = "recipient_address")]
+    recp_addr: PublicKey,
+    amnt: NonZeroU32,
+}
+
+impl TransferOutput {
+    pub fn new(recp_addr: PublicKey, amnt: NonZeroU32) -> Self {
+        Self { recp_addr, amnt }
+    }
+
+    pub fn recp_addr(&self) -> &PublicKey {
+        &self.recp_addr
+    }
+
+    pub fn amnt(&self) -> NonZeroU32 {
+        self.amnt
+    }
 }
 
 impl TransactionPayload {
@@ -30,7 +58,7 @@ impl TransactionPayload {
         match self {
             Self::Stake(coins) => Some(coins.get()),
             Self::Transfer(coins) => Some(coins.get()),
-            Self::Message(_) => None,
+            Self::Message(_) | Self::EncryptedMessage(_) | Self::MultiTransfer(_) => None,
         }
     }
 
@@ -48,17 +76,31 @@ impl Debug for TransactionPayload {
         match self {
             Self::Transfer(amnt) => f
                 .debug_tuple("Transfer")
-                .field(&(amnt.get() as f64 / CENTS_PER_COIN as f64))
+                .field(&(amnt.get() as f64 / chain_params().cents_per_coin as f64))
                 .finish(),
             Self::Message(msg) => f.debug_tuple("Message").field(msg).finish(),
+            Self::EncryptedMessage(msg) => f
+                .debug_tuple("EncryptedMessage")
+                .field(&format_args!("<{} bytes>", msg.len()))
+                .finish(),
             Self::Stake(amnt) => f
                 .debug_tuple("Stake")
-                .field(&(amnt.get() as f64 / CENTS_PER_COIN as f64))
+                .field(&(amnt.get() as f64 / chain_params().cents_per_coin as f64))
                 .finish(),
+            Self::MultiTransfer(outputs) => f.debug_tuple("MultiTransfer").field(&outputs).finish(),
         }
     }
 }
 
+impl Debug for TransferOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TransferOutput")
+            .field(&self.recp_addr)
+            .field(&(self.amnt.get() as f64 / chain_params().cents_per_coin as f64))
+            .finish()
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Transaction {
     payload: TransactionPayload,
@@ -83,6 +125,17 @@ impl Transaction {
         )
     }
 
+    // the only kind of stake transaction with a recipient instead of a sender
+    pub fn new_genesis_stake(recp_addr: PublicKey, amnt: NonZeroU32) -> Self {
+        Self::new(
+            TransactionPayload::Stake(amnt),
+            None,
+            Some(recp_addr),
+            0,
+            None,
+        )
+    }
+
     pub fn new_transfer(
         sndr_addr: PublicKey,
         recp_addr: PublicKey,
@@ -115,6 +168,37 @@ impl Transaction {
         )
     }
 
+    pub fn new_encrypted_message(
+        sndr_addr: PublicKey,
+        recp_addr: PublicKey,
+        msg: EncryptedMessage,
+        nonce: u64,
+        priv_key: &PrivateKey,
+    ) -> Self {
+        Self::new(
+            TransactionPayload::EncryptedMessage(msg),
+            Some(sndr_addr),
+            Some(recp_addr),
+            nonce,
+            Some(priv_key),
+        )
+    }
+
+    pub fn new_multi_transfer(
+        sndr_addr: PublicKey,
+        outputs: Vec<TransferOutput>,
+        nonce: u64,
+        priv_key: &PrivateKey,
+    ) -> Self {
+        Self::new(
+            TransactionPayload::MultiTransfer(outputs),
+            Some(sndr_addr),
+            None,
+            nonce,
+            Some(priv_key),
+        )
+    }
+
     pub fn new_stake(
         sndr_addr: PublicKey,
         amnt: NonZeroU32,
@@ -135,6 +219,12 @@ impl Transaction {
             TransactionPayload::Transfer(amnt) => Self::calculate_transfer_fees(*amnt),
             TransactionPayload::Message(msg) => Self::calculate_message_fees(msg),
             TransactionPayload::Stake(amnt) => Self::calculcate_stake_fees(*amnt),
+            TransactionPayload::EncryptedMessage(msg) => {
+                Self::calculate_encrypted_message_fees(msg)
+            }
+            TransactionPayload::MultiTransfer(outputs) => {
+                Self::calculate_multi_transfer_fees(outputs)
+            }
         }
     }
 
@@ -144,12 +234,29 @@ impl Transaction {
             TransactionPayload::Transfer(amnt) => Self::calculate_transfer_total_cost(*amnt),
             TransactionPayload::Message(msg) => Self::calculate_message_total_cost(msg),
             TransactionPayload::Stake(amnt) => Self::calculate_stake_total_cost(*amnt),
+            TransactionPayload::EncryptedMessage(msg) => {
+                Self::calculate_encrypted_message_total_cost(msg)
+            }
+            TransactionPayload::MultiTransfer(outputs) => {
+                Self::calculate_multi_transfer_total_cost(outputs)
+            }
         }
     }
 
     pub fn calculate_hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
 
+        // the variant is hashed first, so that the fields of one cannot pass for those of another
+        // (e.g. a transfer and a multi-transfer with a single output)
+        let variant: u8 = match self.payload() {
+            TransactionPayload::Transfer(_) => 0,
+            TransactionPayload::Message(_) => 1,
+            TransactionPayload::Stake(_) => 2,
+            TransactionPayload::EncryptedMessage(_) => 3,
+            TransactionPayload::MultiTransfer(_) => 4,
+        };
+        hasher.update([variant]);
+
         if let Some(c) = self.payload().coins() {
             hasher.update(c.to_be_bytes())
         }
@@ -158,6 +265,23 @@ impl Transaction {
             hasher.update(m.as_bytes())
         }
 
+        // the fields are length-prefixed, so that bytes cannot be moved from one to the next
+        // (which would change the fees of the message)
+        if let TransactionPayload::EncryptedMessage(m) = self.payload() {
+            for field in [m.wrapped_key(), m.nonce(), m.ciphertext()] {
+                hasher.update((field.len() as u32).to_be_bytes());
+                hasher.update(field);
+            }
+        }
+
+        if let TransactionPayload::MultiTransfer(outputs) = self.payload() {
+            hasher.update((outputs.len() as u32).to_be_bytes());
+            for out in outputs {
+                hasher.update(out.amnt().get().to_be_bytes());
+                hasher.update(out.recp_addr().to_der());
+            }
+        }
+
         if let Some(a) = self.recp_addr() {
             hasher.update(a.to_der());
         }
@@ -171,30 +295,64 @@ impl Transaction {
         hasher.finalize().into()
     }
 
+    // saturates, so that an amount too large to pay for never looks affordable
     pub fn calculate_transfer_fees(amnt: NonZeroU32) -> u32 {
-        let fee = amnt.get() * TRANSFER_FEE_PERCENTAGE / 100;
+        Self::checked_transfer_fees(amnt).unwrap_or(u32::MAX)
+    }
 
-        if fee < MINIMUM_TRANSFER_FEE_CENTS {
-            MINIMUM_TRANSFER_FEE_CENTS
-        } else {
-            fee
-        }
+    // None if the fee does not fit in a u32
+    pub fn checked_transfer_fees(amnt: NonZeroU32) -> Option<u32> {
+        let params = chain_params();
+        let fee = u64::from(amnt.get()) * u64::from(params.transfer_fee_percentage) / 100;
+        let fee = u32::try_from(fee).ok()?;
+
+        Some(fee.max(params.minimum_transfer_fee_cents))
     }
 
+    // saturates, like the transfer fees, so that a message too long to pay for never looks affordable
     pub fn calculate_message_fees(msg: &NonEmptyString) -> u32 {
-        msg.len() as u32 * MESSAGE_FEE_PER_CHARACTER_CENTS
+        (msg.len() as u32).saturating_mul(chain_params().message_fee_per_character_cents)
+    }
+
+    // encrypted messages are charged per byte of ciphertext instead of per character
+    pub fn calculate_encrypted_message_fees(msg: &EncryptedMessage) -> u32 {
+        (msg.len() as u32).saturating_mul(chain_params().message_fee_per_character_cents)
+    }
+
+    // every output pays the fee of a transfer of its amount
+    // (the sum saturates, so that a multi-transfer too large to pay for never looks affordable)
+    pub fn calculate_multi_transfer_fees(outputs: &[TransferOutput]) -> u32 {
+        outputs.iter().fold(0, |fees, out| {
+            fees.saturating_add(Self::calculate_transfer_fees(out.amnt()))
+        })
     }
 
     pub fn calculcate_stake_fees(_amnt: NonZeroU32) -> u32 {
         0
     }
 
+    // saturates like `calculate_transfer_fees`
     pub fn calculate_transfer_total_cost(amnt: NonZeroU32) -> u32 {
-        amnt.get() + Self::calculate_transfer_fees(amnt)
+        Self::checked_transfer_total_cost(amnt).unwrap_or(u32::MAX)
+    }
+
+    // None if the amount and its fee do not fit in a u32
+    pub fn checked_transfer_total_cost(amnt: NonZeroU32) -> Option<u32> {
+        amnt.get().checked_add(Self::checked_transfer_fees(amnt)?)
     }
 
     pub fn calculate_message_total_cost(msg: &NonEmptyString) -> u32 {
-        msg.len() as u32 + Self::calculate_message_fees(msg)
+        (msg.len() as u32).saturating_add(Self::calculate_message_fees(msg))
+    }
+
+    pub fn calculate_encrypted_message_total_cost(msg: &EncryptedMessage) -> u32 {
+        (msg.len() as u32).saturating_add(Self::calculate_encrypted_message_fees(msg))
+    }
+
+    pub fn calculate_multi_transfer_total_cost(outputs: &[TransferOutput]) -> u32 {
+        outputs.iter().fold(0, |cost, out| {
+            cost.saturating_add(Self::calculate_transfer_total_cost(out.amnt()))
+        })
     }
 
     pub fn calculate_stake_total_cost(amnt: NonZeroU32) -> u32 {
@@ -267,3 +425,65 @@ impl Debug for Transaction {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPrivateKey;
+
+    #[test]
+    fn test_hash_encrypted_message_fields() {
+        // RSA-OAEP with SHA-256 needs at least 1024-bit keys to wrap a 32-byte key
+        let sndr = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap());
+        let recp = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap());
+
+        let msg = NonEmptyString::new("hello there".to_string()).unwrap();
+        let tsx = Transaction::new_encrypted_message(
+            sndr.to_publ_key(),
+            recp.to_publ_key(),
+            EncryptedMessage::seal(&recp.to_publ_key(), &msg),
+            0,
+            &sndr,
+        );
+        TransactionValidator::validate_structure(&tsx).unwrap();
+
+        // move the last byte of the wrapped key to the front of the ciphertext
+        let mut json = serde_json::to_value(&tsx).unwrap();
+        let msg = &mut json["payload"]["EncryptedMessage"];
+        let byte = msg["wrapped_key"].as_array_mut().unwrap().pop().unwrap();
+        msg["ciphertext"].as_array_mut().unwrap().insert(0, byte);
+        let shifted: Transaction = serde_json::from_value(json).unwrap();
+
+        assert!(shifted.fees() > tsx.fees());
+        assert!(matches!(
+            TransactionValidator::validate_structure(&shifted),
+            Err(ValidateStructureError::InvalidHash)
+        ));
+    }
+
+    #[test]
+    fn test_hash_payload_variant() {
+        let sndr = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+        let recp = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+        let amnt = NonZeroU32::new(500).unwrap();
+
+        let tsx = Transaction::new_transfer(sndr.to_publ_key(), recp.to_publ_key(), amnt, 0, &sndr);
+        TransactionValidator::validate_structure(&tsx).unwrap();
+
+        // the same transfer as a multi-transfer with a single output, under the original signature
+        let forged = Transaction {
+            payload: TransactionPayload::MultiTransfer(vec![TransferOutput::new(
+                recp.to_publ_key(),
+                amnt,
+            )]),
+            recp_addr: None,
+            ..tsx.clone()
+        };
+
+        assert_ne!(forged.calculate_hash(), *tsx.hash());
+        assert!(matches!(
+            TransactionValidator::validate_structure(&forged),
+            Err(ValidateStructureError::InvalidHash)
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::crypto::{PrivateKey, PublicKey};
+use chacha20poly1305::{aead::Aead as _, ChaCha20Poly1305, Key, KeyInit as _, Nonce};
+use non_empty_string::NonEmptyString;
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+
+/*
+    An EncryptedMessage can only be read by its recipient.
+
+    The message is encrypted with a random ChaCha20-Poly1305 key, which is in turn
+    encrypted (wrapped) with the RSA public key of the recipient using RSA-OAEP.
+    Everyone else, validators included, only sees the length of the ciphertext,
+    which is also what the fees of the message are based on.
+*/
+
+// the length of the authentication tag appended to the ciphertext
+const TAG_LEN: usize = 16;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct EncryptedMessage {
+    wrapped_key: Vec<u8>,
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+impl EncryptedMessage {
+    pub fn seal(recp_addr: &PublicKey, msg: &NonEmptyString) -> Self {
+        let mut key = [0; 32];
+        let mut nonce = [0; 12];
+        rand::thread_rng().fill_bytes(&mut key);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(Nonce::from_slice(&nonce), msg.as_str().as_bytes())
+            .expect("Failed to encrypt message");
+
+        Self {
+            wrapped_key: recp_addr.encrypt(&key),
+            nonce,
+            ciphertext,
+        }
+    }
+
+    // returns None if the message is not meant for the owner of the given key
+    pub fn open(&self, priv_key: &PrivateKey) -> Option<String> {
+        let key = priv_key.decrypt(&self.wrapped_key)?;
+        if key.len() != 32 {
+            return None;
+        }
+
+        let msg = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_slice())
+            .ok()?;
+
+        String::from_utf8(msg).ok()
+    }
+
+    // the length of the ciphertext (the fees are calculated based on it)
+    pub fn len(&self) -> usize {
+        self.ciphertext.len()
+    }
+
+    // whether there is an actual (non-empty) message behind the ciphertext
+    // the validators cannot tell whether the ciphertext decrypts correctly
+    pub fn is_empty(&self) -> bool {
+        self.ciphertext.len() <= TAG_LEN || self.wrapped_key.is_empty()
+    }
+
+    // getters
+
+    pub fn wrapped_key(&self) -> &[u8] {
+        &self.wrapped_key
+    }
+
+    pub fn nonce(&self) -> &[u8; 12] {
+        &self.nonce
+    }
+
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPrivateKey;
+
+    #[test]
+    fn test_only_recipient_can_open() {
+        // RSA-OAEP with SHA-256 needs at least 1024-bit keys to wrap a 32-byte key
+        let recp = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap());
+        let other = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap());
+
+        let msg = NonEmptyString::new("hello there".to_string()).unwrap();
+        let sealed = EncryptedMessage::seal(&recp.to_publ_key(), &msg);
+
+        assert_eq!(sealed.len(), msg.len() + TAG_LEN);
+        assert!(!sealed.is_empty());
+        assert_eq!(sealed.open(&recp).as_deref(), Some("hello there"));
+        assert_eq!(sealed.open(&other), None);
+    }
+}
@@ -1,5 +1,6 @@
-use super::{Transaction, TransactionPayload};
+use super::{Transaction, TransactionPayload, MAX_TRANSFER_OUTPUTS};
 use crate::account::AccountsCatalog;
+use std::collections::HashSet;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,6 +19,16 @@ pub enum ValidateStructureError {
     InvalidHash,
     #[error("The signature could not be verified")]
     InvalidSignature,
+    #[error("The encrypted message is empty")]
+    EmptyEncryptedMessage,
+    #[error("The multi-transfer has no recipients")]
+    EmptyMultiTransfer,
+    #[error("The multi-transfer has more than {max} recipients")]
+    TooManyOutputs { max: usize },
+    #[error("The multi-transfer sends to the same recipient more than once")]
+    DuplicateRecipientAddr,
+    #[error("The total amount of the transaction is too large")]
+    AmountOverflow,
 }
 
 #[derive(Error, Debug)]
@@ -47,7 +58,11 @@ impl TransactionValidator {
             return Err(MissingSenderAddr);
         }
 
-        if matches!(tsx.payload(), Transfer(_) | Message(_)) && tsx.recp_addr().is_none() {
+        if matches!(
+            tsx.payload(),
+            Transfer(_) | Message(_) | EncryptedMessage(_)
+        ) && tsx.recp_addr().is_none()
+        {
             return Err(MissingRecipientAddr);
         }
 
@@ -55,7 +70,7 @@ impl TransactionValidator {
             return Err(MissingSignature);
         }
 
-        if matches!(tsx.payload(), Stake(_)) && tsx.recp_addr().is_some() {
+        if matches!(tsx.payload(), Stake(_) | MultiTransfer(_)) && tsx.recp_addr().is_some() {
             return Err(UnexpectedRecipientAddr);
         }
 
@@ -63,6 +78,49 @@ impl TransactionValidator {
             return Err(IdenticalSenderRecipientAddrs);
         }
 
+        if let MultiTransfer(outputs) = tsx.payload() {
+            if outputs.is_empty() {
+                return Err(EmptyMultiTransfer);
+            }
+
+            if outputs.len() > MAX_TRANSFER_OUTPUTS {
+                return Err(TooManyOutputs {
+                    max: MAX_TRANSFER_OUTPUTS,
+                });
+            }
+
+            let mut recp_addrs = HashSet::new();
+            for out in outputs {
+                if Some(out.recp_addr()) == tsx.sndr_addr() {
+                    return Err(IdenticalSenderRecipientAddrs);
+                }
+                if !recp_addrs.insert(out.recp_addr()) {
+                    return Err(DuplicateRecipientAddr);
+                }
+            }
+
+            // the cost is only ever computed with saturation, which must not be hit
+            // (otherwise the recipients would be credited more than the sender is charged)
+            let total_cost = outputs.iter().try_fold(0u32, |cost, out| {
+                cost.checked_add(Transaction::checked_transfer_total_cost(out.amnt())?)
+            });
+            if total_cost.is_none() {
+                return Err(AmountOverflow);
+            }
+        }
+
+        if let Transfer(amnt) = tsx.payload() {
+            if Transaction::checked_transfer_total_cost(*amnt).is_none() {
+                return Err(AmountOverflow);
+            }
+        }
+
+        if let EncryptedMessage(msg) = tsx.payload() {
+            if msg.is_empty() {
+                return Err(EmptyEncryptedMessage);
+            }
+        }
+
         if *tsx.hash() != tsx.calculate_hash() {
             return Err(InvalidHash);
         }
@@ -98,12 +156,23 @@ impl TransactionValidator {
             None => return Err(NonExistentSender),
         };
 
-        if matches!(tsx.payload(), Transfer(_) | Message(_))
-            && ctx.get_by_publ_key(tsx.recp_addr().unwrap()).is_none()
+        if matches!(
+            tsx.payload(),
+            Transfer(_) | Message(_) | EncryptedMessage(_)
+        ) && ctx.get_by_publ_key(tsx.recp_addr().unwrap()).is_none()
         {
             return Err(NonExistentRecipient);
         }
 
+        if let MultiTransfer(outputs) = tsx.payload() {
+            if outputs
+                .iter()
+                .any(|out| ctx.get_by_publ_key(out.recp_addr()).is_none())
+            {
+                return Err(NonExistentRecipient);
+            }
+        }
+
         if tsx.total_cost() > sndr.held_cents() {
             return Err(InsufficientFunds {
                 required: tsx.total_cost(),
@@ -118,3 +187,65 @@ impl TransactionValidator {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blockchain::transaction::TransferOutput, crypto::PrivateKey};
+    use rsa::RsaPrivateKey;
+    use std::num::NonZeroU32;
+
+    fn new_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap())
+    }
+
+    #[test]
+    fn test_amount_overflow() {
+        let sndr = new_priv_key();
+        let recps = [new_priv_key(), new_priv_key()];
+        let amnt = |cents| NonZeroU32::new(cents).unwrap();
+
+        // the fee of the amount alone does not fit
+        let tsx = Transaction::new_multi_transfer(
+            sndr.to_publ_key(),
+            vec![TransferOutput::new(recps[0].to_publ_key(), NonZeroU32::MAX)],
+            0,
+            &sndr,
+        );
+        assert!(matches!(
+            TransactionValidator::validate_structure(&tsx),
+            Err(ValidateStructureError::AmountOverflow)
+        ));
+
+        let tsx = Transaction::new_transfer(
+            sndr.to_publ_key(),
+            recps[0].to_publ_key(),
+            NonZeroU32::MAX,
+            0,
+            &sndr,
+        );
+        assert!(matches!(
+            TransactionValidator::validate_structure(&tsx),
+            Err(ValidateStructureError::AmountOverflow)
+        ));
+
+        // every output fits on its own, but not the sum
+        let outputs = recps
+            .iter()
+            .map(|recp| TransferOutput::new(recp.to_publ_key(), amnt(u32::MAX / 2)))
+            .collect();
+        let tsx = Transaction::new_multi_transfer(sndr.to_publ_key(), outputs, 0, &sndr);
+        assert!(matches!(
+            TransactionValidator::validate_structure(&tsx),
+            Err(ValidateStructureError::AmountOverflow)
+        ));
+
+        let tsx = Transaction::new_multi_transfer(
+            sndr.to_publ_key(),
+            vec![TransferOutput::new(recps[0].to_publ_key(), amnt(1000))],
+            0,
+            &sndr,
+        );
+        TransactionValidator::validate_structure(&tsx).unwrap();
+    }
+}
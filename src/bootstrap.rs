@@ -1,16 +1,156 @@
 use crate::{
-    blockchain::{block::Block, transaction::Transaction, Blockchain},
-    crypto::PublicKey,
+    account::AccountsCatalog,
+    blockchain::{block::Block, transaction::Transaction},
+    crypto::{PrivateKey, PublicKey},
+    genesis::Genesis,
     peer::PeersCatalog,
+    snapshot::Snapshot,
 };
+use rsa::sha2::{Digest as _, Sha256};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashSet,
+    fs,
     io::{self, Write as _},
     net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
     num::NonZeroU32,
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
+use thiserror::Error;
+
+/*
+    There are two ways for a node to join a BlockChat network:
+
+    - bootstrap_network(): every node sends a join request to the bootstrap peer,
+      which assigns IDs by arrival order, creates the genesis block once all peers
+      have joined, and sends them the peers and the genesis block in a join response.
+      Join requests are signed by the joining node and can be restricted to an allowlist
+      of public keys. A join request is signed for the IP address it is sent from and
+      for the time it is sent at, and the bootstrap peer accepts it only from that address,
+      only while it is recent and only once, so that it cannot be replayed by someone else.
+      Join responses are signed by the bootstrap peer, and the joining nodes
+      verify them (against the pinned key of the bootstrap peer, if one is given).
+      Every step has a deadline: a joining node resends its join request until it gets
+      a response, and the bootstrap peer retries join responses that cannot be delivered
+      and answers repeated join requests of its peers for a while after the network is formed.
+
+    - static_network(): every node reads the same static membership file, containing the
+      ID, public key and address of every peer, and creates the same peers catalog and
+      genesis block on its own. No messages are exchanged, so the node starts immediately.
+*/
+
+#[derive(Error, Debug)]
+pub enum MembershipError {
+    #[error("The membership file could not be read: {0}")]
+    Io(#[from] io::Error),
+    #[error("The membership file could not be parsed: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("The address of peer {0} could not be resolved")]
+    UnresolvedAddr(u32),
+    #[error("The peer IDs must be 0 to {0} with no gaps or repetitions")]
+    InvalidIds(usize),
+    #[error("The public key of peer {0} appears more than once")]
+    DuplicateKey(u32),
+    #[error("The local public key is not part of the membership")]
+    NotAMember,
+}
+
+#[derive(Error, Debug)]
+pub enum BootstrapError {
+    #[error("A bootstrap listener could not be set up: {0}")]
+    Io(#[from] io::Error),
+    #[error("The address of the bootstrap peer could not be resolved")]
+    UnresolvedAddr,
+    #[error("Only {joined} of {total} peers joined before the deadline")]
+    IncompleteNetwork { joined: usize, total: u16 },
+    #[error("No valid join response was received before the deadline")]
+    NoJoinResponse,
+    #[error("The genesis of the bootstrap peer does not match the local one")]
+    MismatchedGenesis,
+}
+
+// how long the bootstrap process can take and how hard it tries to reach the peers
+#[derive(Clone, Debug)]
+pub struct JoinTimeouts {
+    // how long to wait for the network to form (or for the join response)
+    // the bootstrap peer also answers repeated join requests for this long after that
+    pub deadline: Duration,
+    // how long to wait before resending the join request if no response arrives
+    pub rerequest_interval: Duration,
+    // how many times to try delivering each join response
+    pub response_attempts: NonZeroU32,
+    // how long to wait after a failed attempt to connect to a peer
+    pub retry_interval: Duration,
+}
+
+impl Default for JoinTimeouts {
+    fn default() -> Self {
+        Self {
+            deadline: Duration::from_secs(300),
+            rerequest_interval: Duration::from_secs(5),
+            response_attempts: NonZeroU32::new(5).unwrap(),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+// the timeout of connecting, reading and writing a single bootstrap message
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how often the (non-blocking) bootstrap listener is polled
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// how long a join request is accepted for after it is sent
+// (it is sent again with a new timestamp until a response arrives)
+const JOIN_REQUEST_MAX_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StaticPeer {
+    pub id: u32,
+    pub publ_key: PublicKey,
+    // anything that resolves to a socket address, e.g. `node0:27737`
+    pub addr: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StaticMembership {
+    pub peers: Vec<StaticPeer>,
+}
+
+impl StaticMembership {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MembershipError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    // the peers catalog is the same on every node, regardless of the order in the file
+    pub fn to_catalog(&self) -> Result<PeersCatalog, MembershipError> {
+        let mut peers = self.peers.iter().collect::<Vec<_>>();
+        peers.sort_by_key(|p| p.id);
+
+        if peers.iter().enumerate().any(|(i, p)| p.id != i as u32) {
+            return Err(MembershipError::InvalidIds(peers.len().saturating_sub(1)));
+        }
+
+        let mut catalog = PeersCatalog::new_with_capacity(peers.len());
+        for peer in peers {
+            let sock_addr = peer
+                .addr
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or(MembershipError::UnresolvedAddr(peer.id))?;
+
+            catalog
+                .insert((peer.publ_key.clone(), sock_addr))
+                .map_err(|_| MembershipError::DuplicateKey(peer.id))?;
+        }
+
+        Ok(catalog)
+    }
+}
 
 #[derive(Clone, Deserialize, Serialize)]
 struct PeerInfo {
@@ -20,66 +160,246 @@ struct PeerInfo {
     bs_port: u16,
 }
 
+// a snapshot and the blocks after it
+type Chain = (Snapshot, Vec<Block>);
+
+// the joining peer signs the request with its key
+#[derive(Clone, Deserialize, Serialize)]
+struct JoinRequest {
+    publ_key: PublicKey,
+    // the address the request is sent from
+    ip: IpAddr,
+    net_port: u16,
+    bs_port: u16,
+    genesis_hash: [u8; 32],
+    // in ms since the UNIX epoch
+    timestamp: u128,
+    sig: Vec<u8>,
+}
+
 #[derive(Deserialize, Serialize)]
 enum BootstrapMessage {
-    JoinRequest {
-        publ_key: PublicKey,
-        net_port: u16,
-        bs_port: u16,
-    },
+    // every peer must use the same genesis, so its hash is included in both messages
+    JoinRequest(JoinRequest),
 
+    // the joining peer bootstraps from the snapshot and the blocks after it
+    // the bootstrap peer signs the response with its key
     JoinResponse {
+        genesis_hash: [u8; 32],
         peers_info: Vec<PeerInfo>,
-        blockchain: Blockchain,
+        snapshot: Box<Snapshot>,
+        blocks: Vec<Block>,
+        sig: Vec<u8>,
     },
 }
 
+// optional restrictions on who can join the network and who can bootstrap it
+#[derive(Clone, Debug, Default)]
+pub struct JoinAuthorization {
+    // the public keys allowed to join, besides the bootstrap peer (anyone can join if None)
+    pub allowlist: Option<Vec<PublicKey>>,
+    // the public key of the bootstrap peer
+    // if None, a join response is only checked against the bootstrap peer it lists,
+    // which proves it is intact but not that it comes from the real bootstrap peer
+    pub bootstrap_peer_key: Option<PublicKey>,
+}
+
+impl JoinRequest {
+    fn new(
+        priv_key: &PrivateKey,
+        ip: IpAddr,
+        net_port: u16,
+        bs_port: u16,
+        genesis_hash: [u8; 32],
+        timestamp: u128,
+    ) -> Self {
+        let mut req = Self {
+            publ_key: priv_key.to_publ_key(),
+            ip,
+            net_port,
+            bs_port,
+            genesis_hash,
+            timestamp,
+            sig: vec![],
+        };
+        req.sig = priv_key.sign(&req.digest());
+
+        req
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+
+        hasher.update(self.publ_key.to_der());
+        // the address is hashed as text, ended by a zero byte
+        hasher.update(self.ip.to_string());
+        hasher.update([0]);
+        hasher.update(self.net_port.to_be_bytes());
+        hasher.update(self.bs_port.to_be_bytes());
+        hasher.update(self.genesis_hash);
+        hasher.update(self.timestamp.to_be_bytes());
+
+        hasher.finalize().into()
+    }
+
+    // why the request, received from the given address at the given time, is refused (if it is)
+    // the requests that pass are remembered, so that they are refused if they are received again
+    fn check(
+        &self,
+        addr: IpAddr,
+        now: u128,
+        seen: &mut HashSet<Vec<u8>>,
+    ) -> Result<(), &'static str> {
+        if !self.publ_key.verify(&self.digest(), &self.sig) {
+            return Err("with an invalid signature");
+        }
+
+        if self.ip != addr {
+            return Err("signed for another address");
+        }
+
+        // clocks are allowed to be a little ahead as well
+        if self.timestamp.abs_diff(now) > JOIN_REQUEST_MAX_AGE.as_millis() {
+            return Err("that is too old");
+        }
+
+        if !seen.insert(self.sig.clone()) {
+            return Err("that was already received");
+        }
+
+        Ok(())
+    }
+}
+
+// ms since the UNIX epoch
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+fn join_response_digest(
+    genesis_hash: &[u8; 32],
+    peers_info: &[PeerInfo],
+    snapshot: &Snapshot,
+    blocks: &[Block],
+) -> [u8; 32] {
+    let bytes = serde_json::to_vec(&(genesis_hash, peers_info, snapshot, blocks))
+        .expect("Failed to serialize join response");
+
+    Sha256::digest(bytes).into()
+}
+
+pub fn static_network(
+    membership: &StaticMembership,
+    genesis: &Genesis,
+    publ_key: PublicKey,
+) -> Result<(TcpListener, PeersCatalog, Snapshot, Vec<Block>), MembershipError> {
+    // from now on, the chain parameters of the genesis are used everywhere
+    genesis.install();
+
+    let catalog = membership.to_catalog()?;
+    let local_peer = catalog
+        .get_by_publ_key(&publ_key)
+        .ok_or(MembershipError::NotAMember)?;
+
+    let (net_listener, _) = bind_listener(local_peer.sock_addr().port())?;
+
+    // every node creates the exact same genesis block
+    let snapshot = init_genesis(&catalog, genesis, genesis.timestamp as u128);
+
+    Ok((net_listener, catalog, snapshot, vec![]))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn bootstrap_network(
     total_peers: u16,
-    cents_per_peer: u32,
+    genesis: &Genesis,
+    auth: &JoinAuthorization,
+    timeouts: &JoinTimeouts,
     bootstrap_peer_addr: impl ToSocketAddrs,
     bootstrap_port: u16,
     network_port: u16,
-    publ_key: PublicKey,
-) -> (TcpListener, PeersCatalog, Blockchain) {
+    priv_key: &PrivateKey,
+) -> Result<(TcpListener, PeersCatalog, Snapshot, Vec<Block>), BootstrapError> {
     assert!(total_peers > 1, "The network size cannot be less than 2");
-    assert!(cents_per_peer > 0, "The cents per peer cannot be 0");
     assert!(bootstrap_port > 0, "The bootstrap port cannot be 0");
+
+    let deadline = Instant::now() + timeouts.deadline;
+
+    // from now on, the chain parameters of the genesis are used everywhere
+    genesis.install();
+    let genesis_hash = genesis.calculate_hash();
+
+    if auth.bootstrap_peer_key.is_none() {
+        log::warn!("Bootstrap: The bootstrap peer's key is not pinned, join responses cannot be fully authenticated");
+    }
+
     let bootstrap_peer_addr = bootstrap_peer_addr
         .to_socket_addrs()
-        .expect("Failed to resolve bootstrap address")
+        .map_err(|_| BootstrapError::UnresolvedAddr)?
         .next()
-        .unwrap();
+        .ok_or(BootstrapError::UnresolvedAddr)?;
 
     // bind both listeners before sending the join request
     // the network listener is bound here to avoid missing any transactions
     // sent by peers who happened to connect before the join response is sent
-    let (bs_listener, bs_port) = bind_listener(bootstrap_port).unwrap();
-    let (net_listener, net_port) = bind_listener(network_port).unwrap();
+    let (bs_listener, bs_port) = bind_listener(bootstrap_port)?;
+    let (net_listener, net_port) = bind_listener(network_port)?;
 
-    send_join_request(bootstrap_peer_addr, publ_key.clone(), net_port, bs_port);
+    // the bootstrap listener is polled, so that the deadline can be enforced
+    bs_listener.set_nonblocking(true)?;
 
-    let (peers_info, blockchain) = match discover_peers(bs_listener, total_peers, publ_key.clone())
-    {
-        (peers_info, Some(blockchain)) => (peers_info, blockchain),
-        // if no blockchain is received, initialize a new one (we are the bootstrap peer)
-        (peers_info, None) => {
-            let blockchain = init_blockchain(&peers_info, NonZeroU32::new(cents_per_peer).unwrap());
-            // send the peers_info and blockchain to the other peers
-            send_join_responses(peers_info.clone(), blockchain.clone());
-            (peers_info, blockchain)
-        }
-    };
+    // the join request is resent until this is dropped
+    let _stop_join_requests = send_join_request(
+        bootstrap_peer_addr,
+        priv_key,
+        net_port,
+        bs_port,
+        genesis_hash,
+        timeouts,
+        deadline,
+    );
+
+    let publ_key = priv_key.to_publ_key();
+    let (peers_info, chain) = discover_peers(
+        &bs_listener,
+        total_peers,
+        publ_key,
+        genesis_hash,
+        auth,
+        deadline,
+    )?;
 
     // initialize the peeers catalog from the received peers_info
     let mut catalog = PeersCatalog::new();
-    for peer in peers_info {
+    for peer in &peers_info {
         catalog
-            .insert((peer.publ_key, (peer.ip, peer.net_port).into()))
+            .insert((peer.publ_key.clone(), (peer.ip, peer.net_port).into()))
             .unwrap();
     }
 
-    (net_listener, catalog, blockchain)
+    let (snapshot, blocks) = match chain {
+        Some(chain) => chain,
+        // if no snapshot is received, initialize a new chain (we are the bootstrap peer)
+        None => {
+            let snapshot = init_genesis(&catalog, genesis, now_ms());
+            // send the peers_info and the genesis snapshot to the other peers
+            send_join_responses(
+                bs_listener,
+                peers_info,
+                snapshot.clone(),
+                vec![],
+                genesis_hash,
+                priv_key,
+                timeouts,
+            );
+            (snapshot, vec![])
+        }
+    };
+
+    Ok((net_listener, catalog, snapshot, blocks))
 }
 
 fn bind_listener(port: u16) -> Result<(TcpListener, u16), io::Error> {
@@ -91,97 +411,227 @@ fn bind_listener(port: u16) -> Result<(TcpListener, u16), io::Error> {
     Ok((listener, addr.port()))
 }
 
+fn send_message(addr: SocketAddr, bytes: &[u8]) -> Result<(), io::Error> {
+    let mut stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    stream.write_all(bytes)
+}
+
+// waits for the next bootstrap message
+// returns None if the deadline passes first
+fn recv_message(
+    listener: &TcpListener,
+    deadline: Instant,
+) -> Option<(BootstrapMessage, SocketAddr)> {
+    loop {
+        let (stream, addr) = match listener.accept() {
+            Ok((stream, addr)) => (stream, addr),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return None;
+                }
+
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Bootstrap: Failed to accept incoming connection: {}", e);
+                continue;
+            }
+        };
+
+        // a peer that stalls mid-message must not block the bootstrap process
+        let res = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(IO_TIMEOUT)));
+        if let Err(e) = res {
+            log::warn!(
+                "Bootstrap: Failed to set up connection from {}: {}",
+                addr,
+                e
+            );
+            continue;
+        }
+
+        let mut de = serde_json::Deserializer::from_reader(&stream);
+        match BootstrapMessage::deserialize(&mut de) {
+            Ok(message) => return Some((message, addr)),
+            Err(e) => log::warn!("Bootstrap: Failed to deserialize message: {}", e),
+        }
+    }
+}
+
+// keeps sending the join request until the returned sender is dropped or the deadline passes
+// the bootstrap peer ignores repeated requests while the network is forming,
+// and answers them with the join response afterwards
+// every request is signed anew, for the address it is sent from and the time it is sent at
 fn send_join_request(
-    bs_peer_addr: impl ToSocketAddrs,
-    publ_key: PublicKey,
+    bs_peer_addr: SocketAddr,
+    priv_key: &PrivateKey,
     net_port: u16,
     bs_port: u16,
-) {
-    let bs_peer_addr = bs_peer_addr
-        .to_socket_addrs()
-        .expect("Failed to resolve the bootstrap peer's address")
-        .next()
-        .unwrap();
-
-    let req = BootstrapMessage::JoinRequest {
-        publ_key,
-        net_port,
-        bs_port,
+    genesis_hash: [u8; 32],
+    timeouts: &JoinTimeouts,
+    deadline: Instant,
+) -> Sender<()> {
+    let send = move |priv_key: &PrivateKey| -> Result<(), io::Error> {
+        let mut stream = TcpStream::connect_timeout(&bs_peer_addr, IO_TIMEOUT)?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let ip = stream.local_addr()?.ip();
+        let req = JoinRequest::new(priv_key, ip, net_port, bs_port, genesis_hash, now_ms());
+        let req_bytes = serde_json::to_vec(&BootstrapMessage::JoinRequest(req))
+            .expect("Failed to serialize join request");
+
+        stream.write_all(&req_bytes)
     };
 
-    let req_bytes = serde_json::to_vec(&req).expect("Failed to serialize join request");
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let priv_key = priv_key.clone();
+    let timeouts = timeouts.clone();
 
     thread::spawn(move || loop {
-        let mut stream = match TcpStream::connect(bs_peer_addr) {
-            Ok(stream) => stream,
+        let wait = match send(&priv_key) {
+            Ok(()) => {
+                log::debug!("Bootstrap: Join request successfully sent to the bootstrap node");
+                timeouts.rerequest_interval
+            }
             Err(e) => {
-                log::warn!("Bootstrap: Failed to connect to bootstrap node: {}", e);
-                thread::sleep(Duration::from_secs(1));
-                continue;
+                log::warn!("Bootstrap: Failed to send join request: {}", e);
+                timeouts.retry_interval
             }
         };
 
-        if let Err(e) = stream.write_all(&req_bytes) {
-            log::warn!("Bootstrap: Failed to send join request: {}", e);
-            thread::sleep(Duration::from_secs(1));
-            continue;
+        if Instant::now() + wait >= deadline {
+            break;
         }
 
-        log::debug!("Bootstrap: Join request successfully sent to the bootstrap node");
-
-        break;
+        match stop_rx.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            _ => break,
+        }
     });
+
+    stop_tx
 }
 
 // returns a vec containing info about the peers in the network
 // the first entry is the bootstrap peer
-// also returns a blockchain if the bootstrap peer is not us
+// also returns a snapshot and the blocks after it if the bootstrap peer is not us
 // otherwise None
 fn discover_peers(
-    listener: TcpListener,
+    listener: &TcpListener,
     total_peers: u16,
-    publ_key: PublicKey,
-) -> (Vec<PeerInfo>, Option<Blockchain>) {
+    local_publ_key: PublicKey,
+    genesis_hash: [u8; 32],
+    auth: &JoinAuthorization,
+    deadline: Instant,
+) -> Result<(Vec<PeerInfo>, Option<Chain>), BootstrapError> {
     let mut discovered_peers = vec![];
     let mut added_self = false;
+    let mut seen_requests = HashSet::new();
 
     loop {
-        let (mut stream, addr) = match listener.accept() {
-            Ok((stream, addr)) => (stream, addr),
-            Err(e) => {
-                log::warn!("Bootstrap: Failed to accept incoming connection: {}", e);
-                continue;
-            }
-        };
-
-        let mut de = serde_json::Deserializer::from_reader(&mut stream);
-        let message = match BootstrapMessage::deserialize(&mut de) {
-            Ok(message) => message,
-            Err(e) => {
-                log::warn!("Bootstrap: Failed to deserialize message: {}", e);
-                continue;
-            }
+        let Some((message, addr)) = recv_message(listener, deadline) else {
+            // whoever has received join requests is the bootstrap peer
+            return Err(if discovered_peers.is_empty() {
+                BootstrapError::NoJoinResponse
+            } else {
+                BootstrapError::IncompleteNetwork {
+                    joined: discovered_peers.len(),
+                    total: total_peers,
+                }
+            });
         };
 
         let peer_info = match message {
             // if a join request is received, we are the bootstrap peer
-            BootstrapMessage::JoinRequest {
-                publ_key,
-                net_port,
-                bs_port,
-            } => PeerInfo {
-                publ_key,
-                ip: addr.ip(),
-                net_port,
-                bs_port,
-            },
+            BootstrapMessage::JoinRequest(req) => {
+                if let Err(reason) = req.check(addr.ip(), now_ms(), &mut seen_requests) {
+                    log::warn!("Bootstrap: Rejected join request from {} {}", addr, reason);
+                    continue;
+                }
+
+                let JoinRequest {
+                    publ_key,
+                    net_port,
+                    bs_port,
+                    genesis_hash: peer_genesis_hash,
+                    ..
+                } = req;
+
+                // the bootstrap peer receives its own join request, and always lets itself in
+                if let Some(allowlist) = &auth.allowlist {
+                    if publ_key != local_publ_key && !allowlist.contains(&publ_key) {
+                        log::warn!(
+                            "Bootstrap: Rejected join request from {} with a key not in the allowlist",
+                            addr
+                        );
+                        continue;
+                    }
+                }
+
+                // a peer can only take one slot, no matter how many requests it sends
+                if discovered_peers
+                    .iter()
+                    .any(|p: &PeerInfo| p.publ_key == publ_key)
+                {
+                    log::debug!("Bootstrap: Ignored repeated join request from {}", addr);
+                    continue;
+                }
+
+                if peer_genesis_hash != genesis_hash {
+                    log::warn!(
+                        "Bootstrap: Rejected join request from {} with a different genesis",
+                        addr
+                    );
+                    continue;
+                }
+
+                PeerInfo {
+                    publ_key,
+                    ip: addr.ip(),
+                    net_port,
+                    bs_port,
+                }
+            }
 
             // if a join response is received, we are not the bootstrap peer
             BootstrapMessage::JoinResponse {
+                genesis_hash: peer_genesis_hash,
                 peers_info,
-                blockchain,
+                snapshot,
+                blocks,
+                sig,
             } => {
-                return (peers_info, Some(blockchain));
+                // the bootstrap peer is always the first one
+                let bs_publ_key = match (&auth.bootstrap_peer_key, peers_info.first()) {
+                    (Some(pinned), Some(bs_peer)) if *pinned == bs_peer.publ_key => pinned,
+                    (None, Some(bs_peer)) => &bs_peer.publ_key,
+                    _ => {
+                        log::warn!(
+                            "Bootstrap: Rejected join response from {} with an unknown bootstrap peer",
+                            addr
+                        );
+                        continue;
+                    }
+                };
+
+                let digest =
+                    join_response_digest(&peer_genesis_hash, &peers_info, &snapshot, &blocks);
+                if !bs_publ_key.verify(&digest, &sig) {
+                    log::warn!(
+                        "Bootstrap: Rejected join response from {} with an invalid signature",
+                        addr
+                    );
+                    continue;
+                }
+
+                if peer_genesis_hash != genesis_hash {
+                    return Err(BootstrapError::MismatchedGenesis);
+                }
+
+                return Ok((peers_info, Some((*snapshot, blocks))));
             }
         };
 
@@ -190,7 +640,7 @@ fn discover_peers(
         // to dignify that it's the bootstrap peer
         // this does not affect the system in any way other
         // than ensuring that the bootstrap peer will have an ID of 0
-        if !added_self && peer_info.publ_key == publ_key {
+        if !added_self && peer_info.publ_key == local_publ_key {
             discovered_peers.push(peer_info);
             let last = discovered_peers.len() - 1;
             discovered_peers.swap(0, last); // move self to the front
@@ -200,53 +650,196 @@ fn discover_peers(
         }
 
         if discovered_peers.len() as u16 >= total_peers {
-            return (discovered_peers, None);
+            return Ok((discovered_peers, None));
         }
     }
 }
 
-fn init_blockchain(peer_info: &[PeerInfo], amnt_per_peer: NonZeroU32) -> Blockchain {
-    let gen_tsxs = peer_info
-        .iter()
-        .map(|p| Transaction::new_genesis(p.publ_key.clone(), amnt_per_peer))
-        .collect::<Vec<_>>();
-    let gen_blk = Block::new_genesis(gen_tsxs);
-    Blockchain::new(gen_blk)
+// returns a snapshot of the genesis block along with the accounts it creates
+fn init_genesis(peers: &PeersCatalog, genesis: &Genesis, timestamp: u128) -> Snapshot {
+    let mut gen_tsxs = vec![];
+    for publ_key in peers.iter().publ_keys() {
+        let (cents, staked_cents) = genesis.initial_balance(publ_key);
+
+        if let Some(cents) = NonZeroU32::new(cents) {
+            gen_tsxs.push(Transaction::new_genesis(publ_key.clone(), cents));
+        }
+
+        if let Some(staked_cents) = NonZeroU32::new(staked_cents) {
+            gen_tsxs.push(Transaction::new_genesis_stake(
+                publ_key.clone(),
+                staked_cents,
+            ));
+        }
+    }
+
+    for alloc in &genesis.allocations {
+        if peers.get_by_publ_key(&alloc.publ_key).is_none() {
+            log::warn!("Bootstrap: Genesis allocation for a peer outside the network ignored");
+        }
+    }
+
+    // the genesis block commits to the state it creates, like any other block
+    let mut accounts = AccountsCatalog::new(peers);
+    accounts.process_transactions(&gen_tsxs, None).unwrap();
+
+    let gen_blk = Block::new_genesis(gen_tsxs, timestamp, accounts.calculate_state_root());
+    Snapshot::genesis(&gen_blk, &accounts)
 }
 
-fn send_join_responses(peers_info: Vec<PeerInfo>, blockchain: Blockchain) {
-    let bs_addrs: Vec<SocketAddr> = peers_info
+// the join responses are sent in the background, so that the bootstrap peer can start right away
+// each one is retried a few times, and peers that still miss theirs can request it again
+fn send_join_responses(
+    listener: TcpListener,
+    peers_info: Vec<PeerInfo>,
+    snapshot: Snapshot,
+    blocks: Vec<Block>,
+    genesis_hash: [u8; 32],
+    priv_key: &PrivateKey,
+    timeouts: &JoinTimeouts,
+) {
+    let mut bs_addrs: Vec<SocketAddr> = peers_info
         .iter()
         .skip(1)
         .map(|peer| (peer.ip, peer.bs_port).into())
         .collect::<Vec<_>>();
 
+    let members = peers_info
+        .iter()
+        .skip(1)
+        .map(|peer| peer.publ_key.clone())
+        .collect::<Vec<_>>();
+
+    let sig = priv_key.sign(&join_response_digest(
+        &genesis_hash,
+        &peers_info,
+        &snapshot,
+        &blocks,
+    ));
+
     let res = BootstrapMessage::JoinResponse {
+        genesis_hash,
         peers_info,
-        blockchain,
+        snapshot: Box::new(snapshot),
+        blocks,
+        sig,
     };
 
     let res_bytes = serde_json::to_vec(&res).expect("Failed to serialize join response");
 
-    let mut ok = 0;
-    for addr in bs_addrs {
-        let mut stream = match TcpStream::connect(addr) {
-            Ok(stream) => stream,
-            Err(e) => {
-                log::warn!("Bootstrap: Failed to connect to peer: {}", e);
-                continue;
+    let attempts = timeouts.response_attempts.get();
+    let retry_interval = timeouts.retry_interval;
+    let bytes = res_bytes.clone();
+    thread::spawn(move || {
+        let total = bs_addrs.len();
+
+        for attempt in 1..=attempts {
+            bs_addrs.retain(|addr| match send_message(*addr, &bytes) {
+                Ok(()) => false,
+                Err(e) => {
+                    log::warn!(
+                        "Bootstrap: Failed to send join response to {} (attempt {}/{}): {}",
+                        addr,
+                        attempt,
+                        attempts,
+                        e
+                    );
+                    true
+                }
+            });
+
+            if bs_addrs.is_empty() {
+                break;
             }
+
+            thread::sleep(retry_interval);
+        }
+
+        if !bs_addrs.is_empty() {
+            log::error!(
+                "Bootstrap: Join responses could not be delivered to {:?}, they must request them again",
+                bs_addrs
+            );
+        }
+
+        log::trace!(
+            "Bootstrap: Join responses successfully sent to {} peers",
+            total - bs_addrs.len()
+        );
+    });
+
+    let deadline = Instant::now() + timeouts.deadline;
+    thread::spawn(move || serve_join_rerequests(listener, members, res_bytes, deadline));
+}
+
+// answers the repeated join requests of peers that missed their join response
+fn serve_join_rerequests(
+    listener: TcpListener,
+    members: Vec<PublicKey>,
+    res_bytes: Vec<u8>,
+    deadline: Instant,
+) {
+    let mut seen_requests = HashSet::new();
+
+    while let Some((message, addr)) = recv_message(&listener, deadline) {
+        let BootstrapMessage::JoinRequest(req) = message else {
+            continue;
         };
 
-        if let Err(e) = stream.write_all(&res_bytes) {
-            log::warn!("Bootstrap: Failed to send join response: {}", e);
-        } else {
-            ok += 1;
+        if let Err(reason) = req.check(addr.ip(), now_ms(), &mut seen_requests) {
+            log::warn!("Bootstrap: Rejected join request from {} {}", addr, reason);
+            continue;
+        }
+
+        if !members.contains(&req.publ_key) {
+            log::warn!(
+                "Bootstrap: Rejected join request from {} after the network was formed",
+                addr
+            );
+            continue;
+        }
+
+        match send_message((addr.ip(), req.bs_port).into(), &res_bytes) {
+            Ok(()) => log::debug!("Bootstrap: Join response sent again to {}", addr),
+            Err(e) => log::warn!("Bootstrap: Failed to send join response again: {}", e),
         }
     }
 
-    log::trace!(
-        "Bootstrap: Join responses successfully sent to {} peers",
-        ok
-    );
+    log::debug!("Bootstrap: Stopped answering join requests");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPrivateKey;
+
+    #[test]
+    fn test_join_request_replay() {
+        let priv_key = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+        let ip: IpAddr = [10, 0, 0, 1].into();
+        let other_ip: IpAddr = [10, 0, 0, 2].into();
+        let now = now_ms();
+        let mut seen = HashSet::new();
+
+        let req = JoinRequest::new(&priv_key, ip, 27737, 27738, [1; 32], now);
+
+        // a request sent from another address is refused
+        assert!(req.check(other_ip, now, &mut seen).is_err());
+        assert!(req.check(ip, now, &mut seen).is_ok());
+        // a request that was already received is refused
+        assert!(req.check(ip, now, &mut seen).is_err());
+
+        // a request that is too old is refused
+        let max_age = JOIN_REQUEST_MAX_AGE.as_millis();
+        let old_req = JoinRequest::new(&priv_key, ip, 27737, 27738, [1; 32], now - max_age - 1);
+        assert!(old_req.check(ip, now, &mut seen).is_err());
+
+        // a request whose address is changed after signing is refused
+        let mut moved_req = JoinRequest::new(&priv_key, ip, 27737, 27738, [1; 32], now);
+        moved_req.ip = other_ip;
+        assert!(moved_req.check(other_ip, now, &mut seen).is_err());
+
+        let new_req = JoinRequest::new(&priv_key, ip, 27737, 27738, [1; 32], now + 1);
+        assert!(new_req.check(ip, now, &mut seen).is_ok());
+    }
 }
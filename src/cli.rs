@@ -4,17 +4,42 @@
     crate `clap`. See its documentation to understand the syntax.
 */
 
-use clap::{Parser, Subcommand};
+use crate::{
+    address::{Address, ADDRESS_PREFIX},
+    address_book,
+    crypto::{self, KeyFormat},
+    events::EventType,
+    protocol::Broadcast,
+};
+use clap::{Parser, Subcommand, ValueEnum as _};
 use serde::{Deserialize, Serialize};
 use std::{
+    env,
     fmt::{self, Display, Formatter},
+    fs,
+    io::{self, BufRead, BufReader, Write as _},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs as _},
     num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+// environment variables to set the daemon address (defaults to `localhost:27737`)
+const DAEMON_SOCKET_ENV: &str = "BLOCK_CHAT_DAEMON_SOCKET";
+const DAEMON_PORT_ENV: &str = "BLOCK_CHAT_DAEMON_PORT";
+const DEFAULT_DAEMON_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const DEFAULT_DAEMON_PORT: u16 = 27737;
+
+// how long a client can wait for a transaction to be confirmed at most (a day)
+pub const MAX_WAIT_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
 #[derive(Debug, Deserialize, Parser, Serialize)]
 pub struct Args {
+    /// Print the responses of the daemon as JSON, one object per line
+    #[arg(long, global = true)]
+    pub json: bool,
     #[command(name = "command", subcommand)]
-    pub cmd: Command,
+    pub cmd: ClientCommand,
 }
 
 impl Display for Args {
@@ -23,28 +48,55 @@ impl Display for Args {
     }
 }
 
+// the commands of the client: the commands sent to the daemon, along with the ones run locally
+#[derive(Clone, Debug, Deserialize, Serialize, Subcommand)]
+pub enum ClientCommand {
+    /// Start an interactive prompt that keeps a session with the daemon
+    #[command(name = "repl", visible_alias = "shell")]
+    Repl,
+
+    #[command(flatten)]
+    Daemon(Command),
+}
+
+impl Display for ClientCommand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClientCommand::Repl => write!(f, "repl"),
+            ClientCommand::Daemon(command) => command.fmt(f),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, Subcommand)]
 pub enum Command {
     /// Send BCC to another user
     #[command(arg_required_else_help = true)]
     T {
-        /// The network ID of the recipient
-        #[arg(name = "RECIPIENT_ID")]
-        rcp_id: u32,
+        /// The recipient, given by its network ID, its name in the address book, its address or its key fingerprint
+        #[arg(name = "RECIPIENT", value_parser = parse_recipient)]
+        rcp: Recipient,
         /// The amount of BCC to send
         #[arg(name = "AMOUNT")]
         amt: NonZeroU32,
+        #[command(flatten)]
+        wait: WaitOptions,
     },
 
     /// Send a message to another user
     #[command(arg_required_else_help = true)]
     M {
-        /// The network ID of the recipient
-        #[arg(name = "RECIPIENT_ID")]
-        rcp_id: u32,
+        /// The recipient, given by its network ID, its name in the address book, its address or its key fingerprint
+        #[arg(name = "RECIPIENT", value_parser = parse_recipient)]
+        rcp: Recipient,
         /// The message to send
         #[arg(name = "MESSAGE")]
         msg: Vec<String>,
+        /// Encrypt the message, so that only the recipient can read it
+        #[arg(short, long)]
+        encrypted: bool,
+        #[command(flatten)]
+        wait: WaitOptions,
     },
 
     /// Stake BCC to verify transactions
@@ -53,6 +105,36 @@ pub enum Command {
         /// The amount of BCC to stake
         #[arg(name = "AMOUNT")]
         amt: NonZeroU32,
+        #[command(flatten)]
+        wait: WaitOptions,
+    },
+
+    /// Send BCC to several users in a single transaction
+    #[command(
+        name = "multi-transfer",
+        visible_alias = "mt",
+        arg_required_else_help = true
+    )]
+    Mt {
+        /// The recipients (given as for `t`) and the amounts of BCC to send them, e.g. `bob=5 3=10`
+        #[arg(name = "RECIPIENT=AMOUNT", value_parser = parse_payment, required = true)]
+        payments: Vec<Payment>,
+        #[command(flatten)]
+        wait: WaitOptions,
+    },
+
+    /// Send the transfers, messages and stakes of a file (one `t`, `m`, `mt` or `stake` command per line)
+    #[command(name = "batch")]
+    Batch {
+        /// The file to read (stdin if omitted or `-`); empty lines and lines starting with `#` are skipped
+        #[arg(name = "FILE")]
+        file: Option<PathBuf>,
+        /// Send none of the transactions unless all of them are valid
+        #[arg(short, long)]
+        atomic: bool,
+        // read from the file by the client (see `Command::load_batch`)
+        #[arg(skip)]
+        items: Vec<BatchItem>,
     },
 
     /// View all transactions of the last verified block
@@ -63,16 +145,114 @@ pub enum Command {
     #[command(name = "balance")]
     B,
 
+    /// View a block by its index or hash
+    #[command(name = "block", arg_required_else_help = true)]
+    Block {
+        /// The index of the block or its hash
+        #[arg(name = "BLOCK", value_parser = parse_block_ref)]
+        blk: BlockRef,
+    },
+
+    /// View a range of blocks, e.g. `3..7` (both ends included) or `3..` (up to the last block)
+    #[command(name = "blocks", arg_required_else_help = true)]
+    Blocks {
+        /// The indices of the first and last block
+        #[arg(name = "RANGE", value_parser = parse_block_range)]
+        range: BlockRange,
+    },
+
+    /// View the height and tip of the blockchain, along with the total supply and stakes
+    #[command(name = "chain-info")]
+    ChainInfo,
+
+    /// View your network ID, public key and address
+    #[command(name = "whoami")]
+    Whoami,
+
+    /// Export the full public key of a peer (yours if the peer is omitted)
+    #[command(name = "key")]
+    Key {
+        /// The peer, given by its network ID, its name in the address book, its address or its key fingerprint
+        #[arg(name = "PEER", value_parser = parse_recipient)]
+        peer: Option<Recipient>,
+        /// The format to export the key to
+        #[arg(short, long, value_enum, default_value = "pem")]
+        format: KeyFormat,
+    },
+
+    /// View the peers of the network, along with their balances and whether they are connected
+    #[command(name = "peers")]
+    Peers,
+
+    /// View how many peers are connected, along with the height of the chain and the mempool
+    #[command(name = "network")]
+    Network,
+
+    /// View the transactions waiting to be included in a block
+    #[command(name = "mempool")]
+    Mempool,
+
+    /// Manage the address book, which gives names to the keys of peers
+    #[command(name = "address-book", visible_alias = "ab", subcommand)]
+    AddressBook(AddressBookCommand),
+
+    /// View the transactions of an account, newest first
+    #[command(name = "tx-history")]
+    Tx {
+        /// The network ID of the account (yours if omitted)
+        #[arg(name = "ACCOUNT_ID")]
+        acc_id: Option<u32>,
+        /// The page to show
+        #[arg(short, long, default_value = "1")]
+        page: NonZeroU32,
+        /// How many transactions to show per page
+        #[arg(short = 's', long, default_value = "20")]
+        page_size: NonZeroU32,
+    },
+
+    /// View whether a transaction is pending, included in a block or dropped
+    #[command(name = "status", arg_required_else_help = true)]
+    Status {
+        /// The hash of the transaction (as returned when it was sent)
+        #[arg(name = "HASH", value_parser = parse_hash)]
+        hash: [u8; 32],
+    },
+
+    /// View the messages sent to you (they are marked as read once shown)
+    #[command(name = "inbox")]
+    Inbox {
+        /// Only show messages from this network ID
+        #[arg(long, value_name = "SENDER_ID")]
+        from: Option<u32>,
+        /// Only show messages sent at or after this time
+        /// (UNIX timestamp in seconds, or how long ago, e.g. `30m`, `2h`, `1d`)
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        since: Option<u128>,
+        /// Only show messages sent at or before this time
+        /// (UNIX timestamp in seconds, or how long ago, e.g. `30m`, `2h`, `1d`)
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        until: Option<u128>,
+        /// Only show unread messages
+        #[arg(short, long)]
+        unread: bool,
+        /// Do not mark the shown messages as read
+        #[arg(short, long)]
+        keep_unread: bool,
+    },
+
+    /// Stream the events of the node as they happen (one JSON object per line)
+    #[command(name = "subscribe")]
+    Subscribe {
+        /// The types of events to receive (all of them if omitted)
+        #[arg(name = "EVENT_TYPE", value_enum, value_delimiter = ',')]
+        types: Vec<EventType>,
+    },
+
     // * debug only
     /// View the history of transactions and blocks
     #[command(name = "history")]
     H,
 
-    // * debug only
-    /// View your network ID
-    #[command(skip = true)]
-    Id,
-
     // * debug only
     /// View the average time per transaction and block
     Time,
@@ -82,18 +262,595 @@ pub enum Command {
     Stats,
 }
 
+impl Command {
+    // read the transactions of a batch from its file, so that the daemon receives
+    // the transactions rather than the path (other commands are left as they are)
+    pub fn load_batch(&mut self) -> io::Result<()> {
+        let Command::Batch { file, items, .. } = self else {
+            return Ok(());
+        };
+
+        *items = match file.as_deref() {
+            None => read_batch(io::stdin().lock())?,
+            Some(path) if path == Path::new("-") => read_batch(io::stdin().lock())?,
+            Some(path) => fs::File::open(path)
+                .and_then(|file| read_batch(BufReader::new(file)))
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+        };
+
+        Ok(())
+    }
+
+    // send the command to the daemon, which writes its responses to the stream
+    // (as lines of JSON, see `Response`) and then closes the connection
+    pub fn send(self, daemon_addr: SocketAddr) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(daemon_addr)?;
+
+        let cmd_bytes =
+            serde_json::to_vec(&Broadcast::Command(self)).expect("Failed to serialize command");
+
+        stream.write_all(&cmd_bytes)?;
+
+        Ok(stream)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Subcommand)]
+pub enum AddressBookCommand {
+    /// Give a name to the key of a peer
+    #[command(name = "add", arg_required_else_help = true)]
+    Add {
+        /// The name (it starts with a letter and contains only letters, digits, `-` and `_`)
+        #[arg(name = "NAME")]
+        name: String,
+        /// The peer, given by its network ID, its address or its key fingerprint
+        #[arg(name = "PEER", value_parser = parse_recipient)]
+        peer: Recipient,
+    },
+
+    /// View the names in the address book
+    #[command(name = "list")]
+    List,
+
+    /// Remove a name from the address book
+    #[command(name = "remove", arg_required_else_help = true)]
+    Remove {
+        #[arg(name = "NAME")]
+        name: String,
+    },
+}
+
+// options for waiting until a transaction is confirmed (for `t`, `m`, `mt` and `stake`)
+#[derive(clap::Args, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WaitOptions {
+    /// Wait until the transaction is included in a block
+    #[arg(short, long)]
+    pub wait: bool,
+    /// Wait until the block of the transaction is N blocks deep (implies --wait)
+    #[arg(short, long, value_name = "N")]
+    pub confirmations: Option<NonZeroU32>,
+    /// How many seconds to wait for at most (up to a day)
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(..=MAX_WAIT_TIMEOUT_SECS)
+    )]
+    pub timeout: u64,
+}
+
+// a peer given by its network ID, its name in the address book, its address or the fingerprint of its key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Recipient {
+    Id(u32),
+    Alias(String),
+    Address(Address),
+    Fingerprint(String), // in lowercase
+}
+
+// an amount of BCC to send to a recipient, as part of a multi-transfer
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Payment {
+    pub rcp: Recipient,
+    pub amt: NonZeroU32,
+}
+
+// a transaction of a batch, given on a line of the batch file as a `t`, `m`, `mt` or `stake` command
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BatchItem {
+    Transfer {
+        rcp: Recipient,
+        amt: NonZeroU32,
+    },
+    MultiTransfer {
+        payments: Vec<Payment>,
+    },
+    Message {
+        rcp: Recipient,
+        msg: String,
+        encrypted: bool,
+    },
+    Stake {
+        amt: NonZeroU32,
+    },
+}
+
+// a line of a batch file
+#[derive(Parser)]
+#[command(name = "", no_binary_name = true)]
+struct BatchLine {
+    #[command(subcommand)]
+    cmd: Command,
+}
+
+// a block given either by its index or by its hash
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum BlockRef {
+    Index(u32),
+    Hash([u8; 32]),
+}
+
+// a range of block indices, both ends included (up to the last block if `to` is None)
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct BlockRange {
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+impl WaitOptions {
+    // how many confirmations to wait for (None if the client should not wait)
+    pub fn confirmations(&self) -> Option<NonZeroU32> {
+        match self.confirmations {
+            Some(n) => Some(n),
+            None if self.wait => Some(NonZeroU32::MIN),
+            None => None,
+        }
+    }
+
+    // commands may not come from the client, so the timeout is capped here as well
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.min(MAX_WAIT_TIMEOUT_SECS))
+    }
+}
+
+impl Display for WaitOptions {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.confirmations() {
+            Some(n) => write!(f, " --confirmations {} --timeout {}", n, self.timeout),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id),
+            Self::Alias(name) | Self::Fingerprint(name) => write!(f, "{}", name),
+            Self::Address(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl BatchItem {
+    // parse a line of a batch file (the words of a message are separated by single spaces)
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let BatchLine { cmd } =
+            BatchLine::try_parse_from(line.split_whitespace()).map_err(|e| {
+                // the error without the usage that clap appends to it
+                let e = e.to_string();
+                let e: Vec<_> = e
+                    .lines()
+                    .take_while(|l| !l.is_empty())
+                    .map(str::trim)
+                    .collect();
+                e.join(" ").trim_start_matches("error: ").to_string()
+            })?;
+
+        let (item, wait) = match cmd {
+            Command::T { rcp, amt, wait } => (Self::Transfer { rcp, amt }, wait),
+            Command::Mt { payments, wait } => (Self::MultiTransfer { payments }, wait),
+            Command::M {
+                rcp,
+                msg,
+                encrypted,
+                wait,
+            } => (
+                Self::Message {
+                    rcp,
+                    msg: msg.join(" "),
+                    encrypted,
+                },
+                wait,
+            ),
+            Command::S { amt, wait } => (Self::Stake { amt }, wait),
+            cmd => return Err(format!("`{}` cannot be part of a batch", cmd)),
+        };
+
+        if wait.confirmations().is_some() {
+            return Err("the transactions of a batch cannot be waited for".to_string());
+        }
+
+        Ok(item)
+    }
+}
+
+impl Display for BatchItem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Transfer { rcp, amt } => write!(f, "t {} {}", rcp, amt),
+            Self::MultiTransfer { payments } => write!(f, "mt {}", fmt_payments(payments)),
+            Self::Message {
+                rcp,
+                msg,
+                encrypted: false,
+            } => write!(f, "m {} {}", rcp, msg),
+            Self::Message {
+                rcp,
+                msg,
+                encrypted: true,
+            } => write!(f, "m --encrypted {} {}", rcp, msg),
+            Self::Stake { amt } => write!(f, "stake {}", amt),
+        }
+    }
+}
+
+impl Display for Payment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.rcp, self.amt)
+    }
+}
+
+fn fmt_payments(payments: &[Payment]) -> String {
+    let payments: Vec<_> = payments.iter().map(Payment::to_string).collect();
+    payments.join(" ")
+}
+
+impl Display for BlockRef {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Hash(hash) => write!(f, "{}", hex::encode(hash)),
+        }
+    }
+}
+
+impl Display for BlockRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.to {
+            Some(to) => write!(f, "{}..{}", self.from, to),
+            None => write!(f, "{}..", self.from),
+        }
+    }
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Command::T { rcp_id, amt } => write!(f, "t {} {}", rcp_id, amt),
-            Command::M { rcp_id, msg } => write!(f, "m {} {}", rcp_id, msg.join(" ")),
-            Command::S { amt } => write!(f, "stake {}", amt),
+            Command::T { rcp, amt, wait } => write!(f, "t{} {} {}", wait, rcp, amt),
+            Command::M {
+                rcp,
+                msg,
+                encrypted: false,
+                wait,
+            } => write!(f, "m{} {} {}", wait, rcp, msg.join(" ")),
+            Command::M {
+                rcp,
+                msg,
+                encrypted: true,
+                wait,
+            } => write!(f, "m --encrypted{} {} {}", wait, rcp, msg.join(" ")),
+            Command::S { amt, wait } => write!(f, "stake{} {}", wait, amt),
+            Command::Mt { payments, wait } => write!(f, "mt{} {}", wait, fmt_payments(payments)),
+            Command::Batch { file, atomic, .. } => {
+                write!(f, "batch")?;
+                if *atomic {
+                    write!(f, " --atomic")?;
+                }
+                match file {
+                    Some(file) => write!(f, " {}", file.display()),
+                    None => Ok(()),
+                }
+            }
             Command::V => write!(f, "view"),
             Command::B => write!(f, "balance"),
+            Command::Block { blk } => write!(f, "block {}", blk),
+            Command::Blocks { range } => write!(f, "blocks {}", range),
+            Command::ChainInfo => write!(f, "chain-info"),
+            Command::Whoami => write!(f, "whoami"),
+            Command::Key { peer, format } => {
+                write!(f, "key")?;
+                if let Some(peer) = peer {
+                    write!(f, " {}", peer)?;
+                }
+                write!(
+                    f,
+                    " --format {}",
+                    format.to_possible_value().unwrap().get_name()
+                )
+            }
+            Command::Peers => write!(f, "peers"),
+            Command::Network => write!(f, "network"),
+            Command::Mempool => write!(f, "mempool"),
+            Command::AddressBook(AddressBookCommand::Add { name, peer }) => {
+                write!(f, "address-book add {} {}", name, peer)
+            }
+            Command::AddressBook(AddressBookCommand::List) => write!(f, "address-book list"),
+            Command::AddressBook(AddressBookCommand::Remove { name }) => {
+                write!(f, "address-book remove {}", name)
+            }
+            Command::Tx {
+                acc_id,
+                page,
+                page_size,
+            } => {
+                write!(f, "tx-history")?;
+                if let Some(id) = acc_id {
+                    write!(f, " {}", id)?;
+                }
+                write!(f, " --page {} --page-size {}", page, page_size)
+            }
+            Command::Status { hash } => write!(f, "status {}", hex::encode(hash)),
+            Command::Inbox {
+                from,
+                since,
+                until,
+                unread,
+                keep_unread,
+            } => {
+                write!(f, "inbox")?;
+                if let Some(id) = from {
+                    write!(f, " --from {}", id)?;
+                }
+                // the times are resolved to ms since the UNIX epoch by the client
+                if let Some(t) = since {
+                    write!(f, " --since {}", t / 1000)?;
+                }
+                if let Some(t) = until {
+                    write!(f, " --until {}", t / 1000)?;
+                }
+                if *unread {
+                    write!(f, " --unread")?;
+                }
+                if *keep_unread {
+                    write!(f, " --keep-unread")?;
+                }
+                Ok(())
+            }
+            Command::Subscribe { types } => {
+                write!(f, "subscribe")?;
+                for t in types {
+                    write!(f, " {}", t.to_possible_value().unwrap().get_name())?;
+                }
+                Ok(())
+            }
             Command::H => write!(f, "history"),
-            Command::Id => write!(f, "id"),
             Command::Time => write!(f, "time"),
             Command::Stats => write!(f, "stats"),
         }
     }
 }
+
+// the address of the daemon, given by the environment variables above
+pub fn daemon_addr() -> SocketAddr {
+    if let Ok(addr) = env::var(DAEMON_SOCKET_ENV) {
+        return addr
+            .to_socket_addrs()
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Environment variable `{}` could not be parsed as a valid socket address",
+                    DAEMON_SOCKET_ENV
+                )
+            })
+            .next()
+            .unwrap();
+    }
+
+    if let Ok(port) = env::var(DAEMON_PORT_ENV) {
+        let port = port.parse().unwrap_or_else(|_| {
+            panic!(
+                "Environment variable `{}` could not be parsed as a valid port number",
+                DAEMON_PORT_ENV
+            )
+        });
+
+        return SocketAddr::new(DEFAULT_DAEMON_IP, port);
+    };
+
+    SocketAddr::new(DEFAULT_DAEMON_IP, DEFAULT_DAEMON_PORT)
+}
+
+// parse a point in time given either as a UNIX timestamp in seconds
+// or as a duration before now (`<N>s`, `<N>m`, `<N>h` or `<N>d`)
+// returns the point in time in ms since the UNIX epoch (like block timestamps)
+fn parse_time(s: &str) -> Result<u128, String> {
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs).as_millis());
+    }
+
+    let (amnt, unit) = s.split_at(s.len().saturating_sub(1));
+    let secs_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err("expected a UNIX timestamp or a duration like `30m`".to_string()),
+    };
+    let amnt: u64 = amnt
+        .parse()
+        .map_err(|_| "expected a UNIX timestamp or a duration like `30m`".to_string())?;
+
+    let ago = Duration::from_secs(amnt.saturating_mul(secs_per_unit));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    Ok(now.saturating_sub(ago).as_millis())
+}
+
+// parse a network ID, a name in the address book, an address or a key fingerprint
+pub fn parse_recipient(s: &str) -> Result<Recipient, String> {
+    if let Ok(id) = s.parse() {
+        return Ok(Recipient::Id(id));
+    }
+
+    // names are shorter than addresses, so a long text with the prefix is a mistyped address
+    match s.parse::<Address>() {
+        Ok(addr) => return Ok(Recipient::Address(addr)),
+        Err(e) if s.starts_with(ADDRESS_PREFIX) && s.len() > address_book::MAX_NAME_LEN => {
+            return Err(format!("invalid address: {}", e))
+        }
+        Err(_) => {}
+    }
+
+    // (short) fingerprints can consist of decimal digits only as well
+    if crypto::is_fingerprint(s) {
+        return Ok(Recipient::Fingerprint(s.to_ascii_lowercase()));
+    }
+
+    address_book::validate_name(s)
+        .map(|_| Recipient::Alias(s.to_string()))
+        .map_err(|_| {
+            "expected a network ID, a name in the address book, an address or a key fingerprint"
+                .to_string()
+        })
+}
+
+// parse a recipient and an amount separated by `=`, e.g. `bob=5`
+fn parse_payment(s: &str) -> Result<Payment, String> {
+    let (rcp, amt) = s
+        .rsplit_once('=')
+        .ok_or_else(|| "expected a recipient and an amount, e.g. `bob=5`".to_string())?;
+
+    Ok(Payment {
+        rcp: parse_recipient(rcp)?,
+        amt: amt
+            .parse()
+            .map_err(|_| format!("invalid amount `{}` (expected a positive number)", amt))?,
+    })
+}
+
+// read the transactions of a batch, one per line (empty lines and comments are skipped)
+pub fn read_batch(reader: impl BufRead) -> io::Result<Vec<BatchItem>> {
+    let mut items = vec![];
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let item = BatchItem::parse(line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid batch file (line {}): {}", i + 1, e),
+            )
+        })?;
+        items.push(item);
+    }
+
+    if items.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The batch file contains no transactions",
+        ));
+    }
+
+    Ok(items)
+}
+
+// parse the hex encoded hash of a transaction
+fn parse_hash(s: &str) -> Result<[u8; 32], String> {
+    let mut hash = [0; 32];
+    hex::decode_to_slice(s, &mut hash).map_err(|e| format!("invalid transaction hash: {}", e))?;
+
+    Ok(hash)
+}
+
+// parse a block index or the hex encoded hash of a block
+fn parse_block_ref(s: &str) -> Result<BlockRef, String> {
+    let err = || "expected a block index or a 64 digit hex hash".to_string();
+
+    // hashes can consist of decimal digits only as well
+    if s.len() == 64 {
+        let mut hash = [0; 32];
+        hex::decode_to_slice(s, &mut hash).map_err(|_| err())?;
+
+        return Ok(BlockRef::Hash(hash));
+    }
+
+    s.parse().map(BlockRef::Index).map_err(|_| err())
+}
+
+// parse a range of block indices given as `<from>..<to>` or `<from>..`
+fn parse_block_range(s: &str) -> Result<BlockRange, String> {
+    let err = || "expected a range like `3..7` or `3..`".to_string();
+
+    let (from, to) = s.split_once("..").ok_or_else(err)?;
+    let from = from.parse().map_err(|_| err())?;
+    let to = match to {
+        "" => None,
+        to => Some(to.parse().map_err(|_| err())?),
+    };
+
+    if to.is_some_and(|to| to < from) {
+        return Err("the end of the range cannot be before its start".to_string());
+    }
+
+    Ok(BlockRange { from, to })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_batch() {
+        let file = "# rent\n\nt 1 5\n   # for bob\nm 2 hello   there\nstake 10\n";
+        let items = read_batch(file.as_bytes()).unwrap();
+        assert!(matches!(
+            items.as_slice(),
+            [
+                BatchItem::Transfer {
+                    rcp: Recipient::Id(1),
+                    amt,
+                },
+                BatchItem::Message {
+                    rcp: Recipient::Id(2),
+                    msg,
+                    encrypted: false,
+                },
+                BatchItem::Stake { .. },
+            ] if amt.get() == 5 && msg == "hello there"
+        ));
+
+        // a file of comments only has no transactions
+        assert!(read_batch("# nothing yet\n\n".as_bytes()).is_err());
+
+        // the first invalid line is reported
+        let e = read_batch("t 1 5\n# t 1 -w 5\nt 1 -w 5\n".as_bytes()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("(line 3)"));
+    }
+
+    #[test]
+    fn test_parse_batch_item() {
+        assert!(BatchItem::parse("mt 1=5 2=10").is_ok());
+        assert!(BatchItem::parse("t 1 5 --timeout 10").is_ok());
+
+        // the transactions are sent without waiting for them
+        for line in ["t 1 5 --wait", "m 1 hi -w", "stake 10 --confirmations 2"] {
+            assert_eq!(
+                BatchItem::parse(line).unwrap_err(),
+                "the transactions of a batch cannot be waited for"
+            );
+        }
+
+        // only transactions can be part of a batch
+        for line in ["balance", "view", "batch other.txt"] {
+            assert!(BatchItem::parse(line)
+                .unwrap_err()
+                .contains("cannot be part of a batch"));
+        }
+        assert!(BatchItem::parse("transfer 1 5").is_err());
+    }
+}
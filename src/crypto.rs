@@ -1,8 +1,14 @@
+use crate::address::Address;
+use clap::ValueEnum;
 use hex::{self, ToHex};
 use rsa::{
-    pkcs1::{EncodeRsaPrivateKey as _, EncodeRsaPublicKey as _},
-    sha2::Sha256,
-    Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey,
+    pkcs1::{
+        DecodeRsaPrivateKey as _, DecodeRsaPublicKey as _, EncodeRsaPrivateKey as _,
+        EncodeRsaPublicKey as _, LineEnding,
+    },
+    pkcs8::DecodePublicKey as _,
+    sha2::{Digest as _, Sha256},
+    Oaep, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -17,16 +23,33 @@ use std::{
     They are defined as such to provide a more ergonomic API for the user,
     and to allow for changing the implementation details in the future
     without affecting the rest of the codebase.
+
+    Keys are identified to users by their fingerprint, the SHA-256 hash of the public key
+    (PKCS#1 DER), printed out as its first `FINGERPRINT_HEX_LEN` hex digits by default.
+    Public keys can be exported in full as PEM, DER or hex (of the DER), and parsed back.
 */
 
-// how many hex digits to keep when printing out keys to the console
-const KEY_HEX_LEN: usize = 8;
+// how many hex digits of a fingerprint are printed out to the console by default
+pub const FINGERPRINT_HEX_LEN: usize = 16;
+
+// how many hex digits a full fingerprint has
+pub const FULL_FINGERPRINT_HEX_LEN: usize = 64;
+
+// the formats a public key can be exported to and parsed from
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyFormat {
+    // PKCS#1 PEM (SPKI PEM, e.g. from OpenSSL, is accepted as well when parsing)
+    Pem,
+    // PKCS#1 DER (binary, so the client cannot print it)
+    #[value(skip)]
+    Der,
+    // PKCS#1 DER encoded to hex
+    Hex,
+}
 
 // private key
 
-// how many bytes to skip when encoding a private key to hex
-const PRIVATE_PKCS1_DER_HEADER_LEN: usize = 12;
-
 #[derive(Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct PrivateKey(RsaPrivateKey);
 
@@ -39,9 +62,23 @@ impl PrivateKey {
         self.0.to_pkcs1_der().unwrap().as_bytes().to_vec()
     }
 
+    pub fn to_pem(&self) -> String {
+        self.0.to_pkcs1_pem(LineEnding::LF).unwrap().to_string()
+    }
+
+    pub fn from_pem(pem: &str) -> Option<Self> {
+        RsaPrivateKey::from_pkcs1_pem(pem).ok().map(Self)
+    }
+
     pub fn sign(&self, message: &[u8]) -> Vec<u8> {
         self.0.sign(Pkcs1v15Sign::new::<Sha256>(), message).unwrap()
     }
+
+    // decrypt a ciphertext created by `PublicKey::encrypt()`
+    // returns None if it was encrypted with a different key or has been tampered with
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        self.0.decrypt(Oaep::new::<Sha256>(), ciphertext).ok()
+    }
 }
 
 impl From<RsaPrivateKey> for PrivateKey {
@@ -50,39 +87,17 @@ impl From<RsaPrivateKey> for PrivateKey {
     }
 }
 
-impl ToHex for PrivateKey {
-    fn encode_hex<T: FromIterator<char>>(&self) -> T {
-        let start = PRIVATE_PKCS1_DER_HEADER_LEN;
-        let end = start + KEY_HEX_LEN;
-
-        // encode only the key itself, not the DER header
-        let der = self.0.to_pkcs1_der().unwrap();
-        let key = &der.as_bytes()[start..end];
-        key.encode_hex()
-    }
-
-    fn encode_hex_upper<T: FromIterator<char>>(&self) -> T {
-        let start = PRIVATE_PKCS1_DER_HEADER_LEN;
-        let end = start + KEY_HEX_LEN;
-
-        // encode only the key itself, not the DER header
-        let der = self.0.to_pkcs1_der().unwrap();
-        let key = &der.as_bytes()[start..end];
-        key.encode_hex_upper()
-    }
-}
-
+// nothing of the private key itself is printed, only the fingerprint of its public key
 impl Debug for PrivateKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        self.encode_hex::<String>().fmt(f)
+        f.debug_tuple("PrivateKey")
+            .field(&self.to_publ_key().fingerprint())
+            .finish()
     }
 }
 
 // public key
 
-// how many bytes to skip when encoding a public key to hex
-const PUBLIC_PKCS1_DER_HEADER_LEN: usize = 9;
-
 #[derive(Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct PublicKey(RsaPublicKey);
 
@@ -91,11 +106,95 @@ impl PublicKey {
         self.0.to_pkcs1_der().unwrap().as_bytes().to_vec()
     }
 
+    pub fn to_pem(&self) -> String {
+        self.0.to_pkcs1_pem(LineEnding::LF).unwrap()
+    }
+
+    pub fn from_pem(pem: &str) -> Option<Self> {
+        RsaPublicKey::from_pkcs1_pem(pem)
+            .or_else(|_| RsaPublicKey::from_public_key_pem(pem))
+            .ok()
+            .map(Self)
+    }
+
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        RsaPublicKey::from_pkcs1_der(der)
+            .or_else(|_| RsaPublicKey::from_public_key_der(der))
+            .ok()
+            .map(Self)
+    }
+
+    pub fn from_hex(s: &str) -> Option<Self> {
+        Self::from_der(&hex::decode(s.trim()).ok()?)
+    }
+
+    pub fn export(&self, format: KeyFormat) -> Vec<u8> {
+        match format {
+            KeyFormat::Pem => self.to_pem().into_bytes(),
+            KeyFormat::Der => self.to_der(),
+            KeyFormat::Hex => self.encode_hex::<String>().into_bytes(),
+        }
+    }
+
+    pub fn import(data: &[u8], format: KeyFormat) -> Option<Self> {
+        match format {
+            KeyFormat::Pem => Self::from_pem(std::str::from_utf8(data).ok()?),
+            KeyFormat::Der => Self::from_der(data),
+            KeyFormat::Hex => Self::from_hex(std::str::from_utf8(data).ok()?),
+        }
+    }
+
+    // parse a key exported as text, in either PEM or hex
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.trim_start().starts_with("-----BEGIN") {
+            Self::from_pem(s)
+        } else {
+            Self::from_hex(s)
+        }
+    }
+
+    // the SHA-256 hash of the key
+    pub fn fingerprint_bytes(&self) -> [u8; 32] {
+        Sha256::digest(self.to_der()).into()
+    }
+
+    // identifies the key to users, e.g. in the output of `whoami` and `peers`
+    pub fn fingerprint(&self) -> String {
+        self.fingerprint_with_len(FINGERPRINT_HEX_LEN)
+    }
+
+    // the first `len` hex digits of the fingerprint (all of them if `len` is larger)
+    pub fn fingerprint_with_len(&self, len: usize) -> String {
+        let mut fingerprint: String = self.fingerprint_bytes().encode_hex();
+        fingerprint.truncate(len);
+        fingerprint
+    }
+
+    // the checksummed address of the account that owns the key
+    pub fn address(&self) -> Address {
+        Address::from_publ_key(self)
+    }
+
     pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
         self.0
             .verify(Pkcs1v15Sign::new::<Sha256>(), msg, sig)
             .is_ok()
     }
+
+    // encrypt a short message (e.g. a symmetric key) with RSA-OAEP
+    // so that only the owner of the private key can read it
+    pub fn encrypt(&self, msg: &[u8]) -> Vec<u8> {
+        self.0
+            .encrypt(&mut rand::thread_rng(), Oaep::new::<Sha256>(), msg)
+            .unwrap()
+    }
+}
+
+// whether the text has the form of a key fingerprint, printed out with at least the default
+// number of hex digits (its case is ignored)
+pub fn is_fingerprint(s: &str) -> bool {
+    (FINGERPRINT_HEX_LEN..=FULL_FINGERPRINT_HEX_LEN).contains(&s.len())
+        && s.chars().all(|c| c.is_ascii_hexdigit())
 }
 
 impl From<RsaPublicKey> for PublicKey {
@@ -104,30 +203,48 @@ impl From<RsaPublicKey> for PublicKey {
     }
 }
 
+// the whole key (PKCS#1 DER)
 impl ToHex for PublicKey {
     fn encode_hex<T: FromIterator<char>>(&self) -> T {
-        let start = PUBLIC_PKCS1_DER_HEADER_LEN;
-        let end = start + KEY_HEX_LEN;
-
-        // encode only the key itself, not the DER header
-        let der = self.0.to_pkcs1_der().unwrap();
-        let key = &der.as_bytes()[start..end];
-        key.encode_hex()
+        self.to_der().encode_hex()
     }
 
     fn encode_hex_upper<T: FromIterator<char>>(&self) -> T {
-        let start = PUBLIC_PKCS1_DER_HEADER_LEN;
-        let end = start + KEY_HEX_LEN;
-
-        // encode only the key itself, not the DER header
-        let der = self.0.to_pkcs1_der().unwrap();
-        let key = &der.as_bytes()[start..end];
-        key.encode_hex_upper()
+        self.to_der().encode_hex_upper()
     }
 }
 
 impl Debug for PublicKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        self.encode_hex::<String>().fmt(f)
+        f.debug_tuple("PublicKey")
+            .field(&self.fingerprint())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_and_parse() {
+        let priv_key = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+        let publ_key = priv_key.to_publ_key();
+
+        for format in [KeyFormat::Pem, KeyFormat::Der, KeyFormat::Hex] {
+            let exported = publ_key.export(format);
+            assert_eq!(PublicKey::import(&exported, format), Some(publ_key.clone()));
+        }
+        assert_eq!(PublicKey::parse(&publ_key.to_pem()), Some(publ_key.clone()));
+        assert_eq!(
+            PublicKey::parse(&publ_key.encode_hex::<String>()),
+            Some(publ_key.clone())
+        );
+        assert_eq!(PublicKey::parse("not a key"), None);
+
+        let fingerprint = publ_key.fingerprint_with_len(FULL_FINGERPRINT_HEX_LEN);
+        assert_eq!(fingerprint.len(), FULL_FINGERPRINT_HEX_LEN);
+        assert!(fingerprint.starts_with(&publ_key.fingerprint()));
+        assert!(is_fingerprint(&fingerprint) && is_fingerprint(&publ_key.fingerprint()));
     }
 }
@@ -0,0 +1,204 @@
+use crate::{
+    blockchain::{block::Block, transaction::Transaction},
+    inbox::InboxEntry,
+    peer::PeersCatalog,
+    rpc::TransactionSummary,
+};
+use clap::ValueEnum;
+use hex::ToHex as _;
+use serde::{Deserialize, Serialize};
+
+/*
+    Events are pushed to the clients that have subscribed to them (see `subscribe`),
+    one JSON object per line, so that they can react to the activity of the node
+    without polling it.
+
+    Unlike the debug history, events are only about the local node:
+    - a block is added to the local blockchain
+    - a transaction is added to the local pending transactions
+    - the validator of the next block is elected
+    - a transaction or block is found to be invalid (or a pending transaction is dropped)
+    - a message addressed to the local account is included in a block
+
+    Hashes are hex encoded, amounts are in BCC and timestamps are in ms since the UNIX epoch
+    (as u64, since internally tagged enums cannot be deserialized with u128 fields).
+*/
+
+// what a client can subscribe to
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ValueEnum)]
+pub enum EventType {
+    Block,
+    Transaction,
+    Validator,
+    Invalid,
+    Message,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Block {
+        index: u32,
+        hash: String,
+        validator: Option<u32>, // None for the genesis block
+        timestamp: u64,
+        transactions: Vec<String>,
+    },
+    Transaction(TransactionSummary),
+    // the validator that will mint the block after the given one
+    Validator {
+        after_block: u32,
+        validator: u32,
+    },
+    InvalidTransaction {
+        hash: String,
+        reason: String,
+    },
+    InvalidBlock {
+        hash: String,
+        reason: String,
+    },
+    Message {
+        hash: String,
+        sender: u32,
+        block: u32,
+        timestamp: u64,
+        message: Option<String>, // None if it could not be decrypted
+        encrypted: bool,
+    },
+}
+
+impl Event {
+    pub fn from_block(blk: &Block, peers: &PeersCatalog) -> Self {
+        Self::Block {
+            index: blk.index(),
+            hash: blk.hash().encode_hex(),
+            validator: blk
+                .val()
+                .and_then(|v| peers.get_by_publ_key(v))
+                .map(|p| p.id()),
+            timestamp: blk.timestamp() as u64,
+            transactions: blk.tsxs().iter().map(|t| t.hash().encode_hex()).collect(),
+        }
+    }
+
+    pub fn from_transaction(tsx: &Transaction, peers: &PeersCatalog) -> Self {
+        Self::Transaction(TransactionSummary::from_transaction(tsx, peers))
+    }
+
+    pub fn from_inbox_entry(entry: &InboxEntry) -> Self {
+        Self::Message {
+            hash: entry.tsx_hash().encode_hex(),
+            sender: entry.sndr_id(),
+            block: entry.block_index(),
+            timestamp: entry.timestamp() as u64,
+            message: entry.message().map(str::to_string),
+            encrypted: entry.is_encrypted(),
+        }
+    }
+
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Self::Block { .. } => EventType::Block,
+            Self::Transaction(_) => EventType::Transaction,
+            Self::Validator { .. } => EventType::Validator,
+            Self::InvalidTransaction { .. } | Self::InvalidBlock { .. } => EventType::Invalid,
+            Self::Message { .. } => EventType::Message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn invalid_block() -> Event {
+        Event::InvalidBlock {
+            hash: "ab".to_string(),
+            reason: "Invalid signature".to_string(),
+        }
+    }
+
+    fn transaction() -> Event {
+        Event::Transaction(TransactionSummary {
+            hash: "cd".to_string(),
+            kind: "transfer".to_string(),
+            sender: Some(1),
+            recipient: Some(2),
+            amount: 1.5,
+            fees: 0.03,
+            outputs: vec![],
+        })
+    }
+
+    #[test]
+    fn test_tag() {
+        // the type is a field of the event, next to its own fields
+        assert_eq!(
+            serde_json::to_value(invalid_block()).unwrap(),
+            json!({ "type": "invalid_block", "hash": "ab", "reason": "Invalid signature" })
+        );
+
+        let value = serde_json::to_value(transaction()).unwrap();
+        assert_eq!(value["type"], "transaction");
+        assert_eq!(value["hash"], "cd");
+        assert_eq!(value["sender"], 1);
+
+        // events are read back by the client
+        let line = serde_json::to_string(&transaction()).unwrap();
+        assert!(matches!(
+            serde_json::from_str(&line).unwrap(),
+            Event::Transaction(TransactionSummary {
+                recipient: Some(2),
+                ..
+            })
+        ));
+
+        let line = r#"{"type":"block","index":3,"hash":"ef","validator":null,"timestamp":1000,"transactions":[]}"#;
+        assert!(matches!(
+            serde_json::from_str(line).unwrap(),
+            Event::Block {
+                index: 3,
+                validator: None,
+                timestamp: 1000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_event_type() {
+        let block = Event::Block {
+            index: 1,
+            hash: "ab".to_string(),
+            validator: Some(0),
+            timestamp: 1000,
+            transactions: vec![],
+        };
+        let validator = Event::Validator {
+            after_block: 1,
+            validator: 2,
+        };
+        let invalid_tsx = Event::InvalidTransaction {
+            hash: "ab".to_string(),
+            reason: "Not enough coins".to_string(),
+        };
+        let message = Event::Message {
+            hash: "ab".to_string(),
+            sender: 1,
+            block: 2,
+            timestamp: 1000,
+            message: None,
+            encrypted: true,
+        };
+
+        assert_eq!(block.event_type(), EventType::Block);
+        assert_eq!(transaction().event_type(), EventType::Transaction);
+        assert_eq!(validator.event_type(), EventType::Validator);
+        // both kinds of invalid events are subscribed to together
+        assert_eq!(invalid_tsx.event_type(), EventType::Invalid);
+        assert_eq!(invalid_block().event_type(), EventType::Invalid);
+        assert_eq!(message.event_type(), EventType::Message);
+    }
+}
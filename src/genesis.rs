@@ -0,0 +1,246 @@
+use crate::crypto::PublicKey;
+use rsa::sha2::{Digest as _, Sha256};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path, sync::OnceLock};
+use thiserror::Error;
+
+/*
+    The Genesis struct describes a BlockChat network (the chain spec): the denomination
+    of the coins, the fees, the block capacity, the coins every peer starts with,
+    as well as specific initial allocations and stakes per public key.
+
+    It is loaded from a JSON file, or defaults to the values BlockChat has always used.
+    The chain parameters are needed all over the codebase (e.g. to calculate the fees of
+    a transaction), so they are installed globally once, during bootstrapping.
+    Every node hashes its genesis, and the bootstrap process makes sure that all of them agree.
+
+    Example genesis file (every field is optional):
+
+    {
+        "cents_per_coin": 100,
+        "transfer_fee_percentage": 3,
+        "message_fee_per_character_cents": 100,
+        "minimum_transfer_fee_cents": 1,
+        "block_capacity": 5,
+        "init_coins_per_peer": 1000,
+        "timestamp": 0,
+        "allocations": [
+            { "publ_key": "<public key>", "coins": 5000, "stake": 10 }
+        ]
+    }
+*/
+
+static CHAIN_PARAMS: OnceLock<ChainParams> = OnceLock::new();
+
+#[derive(Error, Debug)]
+pub enum GenesisError {
+    #[error("The genesis file could not be read: {0}")]
+    Io(#[from] io::Error),
+    #[error("The genesis file could not be parsed: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("The {0} must be greater than 0")]
+    ZeroParameter(&'static str),
+    #[error("The {0} must be at most {1}")]
+    TooLargeParameter(&'static str, u32),
+    #[error("The public key at allocation {0} is allocated more than once")]
+    DuplicateAllocation(usize),
+    #[error("The {0} are too many to be counted in cents")]
+    TooManyCoins(&'static str),
+    #[error("The coins or stake of allocation {0} are too many to be counted in cents")]
+    TooManyAllocatedCoins(usize),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ChainParams {
+    // the user sees coins as floating point numbers
+    // but the program uses integers to avoid floating point errors
+    // so whenever the user wants to create a transaction
+    // a conversion is needed
+    pub cents_per_coin: u32,
+    pub transfer_fee_percentage: u32,
+    pub message_fee_per_character_cents: u32,
+    pub minimum_transfer_fee_cents: u32,
+    pub block_capacity: usize,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            cents_per_coin: 100,
+            transfer_fee_percentage: 3,
+            message_fee_per_character_cents: 100,
+            minimum_transfer_fee_cents: 1,
+            block_capacity: 5,
+        }
+    }
+}
+
+// the parameters of the network the node is part of
+// if no genesis has been installed yet, the default parameters are used
+pub fn chain_params() -> &'static ChainParams {
+    CHAIN_PARAMS.get_or_init(ChainParams::default)
+}
+
+// coins and stake a specific peer starts with (instead of `init_coins_per_peer`)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Allocation {
+    pub publ_key: PublicKey,
+    #[serde(default)]
+    pub coins: u32,
+    #[serde(default)]
+    pub stake: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Genesis {
+    #[serde(flatten)]
+    pub params: ChainParams,
+    pub init_coins_per_peer: u32,
+    // the timestamp of the genesis block of networks with static membership
+    // (otherwise the time the network is bootstrapped is used)
+    pub timestamp: u64,
+    pub allocations: Vec<Allocation>,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Self {
+            params: ChainParams::default(),
+            init_coins_per_peer: 1000,
+            timestamp: 0,
+            allocations: vec![],
+        }
+    }
+}
+
+impl Genesis {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GenesisError> {
+        let genesis: Self = serde_json::from_slice(&fs::read(path)?)?;
+        genesis.validate()?;
+
+        Ok(genesis)
+    }
+
+    pub fn validate(&self) -> Result<(), GenesisError> {
+        use GenesisError::*;
+
+        if self.params.cents_per_coin == 0 {
+            return Err(ZeroParameter("cents per coin"));
+        }
+
+        if self.params.block_capacity == 0 {
+            return Err(ZeroParameter("block capacity"));
+        }
+
+        // a transfer cannot cost more in fees than it sends
+        if self.params.transfer_fee_percentage > 100 {
+            return Err(TooLargeParameter("transfer fee percentage", 100));
+        }
+
+        // a single character cannot cost more than a coin
+        if self.params.message_fee_per_character_cents > self.params.cents_per_coin {
+            return Err(TooLargeParameter(
+                "message fee per character",
+                self.params.cents_per_coin,
+            ));
+        }
+
+        // the initial balances are converted to cents by `initial_balance()`
+        let cents_per_coin = self.params.cents_per_coin;
+        if self
+            .init_coins_per_peer
+            .checked_mul(cents_per_coin)
+            .is_none()
+        {
+            return Err(TooManyCoins("initial coins per peer"));
+        }
+
+        for (i, alloc) in self.allocations.iter().enumerate() {
+            if alloc.coins.checked_mul(cents_per_coin).is_none()
+                || alloc.stake.checked_mul(cents_per_coin).is_none()
+            {
+                return Err(TooManyAllocatedCoins(i));
+            }
+
+            if self.allocations[..i]
+                .iter()
+                .any(|a| a.publ_key == alloc.publ_key)
+            {
+                return Err(DuplicateAllocation(i));
+            }
+        }
+
+        Ok(())
+    }
+
+    // make the chain parameters of this genesis available through `chain_params()`
+    // panics if different parameters are already in use
+    pub fn install(&self) {
+        let params = CHAIN_PARAMS.get_or_init(|| self.params.clone());
+
+        assert!(
+            *params == self.params,
+            "The chain parameters cannot change once they are in use"
+        );
+    }
+
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        let bytes = serde_json::to_vec(self).expect("Failed to serialize genesis");
+        Sha256::digest(bytes).into()
+    }
+
+    // the cents and staked cents the given peer starts with
+    // the genesis must be valid, so that the balances fit in cents
+    pub fn initial_balance(&self, publ_key: &PublicKey) -> (u32, u32) {
+        let cents_per_coin = self.params.cents_per_coin;
+
+        match self.allocations.iter().find(|a| a.publ_key == *publ_key) {
+            Some(alloc) => (alloc.coins * cents_per_coin, alloc.stake * cents_per_coin),
+            None => (self.init_coins_per_peer * cents_per_coin, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_overflow() {
+        assert!(Genesis::default().validate().is_ok());
+
+        let genesis = Genesis {
+            init_coins_per_peer: u32::MAX / 100 + 1,
+            ..Genesis::default()
+        };
+        assert!(matches!(
+            genesis.validate(),
+            Err(GenesisError::TooManyCoins(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_fees() {
+        let with_params = |transfer_fee_percentage, message_fee_per_character_cents| Genesis {
+            params: ChainParams {
+                transfer_fee_percentage,
+                message_fee_per_character_cents,
+                ..ChainParams::default()
+            },
+            ..Genesis::default()
+        };
+
+        assert!(with_params(100, 100).validate().is_ok());
+        assert!(with_params(0, 0).validate().is_ok());
+        assert!(matches!(
+            with_params(101, 100).validate(),
+            Err(GenesisError::TooLargeParameter(_, 100))
+        ));
+        assert!(matches!(
+            with_params(3, u32::MAX).validate(),
+            Err(GenesisError::TooLargeParameter(_, 100))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::BTreeMap,
     fmt::{self, Display, Formatter},
+    sync::Mutex,
 };
 
 use serde::{Deserialize, Serialize};
@@ -11,20 +12,22 @@ use crate::{
         transaction::{Transaction, TransactionPayload},
         Blockchain,
     },
+    crypto::PrivateKey,
+    genesis::chain_params,
     peer::PeersCatalog,
-    protocol::CENTS_PER_COIN,
 };
 
-static mut GLOBAL_HISTORY: History = History(vec![]);
+// the events are logged from the threads of the daemon as well as read by them
+static GLOBAL_HISTORY: Mutex<History> = Mutex::new(History(vec![]));
 
 // A struct to keep track of the history of the blockchain
 // used only for debugging purposes.
 
 /*
     The following are considered noteworthy events:
-    - a transaction (transfer, message, stake) is created locally
+    - a transaction (transfer, message, stake, multi-transfer) is created locally
     - a block is created locally
-    - a transaction (transfer, message, stake) is received from the network
+    - a transaction (transfer, message, stake, multi-transfer) is received from the network
     - a block is received from the network
     - a transaction is found to be invalid
     - a block is found to be invalid
@@ -37,38 +40,77 @@ static mut GLOBAL_HISTORY: History = History(vec![]);
 
     Each transaction event also includes the source and destination account ids,
     as well as the amount or message of the transaction
+    (multi-transfers have no destination, but list the id and amount of every recipient)
+    (encrypted messages are only logged in plain text by their recipient)
 
     Each block event includes the validator's id and the ids of the transactions in the block
 */
 
-#[derive(Clone, Serialize, Deserialize)]
+// the names are the tags of the events in the log
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 enum EventKind {
     // Local Transfer
-    LT { amount: f64 },
+    LT {
+        amount: f64,
+    },
     // Local Message
-    LM { message: String },
+    LM {
+        message: String,
+    },
+    // Local Encrypted message
+    LE {
+        length: usize,
+    },
     // Local Stake
-    LS { amount: f64 },
+    LS {
+        amount: f64,
+    },
+    // Local Multi-transfer
+    LMT {
+        outputs: Vec<(u32, f64)>,
+    },
     // Local Block
-    LB { tids: Vec<String> },
+    LB {
+        tids: Vec<String>,
+    },
     // Network Transfer
-    NT { amount: f64 },
+    NT {
+        amount: f64,
+    },
     // Network Message
-    NM { message: String },
+    NM {
+        message: String,
+    },
+    // Network Encrypted message (None if the local peer is not the recipient)
+    NE {
+        length: usize,
+        message: Option<String>,
+    },
     // Network Stake
-    NS { amount: f64 },
+    NS {
+        amount: f64,
+    },
+    // Network Multi-transfer
+    NMT {
+        outputs: Vec<(u32, f64)>,
+    },
     // Network Block
-    NB { tids: Vec<String> },
+    NB {
+        tids: Vec<String>,
+    },
     // Invalid Transaction
     IT,
     // Invalid Block
     IB,
     // New Validator Elected
-    NV { vid: u32 },
+    NV {
+        vid: u32,
+    },
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Event {
     id: String,
     src: u32,
@@ -78,12 +120,12 @@ struct Event {
     kind: EventKind,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct History(Vec<Event>);
 
 impl History {
     pub fn global_stats() -> String {
-        let history = unsafe { GLOBAL_HISTORY.clone() };
+        let history = GLOBAL_HISTORY.lock().unwrap().clone();
 
         // number of transactions sent by each peer
         // create a BTreeMap with id as index and total transactions as value
@@ -93,10 +135,14 @@ impl History {
             match &event.kind {
                 EventKind::LT { .. }
                 | EventKind::LM { .. }
+                | EventKind::LE { .. }
                 | EventKind::LS { .. }
+                | EventKind::LMT { .. }
                 | EventKind::NT { .. }
                 | EventKind::NM { .. }
-                | EventKind::NS { .. } => {
+                | EventKind::NE { .. }
+                | EventKind::NS { .. }
+                | EventKind::NMT { .. } => {
                     total_tsx += 1;
                     *txs_sent.entry(event.src).or_insert(0) += 1;
                 }
@@ -182,7 +228,7 @@ impl History {
     }
 
     pub fn global_history() -> History {
-        unsafe { GLOBAL_HISTORY.clone() }
+        GLOBAL_HISTORY.lock().unwrap().clone()
     }
 
     pub fn log_local_transaction(tsx: &Transaction, peers: &PeersCatalog) {
@@ -190,6 +236,10 @@ impl History {
             TransactionPayload::Transfer(_) => Self::log_local_transfer(tsx, peers),
             TransactionPayload::Message(_) => Self::log_local_message(tsx, peers),
             TransactionPayload::Stake(_) => Self::log_local_stake(tsx, peers),
+            TransactionPayload::EncryptedMessage(_) => {
+                Self::log_local_encrypted_message(tsx, peers)
+            }
+            TransactionPayload::MultiTransfer(_) => Self::log_local_multi_transfer(tsx, peers),
         }
     }
 
@@ -211,11 +261,12 @@ impl History {
                     .id(),
             ),
             kind: EventKind::LT {
-                amount: tsx.payload().coins().unwrap() as f64 / CENTS_PER_COIN as f64,
+                amount: tsx.payload().coins().unwrap() as f64
+                    / chain_params().cents_per_coin as f64,
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     fn log_local_message(tsx: &Transaction, peers: &PeersCatalog) {
@@ -240,7 +291,32 @@ impl History {
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
+    }
+
+    fn log_local_encrypted_message(tsx: &Transaction, peers: &PeersCatalog) {
+        let TransactionPayload::EncryptedMessage(msg) = tsx.payload() else {
+            panic!("Expected an encrypted message");
+        };
+
+        let src = peers
+            .get_by_publ_key(tsx.sndr_addr().unwrap())
+            .unwrap()
+            .id();
+
+        let event = Event {
+            id: format!("E{}-{}", src, tsx.nonce()),
+            src,
+            dst: Some(
+                peers
+                    .get_by_publ_key(tsx.recp_addr().unwrap())
+                    .unwrap()
+                    .id(),
+            ),
+            kind: EventKind::LE { length: msg.len() },
+        };
+
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     fn log_local_stake(tsx: &Transaction, peers: &PeersCatalog) {
@@ -256,11 +332,30 @@ impl History {
             src,
             dst: None,
             kind: EventKind::LS {
-                amount: tsx.payload().coins().unwrap() as f64 / CENTS_PER_COIN as f64,
+                amount: tsx.payload().coins().unwrap() as f64
+                    / chain_params().cents_per_coin as f64,
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
+    }
+
+    fn log_local_multi_transfer(tsx: &Transaction, peers: &PeersCatalog) {
+        let src = peers
+            .get_by_publ_key(tsx.sndr_addr().unwrap())
+            .unwrap()
+            .id();
+
+        let event = Event {
+            id: format!("MT{}-{}", src, tsx.nonce()),
+            src,
+            dst: None,
+            kind: EventKind::LMT {
+                outputs: Self::multi_transfer_outputs(tsx, peers),
+            },
+        };
+
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     pub fn log_local_block(block: &Block, peers: &PeersCatalog) {
@@ -283,6 +378,8 @@ impl History {
                                 TransactionPayload::Transfer(_) => "T",
                                 TransactionPayload::Message(_) => "M",
                                 TransactionPayload::Stake(_) => "S",
+                                TransactionPayload::EncryptedMessage(_) => "E",
+                                TransactionPayload::MultiTransfer(_) => "MT",
                             },
                             src,
                             tsx.nonce()
@@ -292,14 +389,19 @@ impl History {
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
-    pub fn log_network_transaction(tsx: &Transaction, peers: &PeersCatalog) {
+    // the private key is used to read encrypted messages sent to the local peer
+    pub fn log_network_transaction(tsx: &Transaction, peers: &PeersCatalog, priv_key: &PrivateKey) {
         match tsx.payload() {
             TransactionPayload::Transfer(_) => Self::log_network_transfer(tsx, peers),
             TransactionPayload::Message(_) => Self::log_network_message(tsx, peers),
             TransactionPayload::Stake(_) => Self::log_network_stake(tsx, peers),
+            TransactionPayload::EncryptedMessage(_) => {
+                Self::log_network_encrypted_message(tsx, peers, priv_key)
+            }
+            TransactionPayload::MultiTransfer(_) => Self::log_network_multi_transfer(tsx, peers),
         }
     }
 
@@ -321,11 +423,12 @@ impl History {
                     .id(),
             ),
             kind: EventKind::NT {
-                amount: tsx.payload().coins().unwrap() as f64 / CENTS_PER_COIN as f64,
+                amount: tsx.payload().coins().unwrap() as f64
+                    / chain_params().cents_per_coin as f64,
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     fn log_network_message(tsx: &Transaction, peers: &PeersCatalog) {
@@ -350,7 +453,39 @@ impl History {
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
+    }
+
+    fn log_network_encrypted_message(
+        tsx: &Transaction,
+        peers: &PeersCatalog,
+        priv_key: &PrivateKey,
+    ) {
+        let TransactionPayload::EncryptedMessage(msg) = tsx.payload() else {
+            panic!("Expected an encrypted message");
+        };
+
+        let src = peers
+            .get_by_publ_key(tsx.sndr_addr().unwrap())
+            .unwrap()
+            .id();
+
+        let event = Event {
+            id: format!("E{}-{}", src, tsx.nonce()),
+            src,
+            dst: Some(
+                peers
+                    .get_by_publ_key(tsx.recp_addr().unwrap())
+                    .unwrap()
+                    .id(),
+            ),
+            kind: EventKind::NE {
+                length: msg.len(),
+                message: msg.open(priv_key),
+            },
+        };
+
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     fn log_network_stake(tsx: &Transaction, peers: &PeersCatalog) {
@@ -366,11 +501,47 @@ impl History {
             src,
             dst: None,
             kind: EventKind::NS {
-                amount: tsx.payload().coins().unwrap() as f64 / CENTS_PER_COIN as f64,
+                amount: tsx.payload().coins().unwrap() as f64
+                    / chain_params().cents_per_coin as f64,
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
+    }
+
+    fn log_network_multi_transfer(tsx: &Transaction, peers: &PeersCatalog) {
+        let src = peers
+            .get_by_publ_key(tsx.sndr_addr().unwrap())
+            .unwrap()
+            .id();
+
+        let event = Event {
+            id: format!("MT{}-{}", src, tsx.nonce()),
+            src,
+            dst: None,
+            kind: EventKind::NMT {
+                outputs: Self::multi_transfer_outputs(tsx, peers),
+            },
+        };
+
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
+    }
+
+    // the id and amount (in BCC) of every recipient of a multi-transfer
+    fn multi_transfer_outputs(tsx: &Transaction, peers: &PeersCatalog) -> Vec<(u32, f64)> {
+        let TransactionPayload::MultiTransfer(outputs) = tsx.payload() else {
+            panic!("Expected a multi-transfer");
+        };
+
+        outputs
+            .iter()
+            .map(|out| {
+                (
+                    peers.get_by_publ_key(out.recp_addr()).unwrap().id(),
+                    out.amnt().get() as f64 / chain_params().cents_per_coin as f64,
+                )
+            })
+            .collect()
     }
 
     pub fn log_network_block(block: &Block, peers: &PeersCatalog) {
@@ -393,6 +564,8 @@ impl History {
                                 TransactionPayload::Transfer(_) => "T",
                                 TransactionPayload::Message(_) => "M",
                                 TransactionPayload::Stake(_) => "S",
+                                TransactionPayload::EncryptedMessage(_) => "E",
+                                TransactionPayload::MultiTransfer(_) => "MT",
                             },
                             src,
                             tsx.nonce()
@@ -402,7 +575,7 @@ impl History {
             },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     pub fn log_invalid_transaction(tsx: &Transaction, peers: &PeersCatalog) {
@@ -411,19 +584,18 @@ impl History {
             .unwrap()
             .id();
 
+        // stakes and multi-transfers have no recipient
         let event = Event {
             id: format!("IT{}-{}", src, tsx.nonce()),
             src,
-            dst: Some(
-                peers
-                    .get_by_publ_key(tsx.recp_addr().unwrap())
-                    .unwrap()
-                    .id(),
-            ),
+            dst: tsx
+                .recp_addr()
+                .and_then(|addr| peers.get_by_publ_key(addr))
+                .map(|p| p.id()),
             kind: EventKind::IT,
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     pub fn log_invalid_block(block: &Block, peers: &PeersCatalog) {
@@ -434,7 +606,7 @@ impl History {
             kind: EventKind::IB,
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 
     pub fn log_new_validator(local_id: u32, vid: u32, blockchain: &Blockchain) {
@@ -445,7 +617,7 @@ impl History {
             kind: EventKind::NV { vid },
         };
 
-        unsafe { GLOBAL_HISTORY.0.push(event) };
+        GLOBAL_HISTORY.lock().unwrap().0.push(event);
     }
 }
 
@@ -471,9 +643,21 @@ impl Display for History {
                         message
                     )?;
                 }
+                EventKind::LE { length } => {
+                    writeln!(
+                        f,
+                        "{} self to {} | <encrypted, {} bytes>",
+                        event.id,
+                        event.dst.unwrap(),
+                        length
+                    )?;
+                }
                 EventKind::LS { amount } => {
                     writeln!(f, "{} self | {} BCC", event.id, amount)?;
                 }
+                EventKind::LMT { outputs } => {
+                    writeln!(f, "{} self to {}", event.id, fmt_outputs(outputs))?;
+                }
                 EventKind::LB { tids } => {
                     writeln!(f, "{} by self | {:?}", event.id, tids)?;
                 }
@@ -497,9 +681,30 @@ impl Display for History {
                         message
                     )?;
                 }
+                EventKind::NE { length, message } => match message {
+                    Some(message) => writeln!(
+                        f,
+                        "{} {} to {} | '{}' (encrypted)",
+                        event.id,
+                        event.src,
+                        event.dst.unwrap(),
+                        message
+                    )?,
+                    None => writeln!(
+                        f,
+                        "{} {} to {} | <encrypted, {} bytes>",
+                        event.id,
+                        event.src,
+                        event.dst.unwrap(),
+                        length
+                    )?,
+                },
                 EventKind::NS { amount } => {
                     writeln!(f, "{} {} | {} BCC", event.id, event.src, amount)?;
                 }
+                EventKind::NMT { outputs } => {
+                    writeln!(f, "{} {} to {}", event.id, event.src, fmt_outputs(outputs))?;
+                }
                 EventKind::NB { tids } => {
                     writeln!(f, "{} by {} | {:?}", event.id, event.src, tids)?;
                 }
@@ -518,3 +723,12 @@ impl Display for History {
         Ok(())
     }
 }
+
+// e.g. `1 (5 BCC), 2 (3 BCC)`
+fn fmt_outputs(outputs: &[(u32, f64)]) -> String {
+    outputs
+        .iter()
+        .map(|(id, amount)| format!("{} ({} BCC)", id, amount))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
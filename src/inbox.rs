@@ -0,0 +1,412 @@
+use crate::{
+    blockchain::{block::Block, transaction::TransactionPayload},
+    crypto::PrivateKey,
+    peer::PeersCatalog,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/*
+    The Inbox is an index of the messages addressed to the local account.
+
+    It is filled in as blocks are added to the blockchain, so it only contains
+    messages that have been included in a block (pending messages are not shown).
+    Since the index keeps its own copy of every message,
+    it is not affected by the pruning of old blocks.
+    Encrypted messages are decrypted once, when they are indexed.
+
+    Whether a message has been read is tracked locally by the hash of its transaction.
+    If a file is given, the messages and their read state are kept in it, so they survive
+    restarts (a node restarts from a snapshot, so the blocks before it are not indexed again).
+    The file holds the decrypted messages, so it should be kept as private as the key.
+*/
+
+#[derive(Error, Debug)]
+pub enum InboxError {
+    #[error("The inbox could not be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("The inbox could not be (de)serialized: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InboxEntry {
+    tsx_hash: [u8; 32],
+    sndr_id: u32,
+    block_index: u32,
+    timestamp: u128,
+    // None if an encrypted message could not be decrypted
+    message: Option<String>,
+    encrypted: bool,
+}
+
+impl InboxEntry {
+    pub fn tsx_hash(&self) -> &[u8; 32] {
+        &self.tsx_hash
+    }
+
+    pub fn sndr_id(&self) -> u32 {
+        self.sndr_id
+    }
+
+    pub fn block_index(&self) -> u32 {
+        self.block_index
+    }
+
+    pub fn timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+}
+
+// which messages to show (everything if left to the default)
+#[derive(Clone, Debug, Default)]
+pub struct InboxFilter {
+    pub sndr_id: Option<u32>,
+    pub since: Option<u128>, // in ms since the UNIX epoch (inclusive)
+    pub until: Option<u128>, // in ms since the UNIX epoch (inclusive)
+    pub unread_only: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Inbox {
+    entries: Vec<InboxEntry>,
+    read: HashSet<[u8; 32]>,
+    // the transactions of the entries, so that a block indexed again adds nothing
+    #[serde(skip)]
+    indexed: HashSet<[u8; 32]>,
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Inbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // keep the inbox in the given file (it is created if it does not exist)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, InboxError> {
+        let path = path.as_ref();
+
+        let mut inbox: Self = match fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::new(),
+            Err(e) => return Err(e.into()),
+        };
+        inbox.indexed = inbox.entries.iter().map(|e| e.tsx_hash).collect();
+        inbox.file = Some(path.to_path_buf());
+
+        Ok(inbox)
+    }
+
+    fn save(&self) -> Result<(), InboxError> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let file = fs::File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+
+        Ok(())
+    }
+
+    // index the messages of the block that are addressed to the owner of the private key
+    // and persist them (if needed), returns the newly indexed messages
+    pub fn index_block(
+        &mut self,
+        blk: &Block,
+        peers: &PeersCatalog,
+        priv_key: &PrivateKey,
+    ) -> &[InboxEntry] {
+        let local_addr = priv_key.to_publ_key();
+        let first_new = self.entries.len();
+
+        for tsx in blk.tsxs() {
+            if tsx.recp_addr() != Some(&local_addr) || self.indexed.contains(tsx.hash()) {
+                continue;
+            }
+
+            let (message, encrypted) = match tsx.payload() {
+                TransactionPayload::Message(msg) => (Some(msg.to_string()), false),
+                TransactionPayload::EncryptedMessage(msg) => (msg.open(priv_key), true),
+                _ => continue,
+            };
+
+            let Some(sndr) = tsx.sndr_addr().and_then(|a| peers.get_by_publ_key(a)) else {
+                continue;
+            };
+
+            self.entries.push(InboxEntry {
+                tsx_hash: *tsx.hash(),
+                sndr_id: sndr.id(),
+                block_index: blk.index(),
+                timestamp: blk.timestamp(),
+                message,
+                encrypted,
+            });
+            self.indexed.insert(*tsx.hash());
+        }
+
+        // the messages are still shown if they cannot be saved
+        if self.entries.len() > first_new {
+            if let Err(e) = self.save() {
+                log::warn!("Failed to save the inbox: {}", e);
+            }
+        }
+
+        &self.entries[first_new..]
+    }
+
+    // the messages matching the filter, oldest first, along with whether they have been read
+    pub fn query<'a>(
+        &'a self,
+        filter: &'a InboxFilter,
+    ) -> impl 'a + Iterator<Item = (&'a InboxEntry, bool)> {
+        self.entries
+            .iter()
+            .map(|e| (e, self.read.contains(&e.tsx_hash)))
+            .filter(|(e, read)| {
+                filter.sndr_id.is_none_or(|id| e.sndr_id == id)
+                    && filter.since.is_none_or(|t| e.timestamp >= t)
+                    && filter.until.is_none_or(|t| e.timestamp <= t)
+                    && !(filter.unread_only && *read)
+            })
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| !self.read.contains(&e.tsx_hash))
+            .count()
+    }
+
+    // mark the messages matching the filter as read and persist the read state (if needed)
+    pub fn mark_read(&mut self, filter: &InboxFilter) -> Result<(), InboxError> {
+        let hashes: Vec<_> = self.query(filter).map(|(e, _)| e.tsx_hash).collect();
+        if hashes.iter().all(|h| self.read.contains(h)) {
+            return Ok(());
+        }
+
+        self.read.extend(hashes);
+        self.save()
+    }
+}
+
+// format ms since the UNIX epoch as `YYYY-MM-DD hh:mm:ss UTC`
+pub(crate) fn fmt_timestamp(ms: u128) -> String {
+    let secs = (ms / 1000) as u64;
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // convert days since the epoch to a civil date (proleptic gregorian calendar)
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::{EncryptedMessage, Transaction};
+    use non_empty_string::NonEmptyString;
+    use rsa::RsaPrivateKey;
+    use std::{env, num::NonZeroU32};
+
+    // RSA-OAEP with SHA-256 needs at least 1024-bit keys to wrap a 32-byte key
+    fn new_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap())
+    }
+
+    // the local key and two other peers (ids 0, 1 and 2)
+    fn peers() -> ([PrivateKey; 3], PeersCatalog) {
+        let keys = [new_priv_key(), new_priv_key(), new_priv_key()];
+        let mut peers = PeersCatalog::new();
+        for key in &keys {
+            peers
+                .insert((key.to_publ_key(), "127.0.0.1:0".parse().unwrap()))
+                .unwrap();
+        }
+
+        (keys, peers)
+    }
+
+    fn message(sndr: &PrivateKey, recp: &PrivateKey, text: &str) -> Transaction {
+        let msg = NonEmptyString::new(text.to_string()).unwrap();
+        Transaction::new_message(sndr.to_publ_key(), recp.to_publ_key(), msg, 0, sndr)
+    }
+
+    fn block(tsxs: Vec<Transaction>, timestamp: u128) -> Block {
+        Block::new_genesis(tsxs, timestamp, [0; 32])
+    }
+
+    fn texts<'a>(inbox: &'a Inbox, filter: &'a InboxFilter) -> Vec<&'a str> {
+        inbox
+            .query(filter)
+            .filter_map(|(e, _)| e.message())
+            .collect()
+    }
+
+    #[test]
+    fn test_index_block() {
+        let ([local, a, b], peers) = peers();
+        let secret = NonEmptyString::new("secret".to_string()).unwrap();
+
+        let blk = block(
+            vec![
+                message(&a, &local, "hi"),
+                message(&a, &b, "not for me"),
+                Transaction::new_transfer(
+                    a.to_publ_key(),
+                    local.to_publ_key(),
+                    NonZeroU32::MIN,
+                    1,
+                    &a,
+                ),
+                Transaction::new_encrypted_message(
+                    b.to_publ_key(),
+                    local.to_publ_key(),
+                    EncryptedMessage::seal(&local.to_publ_key(), &secret),
+                    0,
+                    &b,
+                ),
+            ],
+            1000,
+        );
+
+        let mut inbox = Inbox::new();
+        let entries = inbox.index_block(&blk, &peers, &local);
+        assert!(matches!(
+            entries,
+            [first, second]
+                if first.sndr_id() == 1 && first.message() == Some("hi") && !first.is_encrypted()
+                && second.sndr_id() == 2 && second.message() == Some("secret") && second.is_encrypted()
+                && first.timestamp() == 1000
+        ));
+
+        // a block that is indexed again adds nothing
+        assert!(inbox.index_block(&blk, &peers, &local).is_empty());
+        assert_eq!(inbox.unread_count(), 2);
+    }
+
+    #[test]
+    fn test_query() {
+        let ([local, a, b], peers) = peers();
+
+        let mut inbox = Inbox::new();
+        for (sndr, text, timestamp) in [(&a, "one", 1000), (&b, "two", 2000), (&a, "three", 3000)] {
+            inbox.index_block(
+                &block(vec![message(sndr, &local, text)], timestamp),
+                &peers,
+                &local,
+            );
+        }
+
+        let filter = |sndr_id, since, until| InboxFilter {
+            sndr_id,
+            since,
+            until,
+            unread_only: false,
+        };
+        assert_eq!(
+            texts(&inbox, &filter(None, None, None)),
+            ["one", "two", "three"]
+        );
+        assert_eq!(
+            texts(&inbox, &filter(Some(1), None, None)),
+            ["one", "three"]
+        );
+        assert_eq!(
+            texts(&inbox, &filter(None, Some(2000), None)),
+            ["two", "three"]
+        );
+        assert_eq!(
+            texts(&inbox, &filter(None, None, Some(2000))),
+            ["one", "two"]
+        );
+        assert_eq!(
+            texts(&inbox, &filter(Some(1), Some(2000), Some(2000))),
+            [] as [&str; 0]
+        );
+        assert_eq!(
+            texts(&inbox, &filter(Some(2), Some(2000), Some(2000))),
+            ["two"]
+        );
+    }
+
+    #[test]
+    fn test_mark_read() {
+        let ([local, a, b], peers) = peers();
+        let path = env::temp_dir().join(format!("block_chat_inbox_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let blks = [
+            block(vec![message(&a, &local, "one")], 1000),
+            block(vec![message(&b, &local, "two")], 2000),
+        ];
+
+        let mut inbox = Inbox::load(&path).unwrap();
+        for blk in &blks {
+            inbox.index_block(blk, &peers, &local);
+        }
+
+        let unread = InboxFilter {
+            unread_only: true,
+            ..InboxFilter::default()
+        };
+        inbox
+            .mark_read(&InboxFilter {
+                sndr_id: Some(1),
+                ..InboxFilter::default()
+            })
+            .unwrap();
+        assert_eq!(inbox.unread_count(), 1);
+        assert_eq!(texts(&inbox, &unread), ["two"]);
+
+        // the messages and their read state survive a restart
+        let mut inbox = Inbox::load(&path).unwrap();
+        assert_eq!(texts(&inbox, &InboxFilter::default()), ["one", "two"]);
+        assert_eq!(texts(&inbox, &unread), ["two"]);
+        assert!(inbox.index_block(&blks[1], &peers, &local).is_empty());
+
+        inbox.mark_read(&InboxFilter::default()).unwrap();
+        assert_eq!(Inbox::load(&path).unwrap().unread_count(), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fmt_timestamp() {
+        assert_eq!(fmt_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(fmt_timestamp(951_782_400_000), "2000-02-29 00:00:00 UTC");
+        assert_eq!(fmt_timestamp(1_792_310_645_999), "2026-10-18 08:04:05 UTC");
+    }
+}
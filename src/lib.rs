@@ -1,8 +1,17 @@
 pub mod account;
+pub mod address;
+pub mod address_book;
 pub mod blockchain;
 pub mod bootstrap;
 pub mod cli;
 pub mod crypto;
+pub mod events;
+pub mod genesis;
 pub mod history;
+pub mod inbox;
 pub mod peer;
 pub mod protocol;
+pub mod response;
+pub mod rpc;
+pub mod snapshot;
+pub mod transport;
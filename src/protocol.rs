@@ -1,29 +1,75 @@
 use crate::{
-    account::{Account, AccountsCatalog},
+    account::{Account, AccountError, AccountsCatalog, Statement},
+    address_book::{AddressBook, AddressBookError},
     blockchain::{
-        block::{Block, BlockValidator, BLOCK_CAPACITY},
-        transaction::{Transaction, TransactionValidator},
-        Blockchain,
+        block::{Block, BlockValidator},
+        transaction::{
+            EncryptedMessage, Transaction, TransactionValidator, TransferOutput,
+            MAX_TRANSFER_OUTPUTS,
+        },
+        Blockchain, BlockchainError,
     },
-    bootstrap::bootstrap_network,
-    cli::Command,
-    crypto::PrivateKey,
+    bootstrap::{
+        bootstrap_network, static_network, JoinAuthorization, JoinTimeouts, StaticMembership,
+    },
+    cli::{
+        AddressBookCommand, BatchItem, BlockRange, BlockRef, Command, Payment, Recipient,
+        WaitOptions,
+    },
+    crypto::{self, KeyFormat, PrivateKey, PublicKey},
+    events::{Event, EventType},
+    genesis::{chain_params, Genesis},
     history::History,
+    inbox::{Inbox, InboxFilter},
     peer::{Peer, PeersCatalog},
+    response::{
+        BatchOutcome, BatchReport, BatchResult, ChainInfo, Confirmation, Contact, InboxMessage,
+        KeyExport, Liveness, NetworkInfo, PeerIdentity, PeerSummary, Response, TxHistoryPage,
+    },
+    rpc::{
+        spawn_rpc_server, Balance, BlockInfo, ErrorCode, PeerInfo, RpcCall, RpcError, RpcResult,
+        Submitted, TransactionInfo, TransactionState, TransactionSummary,
+    },
+    snapshot::Snapshot,
+    transport::{Connection, SecureStream, TransportError},
 };
+use clap::ValueEnum as _;
+use hex::ToHex as _;
 use non_empty_string::NonEmptyString;
 use rand::{RngCore as _, SeedableRng as _};
 use rand_chacha::ChaCha12Rng;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::{
     cell::Cell,
+    collections::{hash_map::Entry, HashMap, HashSet},
     io::Write as _,
-    net::{TcpListener, TcpStream, ToSocketAddrs},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
     num::NonZeroU32,
-    sync::mpsc::{self, Receiver, Sender},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
+use thiserror::Error;
+
+// how long to wait for a subscriber to accept an event before dropping it
+const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// how many events can be queued for a subscriber before it is dropped
+const SUBSCRIBER_QUEUE_LEN: usize = 256;
+
+// how many blocks the `blocks` command shows at most
+const MAX_BLOCKS_PER_RANGE: u32 = 100;
+
+// how many transactions a batch has at most
+const MAX_BATCH_LEN: usize = 1000;
+
+// how often to check whether clients waiting for a confirmation have timed out
+const WAITER_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 // unsafe static muts, used only for benchmarking
 static mut TSX_START: Option<Instant> = None;
@@ -31,29 +77,175 @@ static mut BLK_START: Option<Instant> = None;
 static mut TSX_TIMES: Vec<Duration> = vec![];
 static mut BLK_TIMES: Vec<Duration> = vec![];
 
-// the user sees coins as floating point numbers
-// but the program uses integers to avoid floating point errors
-// so whenever the user wants to create a transaction
-// a conversion is needed
-pub const CENTS_PER_COIN: u32 = 100;
-pub const TRANSFER_FEE_PERCENTAGE: u32 = 3;
-pub const MESSAGE_FEE_PER_CHARACTER_CENTS: u32 = CENTS_PER_COIN;
-pub const MINIMUM_TRANSFER_FEE_CENTS: u32 = 1;
-
 // multiplex Transactions, Blocks and Commands on the same TCP socket
 #[derive(Deserialize, Serialize)]
 pub enum Broadcast {
     Transaction(Transaction),
     Block(Block),
     Command(Command),
+    // request the blocks after the given index from a peer
+    SyncRequest(u32),
+}
+
+// the reply to a `Broadcast::SyncRequest`
+#[derive(Deserialize, Serialize)]
+pub enum SyncResponse {
+    // the blocks after the requested index
+    Blocks(Vec<Block>),
+    // some of the requested blocks fall outside the retention window of the peer
+    // the requesting peer can bootstrap from the snapshot and the blocks after it instead
+    OutsideRetention {
+        oldest_retained: u32,
+        snapshot: Box<Snapshot>,
+        blocks: Vec<Block>,
+    },
+}
+
+// how a node finds out about the other peers in the network
+pub enum Membership<A: ToSocketAddrs> {
+    // join the network through the bootstrap peer
+    Dynamic {
+        total_peers: u16,        // how many peers are in the network
+        bootstrap_peer_addr: A,  // the address of the bootstrap peer
+        bootstrap_port: u16,     // the port to be used for the bootstrap process
+        network_port: u16,       // the port to be used for the network
+        auth: JoinAuthorization, // who can join and who can bootstrap the network
+        timeouts: JoinTimeouts,  // how long to wait for the network to form
+    },
+    // the peers are known in advance (the network port is the one in the membership)
+    Static(StaticMembership),
+}
+
+// what happened to a transaction after it was accepted into the soft state
+// (transactions that are still pending are not tracked)
+#[derive(Clone, Debug)]
+enum TransactionStatus {
+    // included in the block with the given index
+    Included(u32),
+    // discarded because it became invalid after the block with the given index was processed
+    Dropped(u32, String),
+}
+
+// what the main loop handles
+// (almost everything is a broadcast, so there's no point in boxing it)
+#[allow(clippy::large_enum_variant)]
+enum Incoming {
+    // from the listener, along with the connection of commands and requests
+    Broadcast(Broadcast, Option<Connection>),
+    // from the RPC server, along with where to send the result
+    Rpc(RpcCall, Sender<RpcResult>),
+    // from the listener, when a secure connection from a peer is opened or closed
+    PeerConnected(u32),
+    PeerDisconnected(u32),
+    // from the broadcast thread, when a peer becomes reachable or unreachable
+    PeerReachable(u32, bool),
+}
+
+// why a transaction could not be created for a client
+#[derive(Error, Debug)]
+pub enum SubmitError {
+    #[error("You cannot send coins to yourself")]
+    SelfTransfer,
+    #[error("Recipient {0} is given more than once")]
+    DuplicateRecipient(u32),
+    #[error("A multi-transfer has at most {} recipients", MAX_TRANSFER_OUTPUTS)]
+    TooManyRecipients,
+    #[error("You cannot message yourself")]
+    SelfMessage,
+    #[error("Recipient not found")]
+    RecipientNotFound,
+    #[error("The name `{0}` is not in the address book")]
+    UnknownName(String),
+    #[error("Message cannot be empty")]
+    EmptyMessage,
+    #[error("Not enough coins")]
+    NotEnoughCoins,
+}
+
+impl SubmitError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::SelfTransfer
+            | Self::SelfMessage
+            | Self::RecipientNotFound
+            | Self::DuplicateRecipient(_)
+            | Self::UnknownName(_) => ErrorCode::InvalidRecipient,
+            Self::TooManyRecipients => ErrorCode::InvalidParams,
+            Self::EmptyMessage => ErrorCode::InvalidMessage,
+            Self::NotEnoughCoins => ErrorCode::InsufficientFunds,
+        }
+    }
+}
+
+impl From<SubmitError> for RpcError {
+    fn from(e: SubmitError) -> Self {
+        RpcError::new(e.code(), e)
+    }
+}
+
+impl From<SubmitError> for Response {
+    fn from(e: SubmitError) -> Self {
+        Response::error(e.code(), e)
+    }
+}
+
+// a client waiting for its transaction to be confirmed
+struct Waiter {
+    stream: TcpStream,
+    hash: [u8; 32],
+    confirmations: u32,
+    deadline: Instant,
+}
+
+// the connections between the local node and a peer, in both directions
+// a peer is considered connected as long as either direction is up
+struct PeerLink {
+    inbound: u32,
+    // whether the last broadcast reached the peer
+    outbound: bool,
+    // when the peer was last (dis)connected
+    since: Instant,
+}
+
+impl PeerLink {
+    fn is_connected(&self) -> bool {
+        self.inbound > 0 || self.outbound
+    }
+}
+
+// a client receiving the events of the given types
+// the events are written on a thread of its own, so that a slow client cannot hold up the main loop
+struct Subscriber {
+    tx: SyncSender<Arc<[u8]>>,
+    types: Vec<EventType>,
+}
+
+impl Subscriber {
+    fn spawn(mut stream: TcpStream, types: Vec<EventType>) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Arc<[u8]>>(SUBSCRIBER_QUEUE_LEN);
+
+        thread::spawn(move || {
+            for line in rx {
+                if let Err(e) = stream.write_all(&line) {
+                    log::debug!("Dropped subscriber: {}", e);
+                    break;
+                }
+            }
+        });
+
+        Self { tx, types }
+    }
 }
 
 pub struct ProtocolConfig<A: ToSocketAddrs> {
-    pub total_peers: u16,         // how many peers are in the network
-    pub init_coins_per_peer: u32, // how many coins each peer starts with
-    pub bootstrap_peer_addr: A,   // the address of the bootstrap peer
-    pub bootstrap_port: u16,      // the port to be used for the bootstrap process
-    pub network_port: u16,        // the port to be used for the network
+    pub membership: Membership<A>,           // how to find the other peers
+    pub genesis: Genesis,                    // the chain spec of the network
+    pub snapshot_interval: NonZeroU32,       // how many blocks between two snapshots
+    pub snapshot_dir: Option<PathBuf>,       // where to save snapshots (if anywhere)
+    pub block_retention: Option<NonZeroU32>, // how many block bodies to keep (all if None)
+    pub inbox_file: Option<PathBuf>,         // where to keep the inbox
+    pub address_book_file: Option<PathBuf>,  // where to keep the address book
+    pub rpc_addr: Option<SocketAddr>,        // where to serve the RPC API (if anywhere)
 }
 
 struct ProtocolState<'a> {
@@ -64,6 +256,29 @@ struct ProtocolState<'a> {
     pending_transactions: Vec<Transaction>,
     blockchain: Blockchain,
 
+    // the transactions that are no longer pending, by hash
+    // (forgotten once the block they were included in or dropped after is pruned)
+    tsx_statuses: HashMap<[u8; 32], TransactionStatus>,
+    waiters: Vec<Waiter>,
+    subscribers: Vec<Subscriber>,
+
+    // the peers that have connected to the local node (for liveness), by id
+    peer_links: HashMap<u32, PeerLink>,
+
+    // the most recent snapshot of the hard accounts
+    snapshot: Snapshot,
+    snapshot_interval: NonZeroU32,
+    snapshot_dir: Option<PathBuf>,
+
+    // pruning is disabled if None
+    block_retention: Option<NonZeroU32>,
+
+    // the messages addressed to the local account
+    inbox: Inbox,
+
+    // the names given to the keys of peers
+    address_book: AddressBook,
+
     // memoization of `proof_of_stake()`
     next_validator_id: Cell<Option<u32>>,
 
@@ -85,7 +300,12 @@ impl<'a> Protocol<'a> {
     }
 
     pub fn run(&mut self, cfg: ProtocolConfig<impl ToSocketAddrs>) {
-        fn spawn_listener_thread(listener: TcpListener, tx: Sender<(Broadcast, TcpStream)>) {
+        fn spawn_listener_thread(
+            listener: TcpListener,
+            tx: Sender<Incoming>,
+            peers: &'static PeersCatalog,
+            priv_key: PrivateKey,
+        ) {
             debug_assert!(listener.local_addr().is_ok());
 
             thread::spawn(move || {
@@ -98,51 +318,173 @@ impl<'a> Protocol<'a> {
                         }
                     };
 
-                    let mut de = serde_json::Deserializer::from_reader(stream.try_clone().unwrap());
-                    let broadcast = match Broadcast::deserialize(&mut de) {
-                        Ok(broadcast) => broadcast,
+                    // every connection is handled on its own thread
+                    // since connections from peers stay open
+                    let tx = tx.clone();
+                    let priv_key = priv_key.clone();
+                    thread::spawn(move || handle_connection(stream, tx, peers, &priv_key));
+                }
+            });
+        }
+
+        fn handle_connection(
+            stream: TcpStream,
+            tx: Sender<Incoming>,
+            peers: &'static PeersCatalog,
+            priv_key: &PrivateKey,
+        ) {
+            let addr = match stream.peer_addr() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    log::warn!("Listener: Failed to get the remote address: {}", e);
+                    return;
+                }
+            };
+
+            match SecureStream::is_handshake(&stream) {
+                Ok(true) => {}
+                // clients connect without a handshake, and all they can send is a command
+                Ok(false) => {
+                    let mut de = serde_json::Deserializer::from_reader(&stream);
+                    match Broadcast::deserialize(&mut de) {
+                        Ok(Broadcast::Command(command)) => {
+                            log::trace!("Listener: Received command from {}", addr);
+
+                            let conn = Connection::Plain(stream);
+                            tx.send(Incoming::Broadcast(Broadcast::Command(command), Some(conn)))
+                                .unwrap();
+                        }
+                        Ok(_) => {
+                            log::warn!("Listener: Rejected unencrypted broadcast from {}", addr)
+                        }
                         Err(e) => {
-                            log::warn!("Listener: Failed to deserialize stream data: {}", e);
-                            continue;
+                            log::warn!("Listener: Failed to deserialize stream data: {}", e)
                         }
-                    };
+                    }
 
-                    log::trace!(
-                        "Listener: Received {} from {}",
-                        match &broadcast {
-                            Broadcast::Transaction(_) => "transaction",
-                            Broadcast::Block(_) => "block",
-                            Broadcast::Command(_) => "command",
-                        },
-                        stream.peer_addr().unwrap()
-                    );
+                    return;
+                }
+                Err(e) => {
+                    log::warn!("Listener: Failed to read from {}: {}", addr, e);
+                    return;
+                }
+            }
 
-                    tx.send((broadcast, stream)).unwrap();
+            let mut stream = match SecureStream::accept(stream, peers, priv_key) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Listener: Rejected connection from {}: {}", addr, e);
+                    return;
                 }
-            });
+            };
+
+            log::debug!(
+                "Listener: Secure connection established with peer {} ({})",
+                stream.peer_id(),
+                addr
+            );
+            tx.send(Incoming::PeerConnected(stream.peer_id())).unwrap();
+
+            loop {
+                let mut de = serde_json::Deserializer::from_reader(&mut stream);
+                let broadcast = match Broadcast::deserialize(&mut de) {
+                    Ok(broadcast) => broadcast,
+                    Err(e) if e.is_eof() => break,
+                    Err(e) => {
+                        log::warn!("Listener: Failed to deserialize stream data: {}", e);
+                        break;
+                    }
+                };
+
+                log::trace!(
+                    "Listener: Received {} from peer {}",
+                    match &broadcast {
+                        Broadcast::Transaction(_) => "transaction",
+                        Broadcast::Block(_) => "block",
+                        Broadcast::Command(_) => "command",
+                        Broadcast::SyncRequest(_) => "sync request",
+                    },
+                    stream.peer_id()
+                );
+
+                match broadcast {
+                    Broadcast::Transaction(_) | Broadcast::Block(_) => {
+                        tx.send(Incoming::Broadcast(broadcast, None)).unwrap();
+                    }
+                    // a request is answered on the same connection, so it's the last message on it
+                    Broadcast::SyncRequest(_) => {
+                        tx.send(Incoming::PeerDisconnected(stream.peer_id()))
+                            .unwrap();
+                        let conn = Connection::Secure(stream);
+                        tx.send(Incoming::Broadcast(broadcast, Some(conn))).unwrap();
+                        return;
+                    }
+                    // commands can only come from clients
+                    Broadcast::Command(_) => {
+                        log::warn!("Listener: Rejected command from peer {}", stream.peer_id());
+                        break;
+                    }
+                }
+            }
+
+            log::debug!("Listener: Connection with peer {} closed", stream.peer_id());
+            tx.send(Incoming::PeerDisconnected(stream.peer_id()))
+                .unwrap();
         }
 
-        fn spawn_broadcast_thread(rx: Receiver<Broadcast>, id: u32, peers: &'static PeersCatalog) {
+        fn spawn_broadcast_thread(
+            rx: Receiver<Broadcast>,
+            links: Sender<Incoming>,
+            id: u32,
+            peers: &'static PeersCatalog,
+            priv_key: PrivateKey,
+        ) {
+            fn send(
+                conns: &mut HashMap<u32, SecureStream>,
+                peer: &Peer,
+                priv_key: &PrivateKey,
+                bytes: &[u8],
+            ) -> Result<(), TransportError> {
+                let stream = match conns.entry(peer.id()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(SecureStream::connect(peer, priv_key)?),
+                };
+
+                Ok(stream.write_all(bytes)?)
+            }
+
             thread::spawn(move || {
+                // the connection to every peer is kept open between broadcasts
+                let mut conns = HashMap::new();
+                // whether the last broadcast reached each peer
+                let mut reachable = HashMap::new();
+
                 for broadcast in rx {
                     let broadcast_bytes =
                         serde_json::to_vec(&broadcast).expect("Failed to serialize transaction");
 
-                    for addr in peers
-                        .iter()
-                        .filter(|peer| peer.id() != id)
-                        .map(|peer| peer.sock_addr())
-                    {
-                        let mut stream = match TcpStream::connect(addr) {
-                            Ok(stream) => stream,
-                            Err(e) => {
-                                log::warn!("Broadcast: Failed to connect to peer: {}", e);
-                                continue;
-                            }
-                        };
-
-                        if let Err(e) = stream.write_all(&broadcast_bytes) {
-                            log::warn!("Broadcast: Failed to broadcast: {}", e);
+                    for peer in peers.iter().filter(|peer| peer.id() != id) {
+                        // the connection may have been closed since the last broadcast
+                        // so if sending fails, try once more over a new connection
+                        let res =
+                            send(&mut conns, peer, &priv_key, &broadcast_bytes).or_else(|_| {
+                                conns.remove(&peer.id());
+                                send(&mut conns, peer, &priv_key, &broadcast_bytes)
+                            });
+
+                        if let Err(e) = &res {
+                            conns.remove(&peer.id());
+                            log::warn!(
+                                "Broadcast: Failed to broadcast to peer {}: {}",
+                                peer.id(),
+                                e
+                            );
+                        }
+
+                        // only changes are reported to the main loop
+                        let is_reachable = res.is_ok();
+                        if reachable.insert(peer.id(), is_reachable) != Some(is_reachable) {
+                            let _ = links.send(Incoming::PeerReachable(peer.id(), is_reachable));
                         }
                     }
                 }
@@ -150,16 +492,30 @@ impl<'a> Protocol<'a> {
         }
 
         // bootstrapping
-        let (network_listener, peers, blockchain) = bootstrap_network(
-            cfg.total_peers,
-            cfg.init_coins_per_peer * CENTS_PER_COIN,
-            cfg.bootstrap_peer_addr,
-            cfg.bootstrap_port,
-            cfg.network_port,
-            self.priv_key.to_publ_key(),
-        );
-
-        debug_assert!(blockchain.len() == 1);
+        let (network_listener, peers, snapshot, blocks) = match cfg.membership {
+            Membership::Dynamic {
+                total_peers,
+                bootstrap_peer_addr,
+                bootstrap_port,
+                network_port,
+                auth,
+                timeouts,
+            } => bootstrap_network(
+                total_peers,
+                &cfg.genesis,
+                &auth,
+                &timeouts,
+                bootstrap_peer_addr,
+                bootstrap_port,
+                network_port,
+                &self.priv_key,
+            )
+            .unwrap_or_else(|e| panic!("Failed to bootstrap the network: {}", e)),
+            Membership::Static(membership) => {
+                static_network(&membership, &cfg.genesis, self.priv_key.to_publ_key())
+                    .unwrap_or_else(|e| panic!("Failed to set up the static network: {}", e))
+            }
+        };
 
         log::debug!(
             "Protocol: Discovered {} peers: {:#?}",
@@ -173,11 +529,58 @@ impl<'a> Protocol<'a> {
         // object is only dropped if the program exits
         let peers = peers.leak();
 
-        // create an account for each peer and process the genesis transactions
-        let mut hard_accounts = AccountsCatalog::new(peers);
-        for tsx in blockchain.last_block().tsxs() {
-            hard_accounts.process_transaction(tsx).unwrap();
-        }
+        // resume from the latest snapshot saved by a previous run of the node
+        // if it is ahead of the one received while bootstrapping (and on the same chain)
+        let saved = cfg
+            .snapshot_dir
+            .as_deref()
+            .and_then(|dir| Snapshot::load_latest(dir, snapshot.genesis_hash()));
+        let (snapshot, blocks) = match saved {
+            Some(saved)
+                if saved.block().index() > snapshot.block().index()
+                    && blocks.iter().all(|blk| {
+                        blk.index() != saved.block().index() || blk.hash() == saved.block().hash()
+                    }) =>
+            {
+                log::info!(
+                    "Protocol: Resuming from the saved snapshot of block {}",
+                    saved.block().index()
+                );
+
+                // the blocks the saved snapshot covers are skipped
+                let blocks = blocks
+                    .into_iter()
+                    .filter(|blk| blk.index() > saved.block().index())
+                    .collect();
+                (saved, blocks)
+            }
+            _ => (snapshot, blocks),
+        };
+
+        // restore the accounts from the snapshot instead of replaying the blockchain
+        // the snapshot is verified against the state root of its block
+        let hard_accounts = AccountsCatalog::from_snapshot(peers, &snapshot)
+            .unwrap_or_else(|e| panic!("Failed to bootstrap from snapshot: {}", e));
+        let blockchain = Blockchain::from_snapshot(&snapshot);
+
+        let mut inbox = match &cfg.inbox_file {
+            Some(path) => Inbox::load(path).unwrap_or_else(|e| {
+                panic!("Failed to load the inbox from {}: {}", path.display(), e)
+            }),
+            None => Inbox::new(),
+        };
+        inbox.index_block(snapshot.block(), peers, &self.priv_key);
+
+        let address_book = match &cfg.address_book_file {
+            Some(path) => AddressBook::load(path).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to load the address book from {}: {}",
+                    path.display(),
+                    e
+                )
+            }),
+            None => AddressBook::new(),
+        };
 
         // find the local peer id
         let id = peers
@@ -191,8 +594,10 @@ impl<'a> Protocol<'a> {
         // transactions can be technically sent in any order, however it's still desirable
         // to have them in the correct order
         // broadcasting is done on a separate thread in order to avoid blocking the main thread
+        // the broadcast thread reports whether peers are reachable to the main loop
+        let (incoming_tx, incoming_rx): (Sender<Incoming>, _) = mpsc::channel();
         let (tx, rx): (Sender<Broadcast>, _) = mpsc::channel();
-        spawn_broadcast_thread(rx, id, peers);
+        spawn_broadcast_thread(rx, incoming_tx.clone(), id, peers, self.priv_key.clone());
 
         self.state = Some(ProtocolState {
             id,
@@ -201,6 +606,16 @@ impl<'a> Protocol<'a> {
             hard_accounts,
             pending_transactions: vec![],
             blockchain,
+            tsx_statuses: HashMap::new(),
+            waiters: vec![],
+            subscribers: vec![],
+            peer_links: HashMap::new(),
+            snapshot,
+            snapshot_interval: cfg.snapshot_interval,
+            snapshot_dir: cfg.snapshot_dir,
+            block_retention: cfg.block_retention,
+            inbox,
+            address_book,
             next_validator_id: Cell::new(None),
             tx,
         });
@@ -208,20 +623,72 @@ impl<'a> Protocol<'a> {
         // spawn the thread that will listen for incoming transactions and blocks
         // this needs to be done on a separate thread
         // otherwise the main thread would constantly block
-        let (tx, rx): (Sender<(Broadcast, TcpStream)>, _) = mpsc::channel();
-        spawn_listener_thread(network_listener, tx);
+        let (tx, rx) = (incoming_tx, incoming_rx);
+        spawn_listener_thread(network_listener, tx.clone(), peers, self.priv_key.clone());
+
+        // the RPC server hands every call to the main loop and waits for its result
+        if let Some(addr) = cfg.rpc_addr {
+            let listener = TcpListener::bind(addr)
+                .unwrap_or_else(|e| panic!("Failed to bind the RPC server to {}: {}", addr, e));
+
+            spawn_rpc_server(listener, move |call| {
+                let (res_tx, res_rx) = mpsc::channel();
+                tx.send(Incoming::Rpc(call, res_tx)).map_err(|_| {
+                    RpcError::new(ErrorCode::InternalError, "The daemon is shutting down")
+                })?;
+                res_rx.recv().map_err(|_| {
+                    RpcError::new(ErrorCode::InternalError, "The daemon is shutting down")
+                })?
+            });
+        }
 
         unsafe {
             TSX_START.replace(Instant::now());
             BLK_START.replace(Instant::now());
         }
 
+        // catch up with the blocks that came after the snapshot
+        for blk in blocks {
+            self.handle_block(blk, false);
+        }
+
         // main loop
-        for event in rx {
+        // wake up periodically to time out the clients waiting for a confirmation
+        loop {
+            let event = match rx.recv_timeout(WAITER_POLL_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.check_waiters();
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
             match event {
-                (Broadcast::Transaction(tsx), _) => self.handle_transaction(tsx, None, false),
-                (Broadcast::Block(blk), _) => self.handle_block(blk, false),
-                (Broadcast::Command(command), stream) => self.handle_command(command, stream),
+                Incoming::Broadcast(Broadcast::Transaction(tsx), _) => {
+                    self.handle_transaction(tsx, None, false)
+                }
+                Incoming::Broadcast(Broadcast::Block(blk), _) => self.handle_block(blk, false),
+                Incoming::Broadcast(
+                    Broadcast::Command(command),
+                    Some(Connection::Plain(stream)),
+                ) => self.handle_command(command, stream),
+                Incoming::Broadcast(Broadcast::SyncRequest(index), Some(conn)) => {
+                    self.handle_sync_request(index, conn)
+                }
+                // the RPC server may have given up on the result, so it's fine if it's not sent
+                Incoming::Rpc(call, res_tx) => {
+                    let _ = res_tx.send(self.handle_rpc(call));
+                }
+                Incoming::PeerConnected(id) => self.handle_peer_link(id, |l| l.inbound += 1),
+                Incoming::PeerDisconnected(id) => {
+                    self.handle_peer_link(id, |l| l.inbound = l.inbound.saturating_sub(1))
+                }
+                Incoming::PeerReachable(id, reachable) => {
+                    self.handle_peer_link(id, |l| l.outbound = reachable)
+                }
+                // the listener passes on the connection of every command and request
+                _ => unreachable!(),
             }
         }
     }
@@ -246,6 +713,46 @@ impl<'a> Protocol<'a> {
         self.state().peers.get_by_id(id)
     }
 
+    // find the network ID of a recipient, which may be given by name, address or key fingerprint
+    fn resolve_recipient(&self, rcp: &Recipient) -> Result<u32, SubmitError> {
+        let state = self.state();
+
+        let publ_key = match rcp {
+            Recipient::Id(id) => return Ok(*id),
+            Recipient::Alias(name) => state
+                .address_book
+                .get(name)
+                .ok_or_else(|| SubmitError::UnknownName(name.clone()))?,
+            Recipient::Fingerprint(fingerprint) => state
+                .peers
+                .iter()
+                .publ_keys()
+                .find(|key| key.fingerprint_with_len(fingerprint.len()) == *fingerprint)
+                .ok_or(SubmitError::RecipientNotFound)?,
+            Recipient::Address(addr) => state
+                .peers
+                .iter()
+                .publ_keys()
+                .find(|key| key.address() == *addr)
+                .ok_or(SubmitError::RecipientNotFound)?,
+        };
+
+        state
+            .peers
+            .get_by_publ_key(publ_key)
+            .map(|peer| peer.id())
+            .ok_or(SubmitError::RecipientNotFound)
+    }
+
+    fn contact(&self, name: &str, publ_key: &PublicKey) -> Contact {
+        Contact {
+            name: name.to_string(),
+            key: publ_key.fingerprint(),
+            account: publ_key.address(),
+            id: self.state().peers.get_by_publ_key(publ_key).map(|p| p.id()),
+        }
+    }
+
     fn local_soft_account(&self) -> &Account {
         self.state()
             .soft_accounts
@@ -253,218 +760,713 @@ impl<'a> Protocol<'a> {
             .expect("Local account not found")
     }
 
-    fn handle_command(&mut self, command: Command, mut stream: TcpStream) {
-        // t command
-        fn new_transfer(
-            protocol: &Protocol,
-            recp_id: u32,
-            amnt: NonZeroU32,
-            stream: &mut TcpStream,
-        ) -> Option<Transaction> {
-            let sndr = protocol.local_peer();
-            let sndr_acc = protocol.local_soft_account();
+    // build a transfer from the local account (t command), given its soft state
+    fn new_transfer(
+        &self,
+        sndr_acc: &Account,
+        recp_id: u32,
+        amnt: NonZeroU32,
+    ) -> Result<Transaction, SubmitError> {
+        let sndr = self.local_peer();
+
+        if recp_id == sndr.id() {
+            return Err(SubmitError::SelfTransfer);
+        }
 
-            if recp_id == sndr.id() {
-                if let Err(e) = stream.write_all("You cannot send coins to yourself".as_bytes()) {
-                    log::warn!("Failed to respond to `t` command: {}", e);
-                } else {
-                    log::trace!("Successfully responded to `t` command");
-                }
-                return None;
-            }
+        let recp = self
+            .network_peer(recp_id)
+            .ok_or(SubmitError::RecipientNotFound)?;
 
-            let recp = match protocol.network_peer(recp_id) {
-                Some(peer) => peer,
-                None => {
-                    if let Err(e) = stream.write_all("Recipient not found".as_bytes()) {
-                        log::warn!("Failed to respond to `t` command: {}", e);
-                    } else {
-                        log::trace!("Successfully responded to `t` command");
-                    }
-                    return None;
-                }
-            };
+        // coins to cents conversion
+        // (no account can hold more cents than fit in a u32)
+        let amnt_cents = amnt
+            .checked_mul(chain_params().cents_per_coin.try_into().unwrap())
+            .ok_or(SubmitError::NotEnoughCoins)?;
 
-            // coins to cents conversion
-            let amnt_cents = amnt
-                .checked_mul(CENTS_PER_COIN.try_into().unwrap())
-                .unwrap();
+        if sndr_acc.held_cents() < Transaction::calculate_transfer_total_cost(amnt_cents) {
+            return Err(SubmitError::NotEnoughCoins);
+        }
 
-            if sndr_acc.held_cents() < Transaction::calculate_transfer_total_cost(amnt_cents) {
-                if let Err(e) = stream.write_all("Not enough coins".as_bytes()) {
-                    log::warn!("Failed to respond to `t` command: {}", e);
-                } else {
-                    log::trace!("Successfully responded to `t` command");
-                }
-                return None;
-            }
+        Ok(Transaction::new_transfer(
+            sndr.publ_key().clone(),
+            recp.publ_key().clone(),
+            amnt_cents,
+            sndr_acc.nonce_pool().next(),
+            &self.priv_key,
+        ))
+    }
+
+    // build a message from the local account (m command), given its soft state
+    fn new_message(
+        &self,
+        sndr_acc: &Account,
+        recp_id: u32,
+        message: String,
+        encrypted: bool,
+    ) -> Result<Transaction, SubmitError> {
+        let sndr = self.local_peer();
+
+        if recp_id == sndr.id() {
+            return Err(SubmitError::SelfMessage);
+        }
 
-            Some(Transaction::new_transfer(
+        let recp = self
+            .network_peer(recp_id)
+            .ok_or(SubmitError::RecipientNotFound)?;
+
+        let message = NonEmptyString::new(message).map_err(|_| SubmitError::EmptyMessage)?;
+
+        let tsx = if encrypted {
+            // only the recipient will be able to read the message
+            Transaction::new_encrypted_message(
+                sndr.publ_key().clone(),
+                recp.publ_key().clone(),
+                EncryptedMessage::seal(recp.publ_key(), &message),
+                sndr_acc.nonce_pool().next(),
+                &self.priv_key,
+            )
+        } else {
+            Transaction::new_message(
                 sndr.publ_key().clone(),
                 recp.publ_key().clone(),
-                amnt_cents,
+                message,
                 sndr_acc.nonce_pool().next(),
-                &protocol.priv_key,
-            ))
+                &self.priv_key,
+            )
+        };
+
+        if sndr_acc.held_cents() < tsx.total_cost() {
+            return Err(SubmitError::NotEnoughCoins);
         }
 
-        // m command
-        fn new_message(
-            protocol: &Protocol,
-            recp_id: u32,
-            message: String,
-            stream: &mut TcpStream,
-        ) -> Option<Transaction> {
-            let sndr = protocol.local_peer();
-            let sndr_acc = protocol.local_soft_account();
+        Ok(tsx)
+    }
+
+    // find the network IDs of the recipients of a multi-transfer
+    fn resolve_payments(
+        &self,
+        payments: &[Payment],
+    ) -> Result<Vec<(u32, NonZeroU32)>, SubmitError> {
+        payments
+            .iter()
+            .map(|p| self.resolve_recipient(&p.rcp).map(|id| (id, p.amt)))
+            .collect()
+    }
+
+    // build a transfer to several recipients from the local account (mt command), given its soft state
+    fn new_multi_transfer(
+        &self,
+        sndr_acc: &Account,
+        payments: Vec<(u32, NonZeroU32)>,
+    ) -> Result<Transaction, SubmitError> {
+        let sndr = self.local_peer();
 
+        if payments.len() > MAX_TRANSFER_OUTPUTS {
+            return Err(SubmitError::TooManyRecipients);
+        }
+
+        let mut recp_ids = HashSet::new();
+        let mut outputs = vec![];
+        for (recp_id, amnt) in payments {
             if recp_id == sndr.id() {
-                if let Err(e) = stream.write_all("You cannot message yourself".as_bytes()) {
-                    log::warn!("Failed to respond to `m` command: {}", e);
-                } else {
-                    log::trace!("Successfully responded to `m` command");
-                }
-                return None;
+                return Err(SubmitError::SelfTransfer);
+            }
+            if !recp_ids.insert(recp_id) {
+                return Err(SubmitError::DuplicateRecipient(recp_id));
             }
 
-            let recp = match protocol.network_peer(recp_id) {
-                Some(peer) => peer,
-                None => {
-                    if let Err(e) = stream.write_all("Recipient not found".as_bytes()) {
-                        log::warn!("Failed to respond to `m` command: {}", e);
-                    } else {
-                        log::trace!("Successfully responded to `m` command");
-                    }
-                    return None;
+            let recp = self
+                .network_peer(recp_id)
+                .ok_or(SubmitError::RecipientNotFound)?;
+
+            // coins to cents conversion
+            let amnt_cents = amnt
+                .checked_mul(chain_params().cents_per_coin.try_into().unwrap())
+                .ok_or(SubmitError::NotEnoughCoins)?;
+
+            outputs.push(TransferOutput::new(recp.publ_key().clone(), amnt_cents));
+        }
+
+        // the cost saturates, so it cannot look affordable if it does not fit in a u32
+        if sndr_acc.held_cents() < Transaction::calculate_multi_transfer_total_cost(&outputs) {
+            return Err(SubmitError::NotEnoughCoins);
+        }
+
+        Ok(Transaction::new_multi_transfer(
+            sndr.publ_key().clone(),
+            outputs,
+            sndr_acc.nonce_pool().next(),
+            &self.priv_key,
+        ))
+    }
+
+    // build a stake of the local account (stake command), given its soft state
+    fn new_stake(&self, sndr_acc: &Account, amnt: NonZeroU32) -> Result<Transaction, SubmitError> {
+        let sndr = self.local_peer();
+
+        // coins to cents conversion
+        let amnt_cents = amnt
+            .checked_mul(chain_params().cents_per_coin.try_into().unwrap())
+            .ok_or(SubmitError::NotEnoughCoins)?;
+
+        if sndr_acc.held_cents() < Transaction::calculate_stake_total_cost(amnt_cents) {
+            return Err(SubmitError::NotEnoughCoins);
+        }
+
+        Ok(Transaction::new_stake(
+            sndr.publ_key().clone(),
+            amnt_cents,
+            sndr_acc.nonce_pool().next(),
+            &self.priv_key,
+        ))
+    }
+
+    // build the transactions of a batch in order, each against the soft state left by the
+    // valid ones before it (so that they get consecutive nonces)
+    fn new_batch(&self, items: Vec<BatchItem>) -> Vec<Result<Transaction, SubmitError>> {
+        let mut soft_accounts = self.state().soft_accounts.clone();
+
+        items
+            .into_iter()
+            .map(|item| {
+                let sndr_acc = soft_accounts
+                    .get_by_id(self.state().id)
+                    .expect("Local account not found");
+
+                let tsx = match item {
+                    BatchItem::Transfer { rcp, amt } => self
+                        .resolve_recipient(&rcp)
+                        .and_then(|id| self.new_transfer(sndr_acc, id, amt)),
+                    BatchItem::Message {
+                        rcp,
+                        msg,
+                        encrypted,
+                    } => self
+                        .resolve_recipient(&rcp)
+                        .and_then(|id| self.new_message(sndr_acc, id, msg, encrypted)),
+                    BatchItem::MultiTransfer { payments } => self
+                        .resolve_payments(&payments)
+                        .and_then(|payments| self.new_multi_transfer(sndr_acc, payments)),
+                    BatchItem::Stake { amt } => self.new_stake(sndr_acc, amt),
+                }?;
+
+                // this should not panic (the transaction was built against these accounts)
+                soft_accounts.process_transaction(&tsx).unwrap();
+
+                Ok(tsx)
+            })
+            .collect()
+    }
+
+    // the status of a transaction submitted to (or seen by) the local node
+    fn transaction_info(&self, hash: [u8; 32]) -> Option<TransactionInfo> {
+        let state = self.state();
+        let summary = |tsxs: &[Transaction]| {
+            tsxs.iter()
+                .find(|tsx| *tsx.hash() == hash)
+                .map(|tsx| TransactionSummary::from_transaction(tsx, state.peers))
+        };
+
+        let info = if let Some(tsx) = summary(&state.pending_transactions) {
+            TransactionInfo {
+                hash: hash.encode_hex(),
+                status: TransactionState::Pending,
+                block: None,
+                confirmations: None,
+                reason: None,
+                transaction: Some(tsx),
+            }
+        } else {
+            match state.tsx_statuses.get(&hash)? {
+                TransactionStatus::Included(index) => TransactionInfo {
+                    hash: hash.encode_hex(),
+                    status: TransactionState::Included,
+                    block: Some(*index),
+                    confirmations: Some(state.blockchain.last_block().index() - index + 1),
+                    reason: None,
+                    transaction: state
+                        .blockchain
+                        .get_block(*index)
+                        .ok()
+                        .and_then(|blk| summary(blk.tsxs())),
+                },
+                TransactionStatus::Dropped(_, reason) => TransactionInfo {
+                    hash: hash.encode_hex(),
+                    status: TransactionState::Dropped,
+                    block: None,
+                    confirmations: None,
+                    reason: Some(reason.clone()),
+                    transaction: None,
+                },
+            }
+        };
+
+        Some(info)
+    }
+
+    // keep track of the connections with a peer
+    fn handle_peer_link(&mut self, id: u32, update: impl FnOnce(&mut PeerLink)) {
+        let link = self.state_mut().peer_links.entry(id).or_insert(PeerLink {
+            inbound: 0,
+            outbound: false,
+            since: Instant::now(),
+        });
+
+        let was_connected = link.is_connected();
+        update(link);
+
+        if was_connected != link.is_connected() {
+            link.since = Instant::now();
+        }
+    }
+
+    // whoami command
+    fn whoami(&self) -> PeerIdentity {
+        let peer = self.local_peer();
+
+        PeerIdentity {
+            id: peer.id(),
+            key: peer.publ_key().fingerprint(),
+            account: peer.publ_key().address(),
+            address: peer.sock_addr(),
+        }
+    }
+
+    // peers command
+    fn peer_summaries(&self) -> Vec<PeerSummary> {
+        let state = self.state();
+        let cents_per_coin = chain_params().cents_per_coin as f64;
+
+        state
+            .peers
+            .iter()
+            .peers_by_id_asc()
+            .map(|peer| {
+                let (liveness, since) = match state.peer_links.get(&peer.id()) {
+                    _ if peer.id() == state.id => (Liveness::Local, None),
+                    Some(link) if link.is_connected() => (Liveness::Connected, Some(link.since)),
+                    Some(link) => (Liveness::Disconnected, Some(link.since)),
+                    None => (Liveness::Unknown, None),
+                };
+                let acc = state.soft_accounts.get_by_id(peer.id());
+
+                PeerSummary {
+                    id: peer.id(),
+                    key: peer.publ_key().fingerprint(),
+                    account: peer.publ_key().address(),
+                    address: peer.sock_addr(),
+                    balance: acc.map_or(0, |a| a.held_cents()) as f64 / cents_per_coin,
+                    stake: acc.map_or(0, |a| a.staked_cents()) as f64 / cents_per_coin,
+                    liveness,
+                    since_secs: since.map(|t| t.elapsed().as_secs()),
                 }
+            })
+            .collect()
+    }
+
+    // network command
+    fn network_info(&self) -> NetworkInfo {
+        let state = self.state();
+
+        NetworkInfo {
+            id: state.id,
+            connected_peers: state
+                .peer_links
+                .values()
+                .filter(|l| l.is_connected())
+                .count(),
+            other_peers: state.peers.len() - 1,
+            height: state.blockchain.last_block().index(),
+            mempool: state.pending_transactions.len(),
+        }
+    }
+
+    // chain-info command
+    fn chain_info(&self) -> ChainInfo {
+        let state = self.state();
+        let last = state.blockchain.last_block();
+        let cents_per_coin = chain_params().cents_per_coin as f64;
+        let total_held: u64 = state
+            .hard_accounts
+            .iter()
+            .map(|a| a.held_cents() as u64)
+            .sum();
+        let total_staked: u64 = state
+            .hard_accounts
+            .iter()
+            .map(|a| a.staked_cents() as u64)
+            .sum();
+
+        ChainInfo {
+            height: last.index(),
+            tip: last.hash().encode_hex(),
+            oldest_retained: state.blockchain.oldest_retained(),
+            pending_transactions: state.pending_transactions.len(),
+            total_supply: (total_held + total_staked) as f64 / cents_per_coin,
+            total_staked: total_staked as f64 / cents_per_coin,
+            next_validator: self.proof_of_stake(),
+        }
+    }
+
+    fn handle_command(&mut self, command: Command, mut stream: TcpStream) {
+        // reply with the reason a block cannot be shown
+        fn block_error(e: BlockchainError) -> Response {
+            let code = match e {
+                BlockchainError::Pruned { .. } => ErrorCode::BlockPruned,
+                BlockchainError::NotFound { .. } => ErrorCode::BlockNotFound,
             };
 
-            let message = match NonEmptyString::new(message) {
-                Ok(message) => message,
-                Err(_) => {
-                    if let Err(e) = stream.write_all("Message cannot be empty".as_bytes()) {
-                        log::warn!("Failed to respond to `m` command: {}", e);
-                    } else {
-                        log::trace!("Successfully responded to `m` command");
-                    }
-                    return None;
-                }
+            Response::error(code, e)
+        }
+
+        // block command
+        fn block(protocol: &Protocol, blk: BlockRef) -> Response {
+            let blockchain = &protocol.state().blockchain;
+
+            let index = match blk {
+                BlockRef::Index(index) => index,
+                BlockRef::Hash(hash) => match blockchain.find_index(&hash) {
+                    Some(index) => index,
+                    None => return Response::error(ErrorCode::BlockNotFound, "Block not found"),
+                },
             };
 
-            if sndr_acc.held_cents() < Transaction::calculate_message_total_cost(&message) {
-                if let Err(e) = stream.write_all("Not enough coins".as_bytes()) {
-                    log::warn!("Failed to respond to `m` command: {}", e);
-                } else {
-                    log::trace!("Successfully responded to `m` command");
+            match blockchain.get_block(index) {
+                Ok(blk) => {
+                    Response::Block(Box::new(BlockInfo::from_block(blk, protocol.state().peers)))
                 }
-                return None;
+                Err(e) => block_error(e),
             }
-
-            Some(Transaction::new_message(
-                sndr.publ_key().clone(),
-                recp.publ_key().clone(),
-                message,
-                sndr_acc.nonce_pool().next(),
-                &protocol.priv_key,
-            ))
         }
 
-        // s command
-        fn new_stake(
-            protocol: &Protocol,
-            amnt: NonZeroU32,
-            stream: &mut TcpStream,
-        ) -> Option<Transaction> {
-            let sndr = protocol.local_peer();
-            let sndr_acc = protocol.local_soft_account();
+        // blocks command
+        fn blocks(protocol: &Protocol, range: BlockRange) -> Response {
+            let blockchain = &protocol.state().blockchain;
 
-            // coins to cents conversion
-            let amnt_cents = amnt
-                .checked_mul(CENTS_PER_COIN.try_into().unwrap())
-                .unwrap();
+            if let Err(e) = blockchain.get_block(range.from) {
+                return block_error(e);
+            }
 
-            if sndr_acc.held_cents() < Transaction::calculate_stake_total_cost(amnt_cents) {
-                if let Err(e) = stream.write_all("Not enough coins".as_bytes()) {
-                    log::warn!("Failed to respond to `stake` command: {}", e);
-                } else {
-                    log::trace!("Successfully responded to `stake` command");
-                }
-                return None;
+            // the range can extend past the last block
+            let last = blockchain.last_block().index();
+            let to = range.to.map_or(last, |to| to.min(last));
+
+            if to < range.from {
+                return Response::error(
+                    ErrorCode::InvalidParams,
+                    "The range cannot end before it starts",
+                );
             }
 
-            Some(Transaction::new_stake(
-                sndr.publ_key().clone(),
-                amnt_cents,
-                sndr_acc.nonce_pool().next(),
-                &protocol.priv_key,
-            ))
+            if to - range.from >= MAX_BLOCKS_PER_RANGE {
+                return Response::error(
+                    ErrorCode::InvalidParams,
+                    format!(
+                        "At most {} blocks can be shown at once",
+                        MAX_BLOCKS_PER_RANGE
+                    ),
+                );
+            }
+
+            // the first block is retained, so all the blocks after it are as well
+            let blks = (range.from..=to)
+                .filter_map(|index| blockchain.get_block(index).ok())
+                .map(|blk| BlockInfo::from_block(blk, protocol.state().peers))
+                .collect();
+
+            Response::Blocks(blks)
         }
 
-        // b command
-        fn send_balance(account: &Account, stream: &mut TcpStream) {
-            let reply = format!(
-                "Balance: {} held, {} staked",
-                account.held_cents() as f64 / CENTS_PER_COIN as f64,
-                account.staked_cents() as f64 / CENTS_PER_COIN as f64
+        // tx-history command
+        fn statement_page(
+            protocol: &Protocol,
+            acc_id: Option<u32>,
+            page: NonZeroU32,
+            page_size: NonZeroU32,
+        ) -> Response {
+            let state = protocol.state();
+
+            let Some(peer) =
+                acc_id.map_or(Some(protocol.local_peer()), |id| protocol.network_peer(id))
+            else {
+                return Response::error(ErrorCode::AccountNotFound, "Account not found");
+            };
+
+            let statement = Statement::new(
+                peer.publ_key(),
+                &state.blockchain,
+                &state.pending_transactions,
+                state.peers,
             );
+            let (page, page_size) = (page.get() as usize, page_size.get() as usize);
+
+            Response::TxHistory(Box::new(TxHistoryPage {
+                account: peer.id(),
+                page,
+                pages: statement.page_count(page_size).max(1),
+                total: statement.len(),
+                first_block: statement.first_block(),
+                entries: statement.page(page, page_size).to_vec(),
+            }))
+        }
 
-            if let Err(e) = stream.write_all(reply.as_bytes()) {
-                log::warn!("Failed to respond to `balance` command: {}", e);
-            } else {
-                log::trace!("Successfully responded to `balance` command");
+        // inbox command
+        fn inbox(protocol: &mut Protocol, filter: InboxFilter, mark_read: bool) -> Response {
+            let inbox = &mut protocol.state_mut().inbox;
+
+            let messages = inbox
+                .query(&filter)
+                .map(|(entry, read)| InboxMessage::new(entry, read))
+                .collect();
+
+            if mark_read {
+                if let Err(e) = inbox.mark_read(&filter) {
+                    log::warn!("Failed to save the read state of the inbox: {}", e);
+                }
             }
-        }
 
-        // v command
-        fn send_last_block(blockchain: &Blockchain, stream: &mut TcpStream) {
-            let reply = format!("Last block: {:#?}", blockchain.last_block());
+            Response::Inbox {
+                messages,
+                unread: inbox.unread_count(),
+            }
+        }
 
-            if let Err(e) = stream.write_all(reply.as_bytes()) {
-                log::warn!("Failed to respond to `view` command: {}", e);
-            } else {
-                log::trace!("Successfully responded to `view` command");
+        // batch command
+        fn batch(protocol: &mut Protocol, items: Vec<BatchItem>, atomic: bool) -> Response {
+            if items.is_empty() || items.len() > MAX_BATCH_LEN {
+                return Response::error(
+                    ErrorCode::InvalidParams,
+                    format!("A batch has between 1 and {} transactions", MAX_BATCH_LEN),
+                );
             }
+
+            let texts: Vec<_> = items.iter().map(|item| item.to_string()).collect();
+            let tsxs = protocol.new_batch(items);
+            // an atomic batch is sent only if all of its transactions are valid
+            let rejected = atomic && tsxs.iter().any(Result::is_err);
+
+            let results = texts
+                .into_iter()
+                .zip(tsxs)
+                .map(|(item, tsx)| {
+                    let outcome = match tsx {
+                        Err(e) => BatchOutcome::Failed {
+                            code: e.code(),
+                            reason: e.to_string(),
+                        },
+                        Ok(_) if rejected => BatchOutcome::Skipped,
+                        Ok(tsx) => {
+                            let outcome = BatchOutcome::Submitted {
+                                hash: tsx.hash().encode_hex(),
+                                nonce: tsx.nonce(),
+                            };
+                            protocol.handle_transaction(tsx, None, true);
+                            outcome
+                        }
+                    };
+
+                    BatchResult { item, outcome }
+                })
+                .collect();
+
+            Response::Batch(BatchReport { atomic, results })
         }
 
-        // h command
-        fn send_history(history: History, stream: &mut TcpStream) {
-            let history_bytes = serde_json::to_vec(&history).expect("Failed to serialize history");
+        // key command
+        fn key(protocol: &Protocol, peer: Option<Recipient>, format: KeyFormat) -> Response {
+            let id = match peer.map(|peer| protocol.resolve_recipient(&peer)) {
+                None => protocol.state().id,
+                Some(Ok(id)) => id,
+                Some(Err(e)) => return e.into(),
+            };
+            let Some(peer) = protocol.network_peer(id) else {
+                return SubmitError::RecipientNotFound.into();
+            };
 
-            if let Err(e) = stream.write_all(&history_bytes) {
-                log::warn!("Failed to respond to `history` command: {}", e);
-            } else {
-                log::trace!("Successfully responded to `history` command");
-            }
+            let publ_key = peer.publ_key();
+            let Ok(key) = String::from_utf8(publ_key.export(format)) else {
+                return Response::error(
+                    ErrorCode::InvalidParams,
+                    "The key cannot be exported as text",
+                );
+            };
+
+            Response::Key(KeyExport {
+                id,
+                fingerprint: publ_key.fingerprint_with_len(crypto::FULL_FINGERPRINT_HEX_LEN),
+                format,
+                key,
+            })
         }
 
-        use Command::*;
-        match command {
-            T { rcp_id, amt } => {
-                if let Some(tsx) = new_transfer(self, rcp_id, amt, &mut stream) {
-                    self.handle_transaction(tsx, Some(stream), true);
-                }
+        // address-book command
+        fn address_book(protocol: &mut Protocol, cmd: AddressBookCommand) -> Response {
+            fn error(e: AddressBookError) -> Response {
+                let code = match e {
+                    AddressBookError::NotFound(_) => ErrorCode::ContactNotFound,
+                    AddressBookError::InvalidName(_) | AddressBookError::DuplicateName(_) => {
+                        ErrorCode::InvalidParams
+                    }
+                    _ => ErrorCode::InternalError,
+                };
+
+                Response::error(code, e)
             }
 
-            M { rcp_id, msg } => {
-                if let Some(tsx) = new_message(self, rcp_id, msg.join(" "), &mut stream) {
-                    self.handle_transaction(tsx, Some(stream), true);
+            match cmd {
+                AddressBookCommand::Add { name, peer } => {
+                    let publ_key = match protocol.resolve_recipient(&peer) {
+                        Ok(id) => match protocol.network_peer(id) {
+                            Some(peer) => peer.publ_key().clone(),
+                            None => return SubmitError::RecipientNotFound.into(),
+                        },
+                        Err(e) => return e.into(),
+                    };
+
+                    match protocol
+                        .state_mut()
+                        .address_book
+                        .add(&name, publ_key.clone())
+                    {
+                        Ok(()) => Response::ContactAdded(protocol.contact(&name, &publ_key)),
+                        Err(e) => error(e),
+                    }
+                }
+                AddressBookCommand::List => Response::AddressBook(
+                    protocol
+                        .state()
+                        .address_book
+                        .iter()
+                        .map(|(name, publ_key)| protocol.contact(name, publ_key))
+                        .collect(),
+                ),
+                AddressBookCommand::Remove { name } => {
+                    match protocol.state_mut().address_book.remove(&name) {
+                        Ok(publ_key) => {
+                            Response::ContactRemoved(protocol.contact(&name, &publ_key))
+                        }
+                        Err(e) => error(e),
+                    }
                 }
             }
+        }
 
-            S { amt } => {
-                if let Some(tsx) = new_stake(self, amt, &mut stream) {
-                    self.handle_transaction(tsx, Some(stream), true);
-                }
+        use Command::*;
+        let (name, response) = match command {
+            T { rcp, amt, wait } => match self
+                .resolve_recipient(&rcp)
+                .and_then(|id| self.new_transfer(self.local_soft_account(), id, amt))
+            {
+                Ok(tsx) => return self.handle_transaction(tsx, Some((stream, wait)), true),
+                Err(e) => ("t", e.into()),
+            },
+
+            M {
+                rcp,
+                msg,
+                encrypted,
+                wait,
+            } => match self.resolve_recipient(&rcp).and_then(|id| {
+                self.new_message(self.local_soft_account(), id, msg.join(" "), encrypted)
+            }) {
+                Ok(tsx) => return self.handle_transaction(tsx, Some((stream, wait)), true),
+                Err(e) => ("m", e.into()),
+            },
+
+            S { amt, wait } => match self.new_stake(self.local_soft_account(), amt) {
+                Ok(tsx) => return self.handle_transaction(tsx, Some((stream, wait)), true),
+                Err(e) => ("stake", e.into()),
+            },
+
+            Mt { payments, wait } => match self
+                .resolve_payments(&payments)
+                .and_then(|payments| self.new_multi_transfer(self.local_soft_account(), payments))
+            {
+                Ok(tsx) => return self.handle_transaction(tsx, Some((stream, wait)), true),
+                Err(e) => ("mt", e.into()),
+            },
+
+            Batch { items, atomic, .. } => ("batch", batch(self, items, atomic)),
+
+            B => (
+                "balance",
+                Response::Balance(Balance::from_account(
+                    self.state().id,
+                    self.local_soft_account(),
+                )),
+            ),
+            Block { blk } => ("block", block(self, blk)),
+            Blocks { range } => ("blocks", blocks(self, range)),
+            ChainInfo => ("chain-info", Response::ChainInfo(self.chain_info())),
+            // also used by the helper to determine which file to read from during benchmarking
+            Whoami => ("whoami", Response::Whoami(self.whoami())),
+            Key { peer, format } => ("key", key(self, peer, format)),
+            Peers => ("peers", Response::Peers(self.peer_summaries())),
+            Network => ("network", Response::Network(self.network_info())),
+            AddressBook(cmd) => ("address-book", address_book(self, cmd)),
+            Mempool => (
+                "mempool",
+                Response::Mempool(
+                    self.state()
+                        .pending_transactions
+                        .iter()
+                        .map(|tsx| TransactionSummary::from_transaction(tsx, self.state().peers))
+                        .collect(),
+                ),
+            ),
+            V => (
+                "view",
+                Response::Block(Box::new(BlockInfo::from_block(
+                    self.state().blockchain.last_block(),
+                    self.state().peers,
+                ))),
+            ),
+            Tx {
+                acc_id,
+                page,
+                page_size,
+            } => ("tx-history", statement_page(self, acc_id, page, page_size)),
+            Status { hash } => (
+                "status",
+                self.transaction_info(hash).map_or_else(
+                    || Response::error(ErrorCode::TransactionNotFound, "Transaction not found"),
+                    Response::Status,
+                ),
+            ),
+            Inbox {
+                from,
+                since,
+                until,
+                unread,
+                keep_unread,
+            } => {
+                let filter = InboxFilter {
+                    sndr_id: from,
+                    since,
+                    until,
+                    unread_only: unread,
+                };
+                ("inbox", inbox(self, filter, !keep_unread))
             }
+            Subscribe { types } => {
+                if let Err(e) = stream.set_write_timeout(Some(SUBSCRIBER_WRITE_TIMEOUT)) {
+                    log::warn!("Failed to respond to `subscribe` command: {}", e);
+                    return;
+                }
 
-            B => send_balance(self.local_soft_account(), &mut stream),
-            V => send_last_block(&self.state().blockchain, &mut stream),
-            H => send_history(History::global_history(), &mut stream),
+                let types = if types.is_empty() {
+                    EventType::value_variants().to_vec()
+                } else {
+                    types
+                };
 
-            // used only by the helper to determine which file to read from during benchmarking
-            Id => stream
-                .write_all(self.local_peer().id().to_string().as_bytes())
-                .unwrap(),
+                log::trace!("New subscriber for {:?}", types);
+                self.state_mut()
+                    .subscribers
+                    .push(Subscriber::spawn(stream, types));
+                return;
+            }
+            H => ("history", Response::History(History::global_history())),
 
             // used only for benchmarking
             // calculate the average transaction time
@@ -476,25 +1478,126 @@ impl<'a> Protocol<'a> {
                 let tsx_avg = tsx_times.iter().sum::<Duration>() / tsx_times.len() as u32;
                 let blk_avg = blk_times.iter().sum::<Duration>() / blk_times.len() as u32;
 
-                let reply = format!(
-                    "Average transaction time: {} ms\nAverage block time: {} ms\n",
-                    tsx_avg.as_secs_f64() * 1000.0,
-                    blk_avg.as_secs_f64() * 1000.0,
-                );
-
-                stream.write_all(reply.as_bytes()).unwrap()
+                (
+                    "time",
+                    Response::Time {
+                        avg_transaction_ms: tsx_avg.as_secs_f64() * 1000.0,
+                        avg_block_ms: blk_avg.as_secs_f64() * 1000.0,
+                    },
+                )
             }
 
             // used only for benchmarking
-            Stats => {
-                let reply = History::global_stats();
+            Stats => ("stats", Response::Stats(History::global_stats())),
+        };
+
+        if let Err(e) = response.write_to(&mut stream) {
+            log::warn!("Failed to respond to `{}` command: {}", name, e);
+        } else {
+            log::trace!("Successfully responded to `{}` command", name);
+        }
+    }
+
+    fn handle_rpc(&mut self, call: RpcCall) -> RpcResult {
+        fn to_value(res: impl Serialize) -> RpcResult {
+            serde_json::to_value(res).map_err(|e| RpcError::new(ErrorCode::InternalError, e))
+        }
+
+        // submit a locally created transaction and return its hash
+        let submit = |protocol: &mut Self, tsx: Result<Transaction, SubmitError>| {
+            let tsx = tsx?;
+            let hash = tsx.hash().encode_hex();
+            protocol.handle_transaction(tsx, None, true);
+            to_value(Submitted { hash })
+        };
+
+        match call {
+            RpcCall::GetBalance { account } => {
+                let id = account.unwrap_or(self.state().id);
+                let acc = self.state().soft_accounts.get_by_id(id).ok_or_else(|| {
+                    RpcError::new(ErrorCode::AccountNotFound, "Account not found")
+                })?;
+
+                to_value(Balance::from_account(id, acc))
+            }
+
+            RpcCall::SendTransfer { recipient, amount } => {
+                let tsx = self.new_transfer(self.local_soft_account(), recipient, amount);
+                submit(self, tsx)
+            }
+
+            RpcCall::SendMessage {
+                recipient,
+                message,
+                encrypted,
+            } => {
+                let tsx =
+                    self.new_message(self.local_soft_account(), recipient, message, encrypted);
+                submit(self, tsx)
+            }
+
+            RpcCall::SendMultiTransfer { outputs } => {
+                let payments = outputs
+                    .into_iter()
+                    .map(|out| (out.recipient, out.amount))
+                    .collect();
+                let tsx = self.new_multi_transfer(self.local_soft_account(), payments);
+                submit(self, tsx)
+            }
+
+            RpcCall::Stake { amount } => {
+                let tsx = self.new_stake(self.local_soft_account(), amount);
+                submit(self, tsx)
+            }
+
+            RpcCall::GetBlock { index } => {
+                let blockchain = &self.state().blockchain;
+                let index = index.unwrap_or(blockchain.last_block().index());
+
+                let blk = blockchain.get_block(index).map_err(|e| match e {
+                    BlockchainError::Pruned {
+                        oldest_retained, ..
+                    } => RpcError::new(ErrorCode::BlockPruned, &e)
+                        .with_data(json!({ "oldest_retained": oldest_retained })),
+                    BlockchainError::NotFound { last, .. } => {
+                        RpcError::new(ErrorCode::BlockNotFound, &e)
+                            .with_data(json!({ "last": last }))
+                    }
+                })?;
+
+                to_value(BlockInfo::from_block(blk, self.state().peers))
+            }
+
+            RpcCall::GetTransaction { hash } => {
+                let mut hash_bytes = [0; 32];
+                hex::decode_to_slice(&hash, &mut hash_bytes).map_err(|e| {
+                    RpcError::new(ErrorCode::InvalidParams, format!("Invalid hash: {}", e))
+                })?;
+
+                let info = self.transaction_info(hash_bytes).ok_or_else(|| {
+                    RpcError::new(ErrorCode::TransactionNotFound, "Transaction not found")
+                })?;
 
-                stream.write_all(reply.as_bytes()).unwrap();
+                to_value(info)
             }
+
+            RpcCall::GetPeers {} => to_value(
+                self.state()
+                    .peers
+                    .iter()
+                    .map(PeerInfo::from)
+                    .collect::<Vec<_>>(),
+            ),
         }
     }
 
-    fn handle_transaction(&mut self, tsx: Transaction, stream: Option<TcpStream>, is_local: bool) {
+    // the client (if any) is the one that submitted the transaction
+    fn handle_transaction(
+        &mut self,
+        tsx: Transaction,
+        client: Option<(TcpStream, WaitOptions)>,
+        is_local: bool,
+    ) {
         if is_local {
             History::log_local_transaction(&tsx, self.state().peers);
 
@@ -512,12 +1615,16 @@ impl<'a> Protocol<'a> {
                 panic!("Debug assertion failed: {}", e);
             }
         } else {
-            History::log_network_transaction(&tsx, self.state().peers);
+            History::log_network_transaction(&tsx, self.state().peers, &self.priv_key);
 
             // validate the structure of the transaction (ignore context)
             if let Err(e) = TransactionValidator::validate_structure(&tsx) {
                 History::log_invalid_transaction(&tsx, self.state().peers);
                 log::warn!("Received invalid transaction:\n{}\n{:#?}", e, tsx);
+                self.publish(Event::InvalidTransaction {
+                    hash: tsx.hash().encode_hex(),
+                    reason: e.to_string(),
+                });
                 return;
             }
 
@@ -527,6 +1634,10 @@ impl<'a> Protocol<'a> {
             {
                 History::log_invalid_transaction(&tsx, self.state().peers);
                 log::warn!("Received invalid transaction:\n{}\n{:#?}", e, tsx);
+                self.publish(Event::InvalidTransaction {
+                    hash: tsx.hash().encode_hex(),
+                    reason: e.to_string(),
+                });
                 return;
             }
         }
@@ -538,12 +1649,27 @@ impl<'a> Protocol<'a> {
             .unwrap();
 
         self.state_mut().pending_transactions.push(tsx.clone());
+        self.publish(Event::from_transaction(&tsx, self.state().peers));
+
+        if let Some((mut stream, wait)) = client {
+            let response = Response::Submitted {
+                hash: tsx.hash().encode_hex(),
+            };
 
-        if let Some(mut stream) = stream {
-            if let Err(e) = stream.write_all("Transaction successful".as_bytes()) {
+            if let Err(e) = response.write_to(&mut stream) {
                 log::warn!("Failed to send success to client: {}", e);
             } else {
                 log::trace!("Successfully sent success to client");
+
+                // keep the stream open until the transaction is confirmed (or it times out)
+                if let Some(confirmations) = wait.confirmations() {
+                    self.state_mut().waiters.push(Waiter {
+                        stream,
+                        hash: *tsx.hash(),
+                        confirmations: confirmations.get(),
+                        deadline: Instant::now() + wait.timeout(),
+                    });
+                }
             }
         }
 
@@ -586,6 +1712,10 @@ impl<'a> Protocol<'a> {
             if let Err(e) = BlockValidator::validate_structure(&blk) {
                 History::log_invalid_block(&blk, self.state().peers);
                 log::warn!("Received invalid block:\n{}\n{:#?}", e, blk);
+                self.publish(Event::InvalidBlock {
+                    hash: blk.hash().encode_hex(),
+                    reason: e.to_string(),
+                });
                 return;
             }
 
@@ -598,6 +1728,10 @@ impl<'a> Protocol<'a> {
             ) {
                 History::log_invalid_block(&blk, self.state().peers);
                 log::warn!("Received invalid block:\n{}\n{:#?}", e, blk);
+                self.publish(Event::InvalidBlock {
+                    hash: blk.hash().encode_hex(),
+                    reason: e.to_string(),
+                });
                 return;
             }
         }
@@ -606,30 +1740,79 @@ impl<'a> Protocol<'a> {
         self.state_mut().hard_accounts.process_block(&blk).unwrap();
 
         self.state_mut().blockchain.add_block(blk.clone()); // add to blockchain
+
+        // index the block as stored in the blockchain (which assigns its index)
+        let state = self.state.as_mut().expect("Protocol not running");
+        let last_blk = state.blockchain.last_block();
+        let mut events = vec![Event::from_block(last_blk, state.peers)];
+        events.extend(
+            state
+                .inbox
+                .index_block(last_blk, state.peers, &self.priv_key)
+                .iter()
+                .map(Event::from_inbox_entry),
+        );
+
         self.state_mut().next_validator_id.set(None); // reset memoized validator
+        events.push(Event::Validator {
+            after_block: self.state().blockchain.last_block().index(),
+            validator: self.proof_of_stake(),
+        });
+        for event in events {
+            self.publish(event);
+        }
+        self.try_take_snapshot();
+        self.try_prune_blocks();
 
         if is_local {
             self.broadcast_block(blk.clone());
         }
 
         let mut new_soft_accounts = self.state().hard_accounts.clone();
-        let peers = self.state().peers;
+        let state = self.state_mut();
+        let peers = state.peers;
+        let statuses = &mut state.tsx_statuses;
+        let mut dropped = vec![];
+
+        let index = state.blockchain.last_block().index();
+        for tsx in blk.tsxs() {
+            statuses.insert(*tsx.hash(), TransactionStatus::Included(index));
+        }
 
         // discard all transactions pending in the block and reprocess the rest
-        self.state_mut().pending_transactions.retain(|p_tsx| {
+        state.pending_transactions.retain(|p_tsx| {
             // TODO: this could probably be sped up by using a HashSet, but it's not that important
             blk.tsxs().iter().all(|b_tsx| p_tsx.hash() != b_tsx.hash())
-                && if new_soft_accounts.process_transaction(p_tsx).is_err() {
-                    History::log_invalid_transaction(p_tsx, peers);
-                    false // discard now-invalid transactions
-                } else {
-                    true // keep the rest
+                && match new_soft_accounts.process_transaction(p_tsx) {
+                    Err(e) => {
+                        History::log_invalid_transaction(p_tsx, peers);
+                        let AccountError::InsufficientFunds(short) = e.error;
+                        let reason = format!(
+                            "account {} was {} BCC short after block {}",
+                            e.account_id,
+                            short as f64 / chain_params().cents_per_coin as f64,
+                            index
+                        );
+                        dropped.push(Event::InvalidTransaction {
+                            hash: p_tsx.hash().encode_hex(),
+                            reason: reason.clone(),
+                        });
+                        statuses.insert(*p_tsx.hash(), TransactionStatus::Dropped(index, reason));
+                        false // discard now-invalid transactions
+                    }
+                    Ok(()) => true, // keep the rest
                 }
         });
 
         // update soft accounts
         self.state_mut().soft_accounts = new_soft_accounts;
 
+        for event in dropped {
+            self.publish(event);
+        }
+
+        self.check_waiters();
+
         // * blk time end
         unsafe {
             BLK_TIMES.push(BLK_START.take().unwrap().elapsed());
@@ -639,31 +1822,201 @@ impl<'a> Protocol<'a> {
         self.try_mint_block();
     }
 
+    // queue the event for every client subscribed to its type
+    // clients that cannot keep up (or have disconnected) are dropped
+    fn publish(&mut self, event: Event) {
+        let subscribers = &mut self.state_mut().subscribers;
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let event_type = event.event_type();
+        let mut line =
+            serde_json::to_vec(&Response::Event(event)).expect("Failed to serialize event");
+        line.push(b'\n');
+        let line: Arc<[u8]> = line.into();
+
+        subscribers.retain(|sub| {
+            if !sub.types.contains(&event_type) {
+                return true;
+            }
+
+            match sub.tx.try_send(line.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::debug!("Dropped subscriber that cannot keep up");
+                    false
+                }
+                // the writer has stopped, because the client has disconnected
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    // reply to the clients whose transactions have been confirmed, dropped or timed out
+    fn check_waiters(&mut self) {
+        let now = Instant::now();
+        let waiters = std::mem::take(&mut self.state_mut().waiters);
+
+        for mut waiter in waiters {
+            let state = self.state();
+            let last = state.blockchain.last_block().index();
+
+            let confirmation = match state.tsx_statuses.get(&waiter.hash) {
+                Some(TransactionStatus::Included(index))
+                    if last - index + 1 >= waiter.confirmations =>
+                {
+                    Confirmation::Included {
+                        block: *index,
+                        confirmations: last - index + 1,
+                    }
+                }
+                Some(TransactionStatus::Dropped(_, reason)) => Confirmation::Dropped {
+                    reason: reason.clone(),
+                },
+                Some(TransactionStatus::Included(index)) if now >= waiter.deadline => {
+                    Confirmation::TimedOut {
+                        block: Some(*index),
+                        confirmations: last - index + 1,
+                        required: waiter.confirmations,
+                    }
+                }
+                None if now >= waiter.deadline => Confirmation::TimedOut {
+                    block: None,
+                    confirmations: 0,
+                    required: waiter.confirmations,
+                },
+                _ => {
+                    self.state_mut().waiters.push(waiter);
+                    continue;
+                }
+            };
+
+            if let Err(e) = Response::Confirmation(confirmation).write_to(&mut waiter.stream) {
+                log::warn!("Failed to send confirmation to client: {}", e);
+            } else {
+                log::trace!("Successfully sent confirmation to client");
+            }
+        }
+    }
+
     fn try_mint_block(&mut self) {
         // if the block is not full or if the node is not the validator return
-        if self.state().pending_transactions.len() < BLOCK_CAPACITY
+        let capacity = chain_params().block_capacity;
+        if self.state().pending_transactions.len() < capacity
             || self.state().id != self.proof_of_stake()
         {
             return;
         }
 
-        let transactions: [Transaction; BLOCK_CAPACITY] = self
+        let transactions = self
             .state_mut()
             .pending_transactions
-            .drain(..BLOCK_CAPACITY)
-            .collect::<Vec<_>>()
-            .try_into()
+            .drain(..capacity)
+            .collect::<Vec<_>>();
+
+        // execute the block locally to find the state root it commits to
+        let val = self.priv_key.to_publ_key();
+        let mut post_accounts = self.state().hard_accounts.clone();
+        post_accounts
+            .process_transactions(&transactions, Some(&val))
             .unwrap();
 
         let block = Block::new(
             transactions,
-            self.priv_key.to_publ_key(),
+            val,
             *self.state().blockchain.last_block().hash(),
+            post_accounts.calculate_state_root(),
         );
 
         self.handle_block(block, true);
     }
 
+    fn try_take_snapshot(&mut self) {
+        let state = self.state_mut();
+        let index = state.blockchain.last_block().index();
+
+        if !index.is_multiple_of(state.snapshot_interval.get()) {
+            return;
+        }
+
+        state.snapshot = Snapshot::take(
+            *state.snapshot.genesis_hash(),
+            &state.blockchain,
+            &state.hard_accounts,
+        );
+
+        if let Some(dir) = &state.snapshot_dir {
+            let path = dir.join(Snapshot::file_name(index));
+            if let Err(e) = state.snapshot.save(&path) {
+                log::warn!("Failed to save snapshot to {}: {}", path.display(), e);
+            } else {
+                log::debug!("Saved snapshot to {}", path.display());
+            }
+        }
+    }
+
+    fn try_prune_blocks(&mut self) {
+        let state = self.state_mut();
+
+        let Some(retention) = state.block_retention else {
+            return;
+        };
+
+        // keep the last `retention` blocks, as well as every block after the snapshot
+        // so that peers can always be served the snapshot and the blocks after it
+        let last = state.blockchain.last_block().index();
+        let prune_before = (last + 1)
+            .saturating_sub(retention.get())
+            .min(state.snapshot.block().index() + 1);
+
+        state.blockchain.prune(prune_before);
+
+        // the statuses clients are still waiting on are kept until they are answered
+        let oldest_retained = state.blockchain.oldest_retained();
+        let watched: HashSet<_> = state.waiters.iter().map(|waiter| waiter.hash).collect();
+        state.tsx_statuses.retain(|hash, status| match status {
+            TransactionStatus::Included(index) | TransactionStatus::Dropped(index, _) => {
+                *index >= oldest_retained || watched.contains(hash)
+            }
+        });
+    }
+
+    fn handle_sync_request(&self, index: u32, mut stream: Connection) {
+        let state = self.state();
+
+        let res = match state.blockchain.blocks_after(index) {
+            Ok(blocks) => SyncResponse::Blocks(blocks.cloned().collect()),
+            Err(e) => {
+                log::info!(
+                    "Sync request for blocks after {} cannot be served: {}",
+                    index,
+                    e
+                );
+
+                let snapshot = state.snapshot.clone();
+                SyncResponse::OutsideRetention {
+                    oldest_retained: state.blockchain.oldest_retained(),
+                    blocks: state
+                        .blockchain
+                        .blocks_after(snapshot.block().index())
+                        .expect("Blocks after the snapshot should never be pruned")
+                        .cloned()
+                        .collect(),
+                    snapshot: Box::new(snapshot),
+                }
+            }
+        };
+
+        let res_bytes = serde_json::to_vec(&res).expect("Failed to serialize sync response");
+
+        if let Err(e) = stream.write_all(&res_bytes) {
+            log::warn!("Failed to respond to sync request: {}", e);
+        } else {
+            log::trace!("Successfully responded to sync request");
+        }
+    }
+
     fn broadcast_transaction(&self, tsx: Transaction) {
         self.state().tx.send(Broadcast::Transaction(tsx)).unwrap();
     }
@@ -727,3 +2080,343 @@ impl<'a> Protocol<'a> {
         winner_id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bootstrap::StaticPeer, cli::Payment, genesis::Allocation, response::Confirmation};
+    use rsa::RsaPrivateKey;
+    use std::{io::Read as _, sync::Mutex};
+
+    // transactions and blocks are timed with the static muts,
+    // so the tests that handle them run one at a time
+    static TIMING: Mutex<()> = Mutex::new(());
+
+    fn start_timing() {
+        unsafe {
+            TSX_START = Some(Instant::now());
+            BLK_START = Some(Instant::now());
+        }
+    }
+
+    fn new_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap())
+    }
+
+    fn amt(coins: u32) -> NonZeroU32 {
+        NonZeroU32::new(coins).unwrap()
+    }
+
+    // peer 0 of a static network of 3 peers, set up as in `run` but without any threads
+    // (the receiver of the broadcasts is returned so that broadcasting does not fail)
+    // peer 0 is the only one with a stake, so it validates every block
+    fn protocol() -> (Protocol<'static>, Receiver<Broadcast>) {
+        let keys: Vec<_> = (0..3).map(|_| new_priv_key()).collect();
+        let membership = StaticMembership {
+            peers: keys
+                .iter()
+                .enumerate()
+                .map(|(id, key)| StaticPeer {
+                    id: id as u32,
+                    publ_key: key.to_publ_key(),
+                    addr: "127.0.0.1:0".to_string(),
+                })
+                .collect(),
+        };
+
+        let genesis = Genesis {
+            allocations: vec![Allocation {
+                publ_key: keys[0].to_publ_key(),
+                coins: 1000,
+                stake: 10,
+            }],
+            ..Genesis::default()
+        };
+
+        let (_, peers, snapshot, _) =
+            static_network(&membership, &genesis, keys[0].to_publ_key()).unwrap();
+        let peers = peers.leak();
+        let hard_accounts = AccountsCatalog::from_snapshot(peers, &snapshot).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        let mut protocol = Protocol::new(keys[0].clone());
+        protocol.state = Some(ProtocolState {
+            id: 0,
+            peers,
+            soft_accounts: hard_accounts.clone(),
+            hard_accounts,
+            pending_transactions: vec![],
+            blockchain: Blockchain::from_snapshot(&snapshot),
+            tsx_statuses: HashMap::new(),
+            waiters: vec![],
+            subscribers: vec![],
+            peer_links: HashMap::new(),
+            snapshot,
+            snapshot_interval: NonZeroU32::MIN,
+            snapshot_dir: None,
+            block_retention: None,
+            inbox: Inbox::new(),
+            address_book: AddressBook::new(),
+            next_validator_id: Cell::new(None),
+            tx,
+        });
+
+        (protocol, rx)
+    }
+
+    // send a command as the client would, and return the response
+    fn send_command(protocol: &mut Protocol, cmd: Command) -> Response {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        protocol.handle_command(cmd, stream);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    // send a batch command, and return the report
+    fn send_batch(protocol: &mut Protocol, items: Vec<BatchItem>, atomic: bool) -> BatchReport {
+        let cmd = Command::Batch {
+            file: None,
+            atomic,
+            items,
+        };
+
+        match send_command(protocol, cmd) {
+            Response::Batch(report) => report,
+            res => panic!("Unexpected response: {}", res),
+        }
+    }
+
+    #[test]
+    fn test_new_batch() {
+        let (protocol, _rx) = protocol();
+
+        let tsxs = protocol.new_batch(vec![
+            BatchItem::Transfer {
+                rcp: Recipient::Id(1),
+                amt: amt(100),
+            },
+            BatchItem::Stake { amt: amt(10) },
+            BatchItem::Transfer {
+                rcp: Recipient::Id(7),
+                amt: amt(5),
+            },
+            // affordable on its own, but not after the first transfer
+            BatchItem::Transfer {
+                rcp: Recipient::Id(2),
+                amt: amt(900),
+            },
+            BatchItem::MultiTransfer {
+                payments: vec![
+                    Payment {
+                        rcp: Recipient::Id(1),
+                        amt: amt(5),
+                    },
+                    Payment {
+                        rcp: Recipient::Id(2),
+                        amt: amt(5),
+                    },
+                ],
+            },
+        ]);
+
+        assert!(matches!(tsxs[2], Err(SubmitError::RecipientNotFound)));
+        assert!(matches!(tsxs[3], Err(SubmitError::NotEnoughCoins)));
+
+        // every transaction is built on top of the previous ones,
+        // and the failed ones do not use up a nonce
+        let nonces: Vec<_> = tsxs.iter().flatten().map(Transaction::nonce).collect();
+        assert_eq!(nonces.len(), 3);
+        assert!(nonces.windows(2).all(|n| n[1] == n[0] + 1));
+
+        // nothing is submitted yet
+        assert!(protocol.state().pending_transactions.is_empty());
+    }
+
+    #[test]
+    fn test_batch() {
+        let (mut protocol, _rx) = protocol();
+        let held_cents = protocol.local_soft_account().held_cents();
+
+        // the second transfer is to an unknown peer
+        let items = || {
+            [1, 7, 2]
+                .map(|id| BatchItem::Transfer {
+                    rcp: Recipient::Id(id),
+                    amt: amt(5),
+                })
+                .to_vec()
+        };
+
+        let _timing = TIMING.lock().unwrap();
+        start_timing();
+
+        // an atomic batch with an invalid transaction submits none of them
+        let report = send_batch(&mut protocol, items(), true);
+        assert!(matches!(
+            report.results.as_slice(),
+            [
+                BatchResult {
+                    outcome: BatchOutcome::Skipped,
+                    ..
+                },
+                BatchResult {
+                    outcome: BatchOutcome::Failed { .. },
+                    ..
+                },
+                BatchResult {
+                    outcome: BatchOutcome::Skipped,
+                    ..
+                },
+            ]
+        ));
+        assert!(protocol.state().pending_transactions.is_empty());
+        assert_eq!(protocol.local_soft_account().held_cents(), held_cents);
+
+        // otherwise the valid ones are submitted, with consecutive nonces
+        let report = send_batch(&mut protocol, items(), false);
+        let [BatchResult {
+            outcome:
+                BatchOutcome::Submitted {
+                    hash: h0,
+                    nonce: n0,
+                },
+            ..
+        }, BatchResult {
+            outcome: BatchOutcome::Failed { .. },
+            ..
+        }, BatchResult {
+            outcome:
+                BatchOutcome::Submitted {
+                    hash: h2,
+                    nonce: n2,
+                },
+            ..
+        }] = report.results.as_slice()
+        else {
+            panic!("Unexpected report: {}", report);
+        };
+        assert_eq!(*n2, n0 + 1);
+
+        let pending: Vec<String> = protocol
+            .state()
+            .pending_transactions
+            .iter()
+            .map(|tsx| tsx.hash().encode_hex())
+            .collect();
+        assert_eq!(pending, [h0.clone(), h2.clone()]);
+        assert!(protocol.local_soft_account().held_cents() < held_cents);
+    }
+
+    #[test]
+    fn test_blocks_range() {
+        let (mut protocol, _rx) = protocol();
+
+        let range = |from, to| Command::Blocks {
+            range: BlockRange { from, to },
+        };
+
+        match send_command(&mut protocol, range(0, None)) {
+            Response::Blocks(blks) => assert_eq!(blks.len(), 1),
+            res => panic!("Unexpected response: {}", res),
+        }
+
+        // the end of the range is capped at the last block
+        match send_command(&mut protocol, range(0, Some(10))) {
+            Response::Blocks(blks) => assert_eq!(blks.len(), 1),
+            res => panic!("Unexpected response: {}", res),
+        }
+
+        match send_command(&mut protocol, range(1, None)) {
+            Response::Error { code, .. } => assert_eq!(code, ErrorCode::BlockNotFound),
+            res => panic!("Unexpected response: {}", res),
+        }
+
+        // a range that ends before it starts is refused (instead of underflowing)
+        {
+            let _timing = TIMING.lock().unwrap();
+            start_timing();
+
+            for _ in 0..chain_params().block_capacity {
+                let tsx = protocol
+                    .new_transfer(protocol.local_soft_account(), 1, amt(1))
+                    .unwrap();
+                protocol.handle_transaction(tsx, None, true);
+            }
+        }
+        assert_eq!(protocol.state().blockchain.last_block().index(), 1);
+
+        match send_command(&mut protocol, range(1, Some(0))) {
+            Response::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidParams),
+            res => panic!("Unexpected response: {}", res),
+        }
+    }
+
+    #[test]
+    fn test_prune_watched_status() {
+        let (mut protocol, _rx) = protocol();
+        protocol.state_mut().block_retention = Some(NonZeroU32::MIN);
+
+        let _timing = TIMING.lock().unwrap();
+        start_timing();
+
+        // fill a block with 1 BCC transfers (the first one is waited for, if given a client)
+        fn fill_block(
+            protocol: &mut Protocol,
+            mut client: Option<(TcpStream, WaitOptions)>,
+        ) -> [u8; 32] {
+            let mut hashes = vec![];
+            for i in 0..chain_params().block_capacity {
+                let tsx = protocol
+                    .new_transfer(protocol.local_soft_account(), 1 + i as u32 % 2, amt(1))
+                    .unwrap();
+                hashes.push(*tsx.hash());
+                protocol.handle_transaction(tsx, client.take(), true);
+            }
+            hashes[0]
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let wait = WaitOptions {
+            wait: false,
+            confirmations: Some(NonZeroU32::new(3).unwrap()),
+            timeout: 60,
+        };
+
+        // block 1 is pruned once block 2 is added, before it has 3 confirmations
+        let hash = fill_block(&mut protocol, Some((stream, wait)));
+        fill_block(&mut protocol, None);
+        fill_block(&mut protocol, None);
+        assert_eq!(protocol.state().blockchain.oldest_retained(), 3);
+
+        let responses: Vec<_> = serde_json::Deserializer::from_reader(client)
+            .into_iter::<Response>()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(matches!(
+            responses.as_slice(),
+            [
+                Response::Submitted { .. },
+                Response::Confirmation(Confirmation::Included {
+                    block: 1,
+                    confirmations: 3,
+                }),
+            ]
+        ));
+        assert!(protocol.state().waiters.is_empty());
+
+        // once answered, the status is forgotten along with its block
+        assert!(protocol.state().tsx_statuses.contains_key(&hash));
+        fill_block(&mut protocol, None);
+        assert!(!protocol.state().tsx_statuses.contains_key(&hash));
+    }
+}
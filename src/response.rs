@@ -0,0 +1,580 @@
+use crate::{
+    account::StatementEntry,
+    address::Address,
+    crypto::KeyFormat,
+    events::Event,
+    history::History,
+    inbox::{fmt_timestamp, InboxEntry},
+    rpc::{Balance, BlockInfo, ErrorCode, TransactionInfo, TransactionState, TransactionSummary},
+};
+use hex::ToHex as _;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    net::SocketAddr,
+};
+
+/*
+    Every command is answered with Responses, each serialized as a single line of JSON.
+
+    Most commands are answered with a single Response. A transaction submitted with `--wait`
+    is answered with a Submitted response followed by a Confirmation, and `subscribe`
+    is answered with an Event per line for as long as the connection stays open.
+
+    Failures are answered with an Error response, which carries the same error codes
+    as the JSON-RPC API. The Display implementations render responses for humans,
+    which is what the client prints unless it is run with `--json`.
+*/
+
+// how many hex digits of a hash to print
+const HASH_HEX_LEN: usize = 16;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Submitted {
+        hash: String,
+    },
+    Confirmation(Confirmation),
+    Batch(BatchReport),
+    Balance(Balance),
+    Block(Box<BlockInfo>),
+    Blocks(Vec<BlockInfo>),
+    ChainInfo(ChainInfo),
+    TxHistory(Box<TxHistoryPage>),
+    Status(TransactionInfo),
+    Inbox {
+        messages: Vec<InboxMessage>,
+        unread: usize,
+    },
+    Event(Event),
+    History(History),
+    Whoami(PeerIdentity),
+    Key(KeyExport),
+    Peers(Vec<PeerSummary>),
+    Network(NetworkInfo),
+    Mempool(Vec<TransactionSummary>),
+    AddressBook(Vec<Contact>),
+    ContactAdded(Contact),
+    ContactRemoved(Contact),
+    Time {
+        avg_transaction_ms: f64,
+        avg_block_ms: f64,
+    },
+    Stats(String),
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl Response {
+    pub fn error(code: ErrorCode, message: impl ToString) -> Self {
+        Self::Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    // whether the command failed (for a batch, whether any of its transactions was not sent)
+    pub fn is_error(&self) -> bool {
+        match self {
+            Self::Error { .. } => true,
+            Self::Batch(report) => report
+                .results
+                .iter()
+                .any(|res| !matches!(res.outcome, BatchOutcome::Submitted { .. })),
+            _ => false,
+        }
+    }
+
+    // write the response as a line of JSON
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        writer.write_all(&line)
+    }
+}
+
+// the outcome of waiting for a transaction to be confirmed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Confirmation {
+    Included {
+        block: u32,
+        confirmations: u32,
+    },
+    Dropped {
+        reason: String,
+    },
+    TimedOut {
+        block: Option<u32>, // None if the transaction is still pending
+        confirmations: u32,
+        required: u32,
+    },
+}
+
+// what happened to each transaction of a batch, in the order of the batch
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BatchReport {
+    pub atomic: bool,
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BatchResult {
+    // the transaction as it was given (e.g. `t bob 5`)
+    pub item: String,
+    #[serde(flatten)]
+    pub outcome: BatchOutcome,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Submitted { hash: String, nonce: u64 },
+    Failed { code: ErrorCode, reason: String },
+    // valid, but not sent because another transaction of an atomic batch failed
+    Skipped,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChainInfo {
+    // the index of the last block
+    pub height: u32,
+    // the hash of the last block
+    pub tip: String,
+    // the blocks before this one have been pruned
+    pub oldest_retained: u32,
+    pub pending_transactions: usize,
+    // according to the blocks (i.e. without the pending transactions)
+    pub total_supply: f64,
+    pub total_staked: f64,
+    // the validator of the next block
+    pub next_validator: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PeerIdentity {
+    pub id: u32,
+    // the short hex encoding of the public key
+    pub key: String,
+    pub account: Address,
+    pub address: SocketAddr,
+}
+
+// the full public key of a peer
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct KeyExport {
+    pub id: u32,
+    // the full fingerprint
+    pub fingerprint: String,
+    pub format: KeyFormat,
+    pub key: String,
+}
+
+// whether the local node can currently hear from a peer
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    Local,
+    Connected,
+    Disconnected,
+    // the peer has never connected to the local node
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PeerSummary {
+    pub id: u32,
+    pub key: String,
+    pub account: Address,
+    pub address: SocketAddr,
+    // including the pending transactions
+    pub balance: f64,
+    pub stake: f64,
+    pub liveness: Liveness,
+    // how long the peer has been (dis)connected for
+    pub since_secs: Option<u64>,
+}
+
+// a name in the address book
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Contact {
+    pub name: String,
+    // the fingerprint of the key
+    pub key: String,
+    pub account: Address,
+    // None if no peer of the network has the key
+    pub id: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NetworkInfo {
+    pub id: u32,
+    pub connected_peers: usize,
+    // not including the local peer
+    pub other_peers: usize,
+    // the index of the last block
+    pub height: u32,
+    // the pending transactions
+    pub mempool: usize,
+}
+
+// a page of the statement of an account
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TxHistoryPage {
+    pub account: u32,
+    pub page: usize,
+    pub pages: usize,
+    pub total: usize,
+    // the transactions of blocks before this one have been pruned
+    pub first_block: u32,
+    pub entries: Vec<StatementEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InboxMessage {
+    pub hash: String,
+    pub sender: u32,
+    pub block: u32,
+    pub timestamp: u128,
+    pub message: Option<String>, // None if it could not be decrypted
+    pub encrypted: bool,
+    pub read: bool,
+}
+
+impl InboxMessage {
+    pub fn new(entry: &InboxEntry, read: bool) -> Self {
+        Self {
+            hash: entry.tsx_hash().encode_hex(),
+            sender: entry.sndr_id(),
+            block: entry.block_index(),
+            timestamp: entry.timestamp(),
+            message: entry.message().map(str::to_string),
+            encrypted: entry.is_encrypted(),
+            read,
+        }
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Submitted { hash } => write!(f, "Transaction successful: {}", hash),
+            Self::Confirmation(confirmation) => confirmation.fmt(f),
+            Self::Batch(report) => report.fmt(f),
+            Self::Balance(balance) => {
+                write!(
+                    f,
+                    "Balance: {} held, {} staked",
+                    balance.held, balance.staked
+                )
+            }
+            Self::Block(blk) => blk.fmt(f),
+            Self::Blocks(blks) => {
+                for (i, blk) in blks.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    write!(f, "{}", blk)?;
+                }
+                Ok(())
+            }
+            Self::ChainInfo(info) => info.fmt(f),
+            Self::TxHistory(page) => page.fmt(f),
+            Self::Status(info) => match info.status {
+                TransactionState::Pending => write!(f, "Pending"),
+                TransactionState::Included => write!(
+                    f,
+                    "Included in block {} ({} confirmations)",
+                    info.block.unwrap_or_default(),
+                    info.confirmations.unwrap_or_default()
+                ),
+                TransactionState::Dropped => {
+                    write!(f, "Dropped: {}", info.reason.as_deref().unwrap_or_default())
+                }
+            },
+            Self::Inbox { messages, unread } => {
+                if messages.is_empty() {
+                    writeln!(f, "No messages")?;
+                }
+                for msg in messages {
+                    writeln!(f, "{}", msg)?;
+                }
+                write!(f, "{} unread", unread)
+            }
+            // events are meant for programs, so they are printed as JSON either way
+            Self::Event(event) => match serde_json::to_string(event) {
+                Ok(json) => write!(f, "{}", json),
+                Err(_) => Err(fmt::Error),
+            },
+            Self::History(history) => history.fmt(f),
+            Self::Whoami(me) => me.fmt(f),
+            // only the key, so that it can be redirected to a file
+            Self::Key(export) => write!(f, "{}", export.key.trim_end()),
+            Self::Peers(peers) => {
+                write!(
+                    f,
+                    "{:<3} | {:<36} | {:<21} | {:>14} | {:>12} | status",
+                    "id", "account", "address", "balance", "stake"
+                )?;
+                for peer in peers {
+                    write!(f, "\n{}", peer)?;
+                }
+                Ok(())
+            }
+            Self::Network(info) => info.fmt(f),
+            Self::AddressBook(contacts) => {
+                if contacts.is_empty() {
+                    return write!(f, "The address book is empty");
+                }
+                for (i, contact) in contacts.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", contact)?;
+                }
+                Ok(())
+            }
+            Self::ContactAdded(contact) => write!(f, "Added {}", contact),
+            Self::ContactRemoved(contact) => write!(f, "Removed {}", contact),
+            Self::Mempool(tsxs) => {
+                if tsxs.is_empty() {
+                    return write!(f, "No pending transactions");
+                }
+                for (i, tsx) in tsxs.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", tsx)?;
+                }
+                Ok(())
+            }
+            Self::Time {
+                avg_transaction_ms,
+                avg_block_ms,
+            } => write!(
+                f,
+                "Average transaction time: {} ms\nAverage block time: {} ms",
+                avg_transaction_ms, avg_block_ms
+            ),
+            Self::Stats(stats) => write!(f, "{}", stats.trim_end()),
+            Self::Error { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Display for Confirmation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Included {
+                block,
+                confirmations,
+            } => write!(
+                f,
+                "Included in block {} ({} confirmations)",
+                block, confirmations
+            ),
+            Self::Dropped { reason } => write!(f, "Dropped: {}", reason),
+            Self::TimedOut { block: None, .. } => write!(f, "Timed out: still pending"),
+            Self::TimedOut {
+                block: Some(block),
+                confirmations,
+                required,
+            } => write!(
+                f,
+                "Timed out: included in block {} ({} of {} confirmations)",
+                block, confirmations, required
+            ),
+        }
+    }
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut submitted = 0;
+
+        for (i, res) in self.results.iter().enumerate() {
+            write!(f, "{:>3}. {} -> ", i + 1, res.item)?;
+
+            match &res.outcome {
+                BatchOutcome::Submitted { hash, nonce } => {
+                    submitted += 1;
+                    writeln!(f, "submitted {} (nonce {})", hash, nonce)?
+                }
+                BatchOutcome::Failed { reason, .. } => writeln!(f, "failed: {}", reason)?,
+                BatchOutcome::Skipped => writeln!(f, "not sent")?,
+            }
+        }
+
+        write!(
+            f,
+            "{} of {} transactions submitted",
+            submitted,
+            self.results.len()
+        )?;
+        if self.atomic && submitted == 0 {
+            write!(f, " (the batch is atomic, so none was sent)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ChainInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Height: {}", self.height)?;
+        writeln!(f, "Tip: {}", self.tip)?;
+        writeln!(f, "Oldest retained block: {}", self.oldest_retained)?;
+        writeln!(f, "Pending transactions: {}", self.pending_transactions)?;
+        writeln!(f, "Total supply: {} BCC", self.total_supply)?;
+        writeln!(f, "Total staked: {} BCC", self.total_staked)?;
+        write!(f, "Next validator: {}", self.next_validator)
+    }
+}
+
+impl Display for PeerIdentity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "ID: {}", self.id)?;
+        writeln!(f, "Account: {}", self.account)?;
+        writeln!(f, "Key: {}", self.key)?;
+        write!(f, "Address: {}", self.address)
+    }
+}
+
+impl Display for Contact {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:<16} | {} | ", self.name, self.account)?;
+
+        match self.id {
+            Some(id) => write!(f, "peer {}", id),
+            None => write!(f, "not in the network"),
+        }
+    }
+}
+
+impl Display for PeerSummary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<3} | {:<36} | {:<21} | {:>10} BCC | {:>8} BCC | ",
+            self.id,
+            self.account.to_string(),
+            self.address.to_string(),
+            self.balance,
+            self.stake
+        )?;
+
+        match (self.liveness, self.since_secs) {
+            (Liveness::Local, _) => write!(f, "local"),
+            (Liveness::Connected, Some(secs)) => write!(f, "connected for {}s", secs),
+            (Liveness::Disconnected, Some(secs)) => write!(f, "disconnected for {}s", secs),
+            (Liveness::Connected, None) => write!(f, "connected"),
+            (Liveness::Disconnected, None) => write!(f, "disconnected"),
+            (Liveness::Unknown, _) => write!(f, "never connected"),
+        }
+    }
+}
+
+impl Display for NetworkInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "ID: {}", self.id)?;
+        writeln!(
+            f,
+            "Connected peers: {} of {}",
+            self.connected_peers, self.other_peers
+        )?;
+        writeln!(f, "Chain height: {}", self.height)?;
+        write!(f, "Pending transactions: {}", self.mempool)
+    }
+}
+
+impl Display for TxHistoryPage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Transactions of account {} (page {} of {}, {} in total)",
+            self.account, self.page, self.pages, self.total
+        )?;
+        for entry in &self.entries {
+            write!(f, "\n{}", entry)?;
+        }
+        if self.first_block > 0 {
+            write!(
+                f,
+                "\n(blocks before block {} have been pruned and are not included)",
+                self.first_block
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for InboxMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} | block {} | from {} | ",
+            if self.read { " " } else { "*" },
+            fmt_timestamp(self.timestamp),
+            self.block,
+            self.sender
+        )?;
+
+        match (&self.message, self.encrypted) {
+            (Some(msg), false) => write!(f, "{}", msg),
+            (Some(msg), true) => write!(f, "{} (encrypted)", msg),
+            (None, _) => write!(f, "<encrypted message that could not be decrypted>"),
+        }
+    }
+}
+
+impl Display for BlockInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Block {} ({})", self.index, self.hash)?;
+        writeln!(f, "Previous block: {}", self.prev_hash)?;
+        match self.validator {
+            Some(id) => writeln!(f, "Validator: {}", id)?,
+            None => writeln!(f, "Validator: - (genesis block)")?,
+        }
+        writeln!(f, "Timestamp: {}", fmt_timestamp(self.timestamp))?;
+        write!(f, "Transactions: {}", self.transactions.len())?;
+        for tsx in &self.transactions {
+            write!(f, "\n  {}", tsx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TransactionSummary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let sender = self
+            .sender
+            .map_or("genesis".to_string(), |id| id.to_string());
+        let recipient = match (self.recipient, self.outputs.is_empty()) {
+            (Some(id), _) => id.to_string(),
+            (None, true) => "-".to_string(),
+            (None, false) => self
+                .outputs
+                .iter()
+                .map(|out| out.recipient.map_or("?".to_string(), |id| id.to_string()))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+
+        write!(
+            f,
+            "{} | {:<17} | {} -> {} | {} BCC | fee {} BCC",
+            &self.hash[..HASH_HEX_LEN.min(self.hash.len())],
+            self.kind,
+            sender,
+            recipient,
+            self.amount,
+            self.fees
+        )
+    }
+}
@@ -0,0 +1,553 @@
+use crate::{
+    account::Account,
+    address::Address,
+    blockchain::{
+        block::Block,
+        transaction::{Transaction, TransactionPayload},
+    },
+    genesis::chain_params,
+    peer::{Peer, PeersCatalog},
+};
+use hex::ToHex as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead as _, BufReader, Read as _, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream},
+    num::NonZeroU32,
+    thread,
+    time::Duration,
+};
+
+/*
+    The daemon can serve a JSON-RPC 2.0 API over HTTP, for integrations that
+    would rather not speak the raw TCP protocol of the client.
+
+    Every request is a POST with a JSON-RPC request (or a batch of them) as its body:
+
+        curl -s localhost:8545 -d '{"jsonrpc":"2.0","id":1,"method":"get_balance"}'
+
+    Methods (params are given by name, amounts are in BCC and hashes are hex encoded):
+    - get_balance { account?: u32 }                  -> Balance (of the local account by default)
+    - send_transfer { recipient: u32, amount: u32 }  -> Submitted
+    - send_message { recipient: u32, message: string, encrypted?: bool } -> Submitted
+    - send_multi_transfer { outputs: [{ recipient: u32, amount: u32 }] } -> Submitted
+    - stake { amount: u32 }                          -> Submitted
+    - get_block { index?: u32 }                      -> BlockInfo (the last block by default)
+    - get_transaction { hash: string }               -> TransactionInfo
+    - get_peers {}                                   -> [PeerInfo]
+
+    Errors are JSON-RPC error objects, with the standard codes for malformed requests
+    and the codes of `ErrorCode` for everything else. The `data` of an error carries
+    extra details where available (e.g. the oldest retained block for pruned blocks).
+*/
+
+// how long to wait for a client to send its request
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// requests larger than this are rejected
+const MAX_HEADERS_LEN: usize = 8 * 1024;
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+const JSONRPC_VERSION: &str = "2.0";
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // standard JSON-RPC errors
+    ParseError = -32700,
+    InvalidRequest = -32600,
+    MethodNotFound = -32601,
+    InvalidParams = -32602,
+    InternalError = -32603,
+
+    // application errors
+    AccountNotFound = -32001,
+    InvalidRecipient = -32002,
+    InsufficientFunds = -32003,
+    InvalidMessage = -32004,
+    BlockNotFound = -32005,
+    BlockPruned = -32006,
+    TransactionNotFound = -32007,
+    ContactNotFound = -32008,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Self {
+            code: code as i32,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+pub type RpcResult = Result<Value, RpcError>;
+
+// a recipient of a multi-transfer
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RpcOutput {
+    pub recipient: u32,
+    pub amount: NonZeroU32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum RpcCall {
+    GetBalance {
+        account: Option<u32>,
+    },
+    SendTransfer {
+        recipient: u32,
+        amount: NonZeroU32,
+    },
+    SendMessage {
+        recipient: u32,
+        message: String,
+        #[serde(default)]
+        encrypted: bool,
+    },
+    SendMultiTransfer {
+        outputs: Vec<RpcOutput>,
+    },
+    Stake {
+        amount: NonZeroU32,
+    },
+    GetBlock {
+        index: Option<u32>,
+    },
+    GetTransaction {
+        hash: String,
+    },
+    GetPeers {},
+}
+
+impl RpcCall {
+    const METHODS: [&'static str; 8] = [
+        "get_balance",
+        "send_transfer",
+        "send_message",
+        "send_multi_transfer",
+        "stake",
+        "get_block",
+        "get_transaction",
+        "get_peers",
+    ];
+}
+
+// results
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Balance {
+    pub account: u32,
+    pub held: f64,
+    pub staked: f64,
+}
+
+impl Balance {
+    pub fn from_account(id: u32, acc: &Account) -> Self {
+        let cents_per_coin = chain_params().cents_per_coin as f64;
+
+        Self {
+            account: id,
+            held: acc.held_cents() as f64 / cents_per_coin,
+            staked: acc.staked_cents() as f64 / cents_per_coin,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Submitted {
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TransactionSummary {
+    pub hash: String,
+    pub kind: String,
+    pub sender: Option<u32>,    // None for genesis transactions
+    pub recipient: Option<u32>, // None for stakes and multi-transfers
+    pub amount: f64,
+    pub fees: f64,
+    // the recipients of a multi-transfer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<OutputSummary>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutputSummary {
+    pub recipient: Option<u32>,
+    pub amount: f64,
+}
+
+impl TransactionSummary {
+    pub fn from_transaction(tsx: &Transaction, peers: &PeersCatalog) -> Self {
+        let kind = match tsx.payload() {
+            TransactionPayload::Transfer(_) => "transfer",
+            TransactionPayload::Message(_) => "message",
+            TransactionPayload::EncryptedMessage(_) => "encrypted_message",
+            TransactionPayload::Stake(_) => "stake",
+            TransactionPayload::MultiTransfer(_) => "multi_transfer",
+        };
+        let id = |addr| peers.get_by_publ_key(addr).map(|p| p.id());
+        let cents_per_coin = chain_params().cents_per_coin as f64;
+
+        Self {
+            hash: tsx.hash().encode_hex(),
+            kind: kind.to_string(),
+            sender: tsx.sndr_addr().and_then(id),
+            recipient: tsx.recp_addr().and_then(id),
+            amount: (tsx.total_cost() - tsx.fees()) as f64 / cents_per_coin,
+            fees: tsx.fees() as f64 / cents_per_coin,
+            outputs: match tsx.payload() {
+                TransactionPayload::MultiTransfer(outputs) => outputs
+                    .iter()
+                    .map(|out| OutputSummary {
+                        recipient: id(out.recp_addr()),
+                        amount: out.amnt().get() as f64 / cents_per_coin,
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockInfo {
+    pub index: u32,
+    pub hash: String,
+    pub prev_hash: String,
+    pub validator: Option<u32>, // None for the genesis block
+    pub timestamp: u128,
+    pub transactions: Vec<TransactionSummary>,
+}
+
+impl BlockInfo {
+    pub fn from_block(blk: &Block, peers: &PeersCatalog) -> Self {
+        Self {
+            index: blk.index(),
+            hash: blk.hash().encode_hex(),
+            prev_hash: blk.prev_hash().encode_hex(),
+            validator: blk
+                .val()
+                .and_then(|v| peers.get_by_publ_key(v))
+                .map(|p| p.id()),
+            timestamp: blk.timestamp(),
+            transactions: blk
+                .tsxs()
+                .iter()
+                .map(|tsx| TransactionSummary::from_transaction(tsx, peers))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionState {
+    Pending,
+    Included,
+    Dropped,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TransactionInfo {
+    pub hash: String,
+    pub status: TransactionState,
+    pub block: Option<u32>,
+    pub confirmations: Option<u32>,
+    pub reason: Option<String>, // why it was dropped
+    // None if the block of the transaction has been pruned (or it was dropped)
+    pub transaction: Option<TransactionSummary>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PeerInfo {
+    pub id: u32,
+    pub account: Address,
+    pub address: SocketAddr,
+    pub public_key: String, // PKCS#1 PEM
+}
+
+impl From<&Peer> for PeerInfo {
+    fn from(peer: &Peer) -> Self {
+        Self {
+            id: peer.id(),
+            account: peer.publ_key().address(),
+            address: peer.sock_addr(),
+            public_key: peer.publ_key().to_pem(),
+        }
+    }
+}
+
+// serving
+
+// serve the API on the given listener, handing every call to the handler
+pub fn spawn_rpc_server<H>(listener: TcpListener, handler: H)
+where
+    H: Fn(RpcCall) -> RpcResult + Clone + Send + 'static,
+{
+    thread::spawn(move || {
+        for conn in listener.incoming() {
+            let stream = match conn {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("RPC: Failed to establish connection: {}", e);
+                    continue;
+                }
+            };
+
+            let handler = handler.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_http(stream, handler) {
+                    log::debug!("RPC: Failed to serve request: {}", e);
+                }
+            });
+        }
+    });
+}
+
+fn handle_http(stream: TcpStream, handler: impl Fn(RpcCall) -> RpcResult) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream).take(MAX_HEADERS_LEN as u64);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut content_len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    if !request_line.starts_with("POST ") {
+        return write_http(&stream, "405 Method Not Allowed", "Allow: POST\r\n", "");
+    }
+
+    let Some(content_len) = content_len else {
+        return write_http(&stream, "411 Length Required", "", "");
+    };
+
+    if content_len > MAX_BODY_LEN {
+        return write_http(&stream, "413 Payload Too Large", "", "");
+    }
+
+    let mut body = vec![0; content_len];
+    let mut reader = reader.into_inner();
+    reader.read_exact(&mut body)?;
+
+    let response = handle_body(&body, handler).to_string();
+    write_http(
+        &stream,
+        "200 OK",
+        "Content-Type: application/json\r\n",
+        &response,
+    )
+}
+
+fn write_http(mut stream: &TcpStream, status: &str, headers: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes())
+}
+
+// handle a single request or a batch of them
+fn handle_body(body: &[u8], handler: impl Fn(RpcCall) -> RpcResult) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return response(Value::Null, Err(RpcError::new(ErrorCode::ParseError, e))),
+    };
+
+    match request {
+        Value::Array(requests) if !requests.is_empty() => requests
+            .into_iter()
+            .map(|request| handle_request(request, &handler))
+            .collect(),
+        request => handle_request(request, &handler),
+    }
+}
+
+fn handle_request(request: Value, handler: impl Fn(RpcCall) -> RpcResult) -> Value {
+    #[derive(Deserialize)]
+    struct Request {
+        jsonrpc: String,
+        #[serde(default)]
+        id: Value,
+        method: String,
+        #[serde(default)]
+        params: Value,
+    }
+
+    let Ok(request) = serde_json::from_value::<Request>(request) else {
+        let error = RpcError::new(ErrorCode::InvalidRequest, "Invalid request");
+        return response(Value::Null, Err(error));
+    };
+
+    if request.jsonrpc != JSONRPC_VERSION {
+        let error = RpcError::new(ErrorCode::InvalidRequest, "Unsupported JSON-RPC version");
+        return response(request.id, Err(error));
+    }
+
+    if !RpcCall::METHODS.contains(&request.method.as_str()) {
+        let error = RpcError::new(ErrorCode::MethodNotFound, "Method not found")
+            .with_data(json!({ "methods": RpcCall::METHODS }));
+        return response(request.id, Err(error));
+    }
+
+    // methods without parameters can omit them
+    let params = match request.params {
+        Value::Null => json!({}),
+        params => params,
+    };
+
+    let call = match serde_json::from_value(json!({ "method": request.method, "params": params })) {
+        Ok(call) => call,
+        Err(e) => return response(request.id, Err(RpcError::new(ErrorCode::InvalidParams, e))),
+    };
+
+    response(request.id, handler(call))
+}
+
+fn response(id: Value, result: RpcResult) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": JSONRPC_VERSION, "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": JSONRPC_VERSION, "id": id, "error": error }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // answers get_balance and get_peers, and fails every other call
+    fn handler(call: RpcCall) -> RpcResult {
+        match call {
+            RpcCall::GetBalance { account } => Ok(json!({ "account": account })),
+            RpcCall::GetPeers {} => Ok(json!([])),
+            _ => Err(RpcError::new(
+                ErrorCode::AccountNotFound,
+                "Account not found",
+            )),
+        }
+    }
+
+    fn handle(body: &str) -> Value {
+        handle_body(body.as_bytes(), handler)
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let res = handle("{");
+        assert_eq!(error_code(&res), ErrorCode::ParseError as i64);
+        assert_eq!(res["id"], Value::Null);
+
+        let res = handle(r#"{"id": 1}"#);
+        assert_eq!(error_code(&res), ErrorCode::InvalidRequest as i64);
+
+        // an empty batch is an invalid request, not an empty response
+        let res = handle("[]");
+        assert_eq!(error_code(&res), ErrorCode::InvalidRequest as i64);
+
+        let res = handle(r#"{"jsonrpc": "1.0", "id": 1, "method": "get_peers"}"#);
+        assert_eq!(error_code(&res), ErrorCode::InvalidRequest as i64);
+        assert_eq!(res["id"], 1);
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let res = handle(r#"{"jsonrpc": "2.0", "id": "a", "method": "get_everything"}"#);
+        assert_eq!(error_code(&res), ErrorCode::MethodNotFound as i64);
+        assert_eq!(res["id"], "a");
+        assert_eq!(res["error"]["data"]["methods"], json!(RpcCall::METHODS));
+    }
+
+    #[test]
+    fn test_params() {
+        // params can be omitted, and so can optional params
+        let res = handle(r#"{"jsonrpc": "2.0", "id": 1, "method": "get_peers"}"#);
+        assert_eq!(res["result"], json!([]));
+
+        let res = handle(r#"{"jsonrpc": "2.0", "id": 1, "method": "get_balance"}"#);
+        assert_eq!(res["result"], json!({ "account": null }));
+
+        let res = handle(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "get_balance", "params": {"account": 2}}"#,
+        );
+        assert_eq!(res["result"], json!({ "account": 2 }));
+
+        // missing, mistyped and zero amounts are invalid
+        for params in [
+            r#"{"amount": 1}"#,
+            r#"{"recipient": "1", "amount": 1}"#,
+            r#"{"recipient": 1, "amount": 0}"#,
+        ] {
+            let res = handle(&format!(
+                r#"{{"jsonrpc": "2.0", "id": 1, "method": "send_transfer", "params": {}}}"#,
+                params
+            ));
+            assert_eq!(error_code(&res), ErrorCode::InvalidParams as i64);
+        }
+
+        // valid params reach the handler, whose errors are passed on
+        let res = handle(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "send_transfer", "params": {"recipient": 1, "amount": 1}}"#,
+        );
+        assert_eq!(error_code(&res), ErrorCode::AccountNotFound as i64);
+    }
+
+    #[test]
+    fn test_batch() {
+        let res = handle(
+            r#"[
+                {"jsonrpc": "2.0", "id": 1, "method": "get_peers"},
+                {"jsonrpc": "2.0", "id": 2, "method": "get_everything"},
+                {"id": 3}
+            ]"#,
+        );
+
+        let responses = res.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"], json!([]));
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(error_code(&responses[1]), ErrorCode::MethodNotFound as i64);
+        assert_eq!(error_code(&responses[2]), ErrorCode::InvalidRequest as i64);
+    }
+}
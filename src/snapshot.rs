@@ -0,0 +1,199 @@
+use crate::{
+    account::{self, Account, AccountsCatalog},
+    blockchain::{block::Block, Blockchain},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+use thiserror::Error;
+
+/*
+    A Snapshot captures the state of every account (balances, stakes and nonce pools)
+    right after a block has been processed, along with that block.
+
+    A node can be bootstrapped from a snapshot plus the blocks that come after it,
+    instead of replaying the whole blockchain from the genesis block.
+    Since every block commits to the state root of the accounts after it,
+    a snapshot can be verified by recalculating the state root of its accounts
+    and comparing it to the one stored in its block.
+
+    Snapshots are saved as `snapshot-<index>.json`, and a restarting node resumes from the latest one
+    of its chain (told apart by the hash of its genesis block) instead of the genesis block.
+*/
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("The snapshot could not be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("The snapshot could not be (de)serialized: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("The calculated hash of the snapshot's block does not match the provided one")]
+    InvalidBlockHash,
+    #[error("The state root of the snapshot's accounts does not match the block's state root")]
+    MismatchedStateRoot,
+    #[error("The snapshot has {actual} accounts, while the network has {expected} peers")]
+    MismatchedAccounts { expected: usize, actual: usize },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    // the hash of the genesis block of the chain
+    genesis_hash: [u8; 32],
+    block: Block,
+    accounts: Vec<Account>,
+}
+
+impl Snapshot {
+    // the accounts must be the result of processing the given block
+    pub fn new(genesis_hash: [u8; 32], blk: &Block, accounts: &AccountsCatalog) -> Self {
+        Self {
+            genesis_hash,
+            block: blk.clone(),
+            accounts: accounts.to_vec(),
+        }
+    }
+
+    // the snapshot of a new chain
+    pub fn genesis(gen_blk: &Block, accounts: &AccountsCatalog) -> Self {
+        Self::new(*gen_blk.hash(), gen_blk, accounts)
+    }
+
+    // take a snapshot of the tip of the blockchain
+    pub fn take(
+        genesis_hash: [u8; 32],
+        blockchain: &Blockchain,
+        accounts: &AccountsCatalog,
+    ) -> Self {
+        Self::new(genesis_hash, blockchain.last_block(), accounts)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let file = fs::File::open(path)?;
+        let snapshot = serde_json::from_reader(BufReader::new(file))?;
+
+        Ok(snapshot)
+    }
+
+    // the latest verified snapshot of the given chain saved in the directory (if any)
+    // files that cannot be loaded or verified are skipped
+    pub fn load_latest(dir: impl AsRef<Path>, genesis_hash: &[u8; 32]) -> Option<Self> {
+        let entries = match fs::read_dir(dir.as_ref()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("Snapshot: Failed to read {}: {}", dir.as_ref().display(), e);
+                return None;
+            }
+        };
+
+        let mut files: Vec<_> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let index = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix("snapshot-")?
+                    .strip_suffix(".json")?
+                    .parse::<u32>()
+                    .ok()?;
+                Some((index, path))
+            })
+            .collect();
+        files.sort_unstable_by_key(|(index, _)| *index);
+
+        files.into_iter().rev().find_map(|(_, path)| {
+            let snapshot = Self::load(&path)
+                .and_then(|snapshot| snapshot.verify().map(|_| snapshot))
+                .inspect_err(|e| log::warn!("Snapshot: Skipped {}: {}", path.display(), e))
+                .ok()?;
+
+            (snapshot.genesis_hash == *genesis_hash).then_some(snapshot)
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+
+        Ok(())
+    }
+
+    // verify the snapshot against the state commitment of its block
+    pub fn verify(&self) -> Result<(), SnapshotError> {
+        if *self.block.hash() != self.block.calculate_hash() {
+            return Err(SnapshotError::InvalidBlockHash);
+        }
+
+        if account::calculate_state_root(&self.accounts) != *self.block.state_root() {
+            return Err(SnapshotError::MismatchedStateRoot);
+        }
+
+        Ok(())
+    }
+
+    // the name of the file the snapshot of the given block is saved as
+    pub fn file_name(index: u32) -> String {
+        format!("snapshot-{}.json", index)
+    }
+
+    // getters
+
+    pub fn genesis_hash(&self) -> &[u8; 32] {
+        &self.genesis_hash
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blockchain::transaction::Transaction, crypto::PrivateKey, peer::PeersCatalog};
+    use rsa::RsaPrivateKey;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn test_verify() {
+        let mut peers = PeersCatalog::new();
+        let priv_key = PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap());
+        peers
+            .insert((priv_key.to_publ_key(), ([127, 0, 0, 1], 0).into()))
+            .unwrap();
+
+        let gen_tsxs = vec![Transaction::new_genesis(
+            priv_key.to_publ_key(),
+            NonZeroU32::new(100).unwrap(),
+        )];
+        let mut accounts = AccountsCatalog::new(&peers);
+        accounts.process_transactions(&gen_tsxs, None).unwrap();
+        let gen_blk = Block::new_genesis(gen_tsxs, 0, accounts.calculate_state_root());
+
+        let snapshot = Snapshot::genesis(&gen_blk, &accounts);
+        assert!(snapshot.verify().is_ok());
+
+        // the accounts no longer match the state root of the block
+        let mut tampered = snapshot.clone();
+        tampered.accounts[0].add_held(1);
+        assert!(matches!(
+            tampered.verify(),
+            Err(SnapshotError::MismatchedStateRoot)
+        ));
+
+        // the state root no longer matches the hash of the block
+        let mut json = serde_json::to_value(&snapshot).unwrap();
+        json["block"]["state_root"] = serde_json::to_value([0u8; 32]).unwrap();
+        let tampered: Snapshot = serde_json::from_value(json).unwrap();
+        assert!(matches!(
+            tampered.verify(),
+            Err(SnapshotError::InvalidBlockHash)
+        ));
+    }
+}
@@ -0,0 +1,572 @@
+use crate::{
+    crypto::{PrivateKey, PublicKey},
+    peer::{Peer, PeersCatalog},
+};
+use chacha20poly1305::{
+    aead::{Aead as _, KeyInit as _},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rsa::sha2::{Digest as _, Sha256};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey as EphemeralKey};
+
+/*
+    This module provides authenticated and encrypted connections between peers.
+
+    Every connection starts with a handshake, similar to the Noise XX pattern:
+    both sides exchange their identity (RSA) keys along with fresh ephemeral X25519 keys,
+    and each side signs the handshake transcript with its identity key.
+    The accepting side only talks to peers in the PeersCatalog, and the connecting side
+    makes sure it talks to the peer it meant to, so nobody can join the conversation
+    or pretend to be someone else. The session keys are derived from the ephemeral keys,
+    so recorded traffic stays secret even if an identity key leaks later on.
+
+    After the handshake, data is sent in frames encrypted with ChaCha20-Poly1305:
+    a 4-byte big-endian length followed by the ciphertext. Each direction has its own key,
+    and the frame counter is used as the nonce, so frames cannot be altered, replayed
+    or reordered without the connection failing.
+
+    Clients talk to their daemon in plaintext, so a connection that does not start
+    with HANDSHAKE_TAG is a client connection. Bootstrap messages are not sent over these
+    connections either (the peers are not known yet), they are signed instead.
+*/
+
+// the first byte sent by a peer, which can never be the first byte of a JSON message
+const HANDSHAKE_TAG: u8 = 0x00;
+
+// the handshake must finish within this time, so a stalled peer cannot hold a connection
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the maximum length of a handshake message
+const MAX_HANDSHAKE_LEN: usize = 16 * 1024;
+
+// the maximum length of the plaintext of a frame
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+// the length of the authentication tag appended to every encrypted frame
+const TAG_LEN: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum TransportError {
+    #[error("The connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("A handshake message could not be (de)serialized: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("The remote node is not a peer of the network")]
+    UnknownPeer,
+    #[error("The remote node is not peer {0}")]
+    UnexpectedPeer(u32),
+    #[error("The handshake signature of the remote node is invalid")]
+    InvalidSignature,
+}
+
+// handshake messages
+
+// initiator -> responder
+#[derive(Deserialize, Serialize)]
+struct Hello {
+    publ_key: PublicKey,
+    eph_key: [u8; 32],
+}
+
+// responder -> initiator
+#[derive(Deserialize, Serialize)]
+struct HelloReply {
+    publ_key: PublicKey,
+    eph_key: [u8; 32],
+    sig: Vec<u8>,
+}
+
+// initiator -> responder
+#[derive(Deserialize, Serialize)]
+struct Finish {
+    sig: Vec<u8>,
+}
+
+// the hash of everything both sides have sent, which both of them sign
+fn transcript_hash(
+    init_publ_key: &PublicKey,
+    init_eph_key: &[u8; 32],
+    resp_publ_key: &PublicKey,
+    resp_eph_key: &[u8; 32],
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(b"block_chat transport v1");
+    hasher.update(init_publ_key.to_der());
+    hasher.update(init_eph_key);
+    hasher.update(resp_publ_key.to_der());
+    hasher.update(resp_eph_key);
+
+    hasher.finalize().into()
+}
+
+// the label keeps the signature of one side from being replayed as the other's
+// and separates the keys of the two directions
+fn labeled_hash(label: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(label);
+    for part in parts {
+        hasher.update(part);
+    }
+
+    hasher.finalize().into()
+}
+
+fn write_handshake_msg(stream: &mut TcpStream, msg: &impl Serialize) -> Result<(), TransportError> {
+    let bytes = serde_json::to_vec(msg)?;
+
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(&bytes)?;
+
+    Ok(())
+}
+
+fn read_handshake_msg<T: DeserializeOwned>(stream: &mut TcpStream) -> Result<T, TransportError> {
+    let bytes = read_frame(stream, MAX_HANDSHAKE_LEN)?
+        .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+// returns None if the stream ends before a new frame starts
+fn read_frame(stream: &mut TcpStream, max_len: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+
+    match stream.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => stream.read_exact(&mut len[1..])?,
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Frame exceeds the maximum length",
+        ));
+    }
+
+    let mut frame = vec![0; len];
+    stream.read_exact(&mut frame)?;
+
+    Ok(Some(frame))
+}
+
+// one direction of an encrypted connection
+struct CipherState {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl CipherState {
+    fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            counter: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> io::Result<Nonce> {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or(io::Error::other("The connection has run out of nonces"))?;
+
+        Ok(*Nonce::from_slice(&nonce))
+    }
+
+    fn encrypt(&mut self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        self.cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("Failed to encrypt frame"))
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        self.cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt frame"))
+    }
+}
+
+pub struct SecureStream {
+    stream: TcpStream,
+    peer_id: u32,
+    send: CipherState,
+    recv: CipherState,
+
+    // decrypted data that has not been read yet
+    recv_buf: Vec<u8>,
+    recv_pos: usize,
+}
+
+impl SecureStream {
+    // connect to the given peer, failing if it cannot prove it is that peer
+    pub fn connect(peer: &Peer, priv_key: &PrivateKey) -> Result<Self, TransportError> {
+        let mut stream = TcpStream::connect_timeout(&peer.sock_addr(), HANDSHAKE_TIMEOUT)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        let publ_key = priv_key.to_publ_key();
+        let eph_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+        let eph_key = EphemeralKey::from(&eph_secret).to_bytes();
+
+        stream.write_all(&[HANDSHAKE_TAG])?;
+        write_handshake_msg(
+            &mut stream,
+            &Hello {
+                publ_key: publ_key.clone(),
+                eph_key,
+            },
+        )?;
+
+        let reply: HelloReply = read_handshake_msg(&mut stream)?;
+        if reply.publ_key != *peer.publ_key() {
+            return Err(TransportError::UnexpectedPeer(peer.id()));
+        }
+
+        let th = transcript_hash(&publ_key, &eph_key, &reply.publ_key, &reply.eph_key);
+        if !reply
+            .publ_key
+            .verify(&labeled_hash(b"responder", &[&th]), &reply.sig)
+        {
+            return Err(TransportError::InvalidSignature);
+        }
+
+        let sig = priv_key.sign(&labeled_hash(b"initiator", &[&th]));
+        write_handshake_msg(&mut stream, &Finish { sig })?;
+
+        let shared = eph_secret.diffie_hellman(&EphemeralKey::from(reply.eph_key));
+        let init_key = labeled_hash(b"initiator key", &[shared.as_bytes(), &th]);
+        let resp_key = labeled_hash(b"responder key", &[shared.as_bytes(), &th]);
+
+        stream.set_read_timeout(None)?;
+
+        Ok(Self::new(stream, peer.id(), init_key, resp_key))
+    }
+
+    // accept a connection from a peer, after `is_handshake()` has returned true
+    // fails if the remote node is not one of the given peers
+    pub fn accept(
+        mut stream: TcpStream,
+        peers: &PeersCatalog,
+        priv_key: &PrivateKey,
+    ) -> Result<Self, TransportError> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        let mut tag = [0; 1];
+        stream.read_exact(&mut tag)?;
+
+        let hello: Hello = read_handshake_msg(&mut stream)?;
+        let peer = peers
+            .get_by_publ_key(&hello.publ_key)
+            .ok_or(TransportError::UnknownPeer)?;
+
+        let publ_key = priv_key.to_publ_key();
+        let eph_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+        let eph_key = EphemeralKey::from(&eph_secret).to_bytes();
+
+        let th = transcript_hash(&hello.publ_key, &hello.eph_key, &publ_key, &eph_key);
+        let sig = priv_key.sign(&labeled_hash(b"responder", &[&th]));
+        write_handshake_msg(
+            &mut stream,
+            &HelloReply {
+                publ_key,
+                eph_key,
+                sig,
+            },
+        )?;
+
+        let finish: Finish = read_handshake_msg(&mut stream)?;
+        if !hello
+            .publ_key
+            .verify(&labeled_hash(b"initiator", &[&th]), &finish.sig)
+        {
+            return Err(TransportError::InvalidSignature);
+        }
+
+        let shared = eph_secret.diffie_hellman(&EphemeralKey::from(hello.eph_key));
+        let init_key = labeled_hash(b"initiator key", &[shared.as_bytes(), &th]);
+        let resp_key = labeled_hash(b"responder key", &[shared.as_bytes(), &th]);
+
+        stream.set_read_timeout(None)?;
+
+        Ok(Self::new(stream, peer.id(), resp_key, init_key))
+    }
+
+    fn new(stream: TcpStream, peer_id: u32, send_key: [u8; 32], recv_key: [u8; 32]) -> Self {
+        Self {
+            stream,
+            peer_id,
+            send: CipherState::new(send_key),
+            recv: CipherState::new(recv_key),
+            recv_buf: vec![],
+            recv_pos: 0,
+        }
+    }
+
+    // whether the remote node of the connection is a peer starting a handshake
+    // (as opposed to a client), without consuming any data
+    pub fn is_handshake(stream: &TcpStream) -> io::Result<bool> {
+        let mut tag = [0; 1];
+        let n = stream.peek(&mut tag)?;
+
+        Ok(n == 1 && tag[0] == HANDSHAKE_TAG)
+    }
+
+    // the ID of the (authenticated) peer on the other side
+    pub fn peer_id(&self) -> u32 {
+        self.peer_id
+    }
+}
+
+impl Read for SecureStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // frames with an empty plaintext are skipped (returning 0 would mean the end of the stream)
+        while self.recv_pos == self.recv_buf.len() {
+            let Some(frame) = read_frame(&mut self.stream, MAX_FRAME_LEN + TAG_LEN)? else {
+                return Ok(0);
+            };
+
+            self.recv_buf = self.recv.decrypt(&frame)?;
+            self.recv_pos = 0;
+        }
+
+        let n = buf.len().min(self.recv_buf.len() - self.recv_pos);
+        buf[..n].copy_from_slice(&self.recv_buf[self.recv_pos..self.recv_pos + n]);
+        self.recv_pos += n;
+
+        Ok(n)
+    }
+}
+
+impl Write for SecureStream {
+    // every write is sent as (at most) one frame
+    // (nothing is sent for an empty write, so that the reader never gets an empty frame)
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let n = buf.len().min(MAX_FRAME_LEN);
+        let ciphertext = self.send.encrypt(&buf[..n])?;
+
+        let mut frame = Vec::with_capacity(4 + ciphertext.len());
+        frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&ciphertext);
+        self.stream.write_all(&frame)?;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// a connection to the daemon, either from a client or from a peer
+pub enum Connection {
+    Plain(TcpStream),
+    Secure(SecureStream),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Secure(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Secure(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Secure(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPrivateKey;
+    use std::{net::TcpListener, thread};
+
+    fn new_priv_key() -> PrivateKey {
+        PrivateKey::from(RsaPrivateKey::new(&mut rand::thread_rng(), 512).unwrap())
+    }
+
+    // a catalog of the given keys, all at the address of the listener
+    fn catalog(keys: &[&PrivateKey], listener: &TcpListener) -> PeersCatalog {
+        let mut peers = PeersCatalog::new();
+        for key in keys {
+            peers
+                .insert((key.to_publ_key(), listener.local_addr().unwrap()))
+                .unwrap();
+        }
+        peers
+    }
+
+    // connect to peer `id` of the connecting catalog, which the listener accepts
+    // with the responder's key and catalog
+    fn handshake(
+        init_key: &PrivateKey,
+        init_peers: &PeersCatalog,
+        id: u32,
+        resp_key: &PrivateKey,
+        resp_peers: &PeersCatalog,
+        listener: &TcpListener,
+    ) -> (
+        Result<SecureStream, TransportError>,
+        Result<SecureStream, TransportError>,
+    ) {
+        thread::scope(|s| {
+            let resp = s.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                assert!(SecureStream::is_handshake(&stream).unwrap());
+                SecureStream::accept(stream, resp_peers, resp_key)
+            });
+
+            let init = SecureStream::connect(init_peers.get_by_id(id).unwrap(), init_key);
+            (init, resp.join().unwrap())
+        })
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (a, b) = (new_priv_key(), new_priv_key());
+        let peers = catalog(&[&a, &b], &listener);
+        let b_id = peers.get_by_publ_key(&b.to_publ_key()).unwrap().id();
+
+        let (init, resp) = handshake(&a, &peers, b_id, &b, &peers, &listener);
+        let (mut init, mut resp) = (init.unwrap(), resp.unwrap());
+        assert_eq!(init.peer_id(), b_id);
+        assert_eq!(resp.peer_id(), 1 - b_id);
+
+        // larger than a frame, so it is split
+        let data = vec![7; MAX_FRAME_LEN + 10];
+        init.write_all(&data).unwrap();
+        let mut buf = vec![0; data.len()];
+        resp.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        resp.write_all(b"pong").unwrap();
+        let mut buf = [0; 4];
+        init.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+
+        // the stream ends cleanly once the other side is gone
+        drop(init);
+        assert_eq!(resp.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_empty_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (a, b) = (new_priv_key(), new_priv_key());
+        let peers = catalog(&[&a, &b], &listener);
+        let b_id = peers.get_by_publ_key(&b.to_publ_key()).unwrap().id();
+
+        let (init, resp) = handshake(&a, &peers, b_id, &b, &peers, &listener);
+        let (mut init, mut resp) = (init.unwrap(), resp.unwrap());
+
+        // an empty write sends nothing
+        assert_eq!(init.write(&[]).unwrap(), 0);
+
+        // a frame with an empty plaintext (sent the way `write()` sends a frame) is skipped
+        let ciphertext = init.send.encrypt(&[]).unwrap();
+        let mut frame = (ciphertext.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&ciphertext);
+        init.stream.write_all(&frame).unwrap();
+        init.write_all(b"ping").unwrap();
+
+        let mut buf = [0; 4];
+        assert_eq!(resp.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn test_wrong_peer_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (a, b, c) = (new_priv_key(), new_priv_key(), new_priv_key());
+
+        // the initiator expects c at the address where b answers
+        let init_peers = catalog(&[&a, &c], &listener);
+        let resp_peers = catalog(&[&a, &b], &listener);
+        let c_id = init_peers.get_by_publ_key(&c.to_publ_key()).unwrap().id();
+
+        let (init, resp) = handshake(&a, &init_peers, c_id, &b, &resp_peers, &listener);
+        assert!(matches!(init, Err(TransportError::UnexpectedPeer(id)) if id == c_id));
+        assert!(resp.is_err());
+
+        // the responder does not know the initiator
+        let init_peers = catalog(&[&a, &b], &listener);
+        let resp_peers = catalog(&[&b, &c], &listener);
+        let b_id = init_peers.get_by_publ_key(&b.to_publ_key()).unwrap().id();
+
+        let (init, resp) = handshake(&a, &init_peers, b_id, &b, &resp_peers, &listener);
+        assert!(matches!(resp, Err(TransportError::UnknownPeer)));
+        assert!(init.is_err());
+    }
+
+    #[test]
+    fn test_tampered_and_replayed_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (a, b) = (new_priv_key(), new_priv_key());
+        let peers = catalog(&[&a, &b], &listener);
+        let b_id = peers.get_by_publ_key(&b.to_publ_key()).unwrap().id();
+
+        // send a frame the way `write()` does, given its ciphertext
+        fn send_frame(stream: &mut SecureStream, ciphertext: &[u8]) {
+            let mut frame = (ciphertext.len() as u32).to_be_bytes().to_vec();
+            frame.extend_from_slice(ciphertext);
+            stream.stream.write_all(&frame).unwrap();
+        }
+
+        // a frame replayed after it has been received
+        let (init, resp) = handshake(&a, &peers, b_id, &b, &peers, &listener);
+        let (mut init, mut resp) = (init.unwrap(), resp.unwrap());
+        let ciphertext = init.send.encrypt(b"hello").unwrap();
+        send_frame(&mut init, &ciphertext);
+        send_frame(&mut init, &ciphertext);
+
+        let mut buf = [0; 5];
+        resp.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        let err = resp.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a frame altered on the way
+        let (init, resp) = handshake(&a, &peers, b_id, &b, &peers, &listener);
+        let (mut init, mut resp) = (init.unwrap(), resp.unwrap());
+        let mut ciphertext = init.send.encrypt(b"hello").unwrap();
+        ciphertext[0] ^= 1;
+        send_frame(&mut init, &ciphertext);
+
+        let err = resp.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}